@@ -288,8 +288,8 @@ export class AsmCompiler {
 
 			// TODO: insert jump point at beginning of program
 			codes.unshift(
-				..._DslOpcodes.LoadImmmediateToBus(codes.length + 4),
-				..._DslOpcodes.JumpWithBusValueRelative()
+				..._DslOpcodes.LoadImmediateToBus(codes.length + 4),
+				..._DslOpcodes.JumpToBus()
 			);
 
 			let expanded: InstructionBoundWithData[] = [];
@@ -142,7 +142,7 @@ const _instructions = (Load: get, Save: get, Label: label): dslaInstructions =>
 		return [
 			Load(_source),
 			op.AluPushFromBus(),
-			op.LoadImmmediateToBus(int(_imm)),
+			op.LoadImmediateToBus(int(_imm)),
 			op.AluDoAdd(),
 			op.AluHiToBus(),
 			Save(_dest),
@@ -151,7 +151,7 @@ const _instructions = (Load: get, Save: get, Label: label): dslaInstructions =>
 
 	loadi(_dest, _imm) {
 		return [
-			op.LoadImmmediateToBus(int(_imm)),
+			op.LoadImmediateToBus(int(_imm)),
 			Save(_dest),
 		];
 	},
@@ -168,8 +168,8 @@ const _instructions = (Load: get, Save: get, Label: label): dslaInstructions =>
 
 	goto(_label) {
 		return [
-			op.LoadImmmediateToBus(Label(_label)),
-			op.JumpWithBusValueRelative(),
+			op.LoadImmediateToBus(Label(_label)),
+			op.JumpToBus(),
 		];
 	},
 
@@ -186,8 +186,8 @@ const _instructions = (Load: get, Save: get, Label: label): dslaInstructions =>
 
 	gotol(_label) {
 		return [
-			op.LoadImmmediateToBus(Label(_label)),
-			op.JumpWithBusValueRelative(),
+			op.LoadImmediateToBus(Label(_label)),
+			op.JumpToBus(),
 			op.GetCurrentPosition(),
 		];
 	},
@@ -201,7 +201,7 @@ const _instructions = (Load: get, Save: get, Label: label): dslaInstructions =>
 	ret(_sourceVar) {
 		return [
 			Load(_sourceVar),
-			op.JumpWithBusValueRelative(),
+			op.JumpToBus(),
 		];
 	},
 
@@ -214,7 +214,7 @@ const _instructions = (Load: get, Save: get, Label: label): dslaInstructions =>
 
 	halt() {
 		return [
-			op.Halt(),
+			op.DslHalt(),
 		];
 	},
 });
@@ -7,7 +7,8 @@ import { SyscallsEnum } from '../SyscallsEnum';
 type i = number;
 
 /**
- * The machine code opcodes
+ * The machine code opcodes. The keys are the mnemonics of the instruction set
+ * in rust/src/isa.rs, which checks this table against it, except for the syscall helpers
  */
 export const _DslOpcodes = {
 	Noop: () => [0],
@@ -30,13 +31,13 @@ export const _DslOpcodes = {
 
 	AluDoAdd: () => [9],
 
-	// 10
+	AluNegate: () => [10],
 
 	AluMultiply: () => [11],
 
 	AluDivide: () => [12],
 
-	JumpWithBusValueRelative: () => [13],
+	JumpToBus: () => [13],
 
 	BranchTo: (offset: i) => [14, offset],
 
@@ -52,13 +53,13 @@ export const _DslOpcodes = {
 
 	NewBlock: () => [20],
 
-	// Syscall: (i: i) => [21, i],
+	Syscall: (code: i) => [21, code],
 
-	// 22
+	Halt: () => [22],
 
 	Pause: () => [23],
 
-	LoadImmmediateToBus: (i: i) => [24, i],
+	LoadImmediateToBus: (i: i) => [24, i],
 
 	AluPushFromBus: () => [25],
 
@@ -78,6 +79,40 @@ export const _DslOpcodes = {
 
 	ShiftRight: () => [33],
 
+	Push: () => [34],
+
+	Pop: () => [35],
+
+	Call: (address: i) => [36, address],
+
+	Return: () => [37],
+
+	GetStackPointer: () => [38],
+
+	EnableInterrupts: () => [39],
+
+	DisableInterrupts: () => [40],
+
+	ReturnFromInterrupt: () => [41],
+
+	SetPermissions: (address: i, bits: i) => [42, address, bits],
+
+	Allocate: () => [43],
+
+	Free: () => [44],
+
+	SetPageTable: (length: i) => [45, length],
+
+	EnablePaging: () => [46],
+
+	DisablePaging: () => [47],
+
+	ReturnFromTrap: () => [48],
+
+	Trap: (detail: i) => [49, detail],
+
+	GetCoreId: () => [50],
+
 	// Syscalls
 
 	Alert: () => [
@@ -87,7 +122,7 @@ export const _DslOpcodes = {
 
 	// ---------------
 
-	Halt: () => [100],
+	DslHalt: () => [100],
 };
 
 // export const DslOpcodeComments: {[p in keyof typeof _DslOpcodes]: string} = {
@@ -102,12 +137,12 @@ export const _DslOpcodes = {
 // 	AluDoComparisonWithMode: '',
 // 	AluHiToBus: '',
 // 	AluLoToBus: '',
-// 	LoadImmmediateToBus: '',
+// 	LoadImmediateToBus: '',
 // 	AluPushFromBus: '',
 // 	BranchTo: '',
-// 	JumpWithBusValueRelative: '',
+// 	JumpToBus: '',
 // 	GetCurrentPosition: '',
-// 	Halt: '',
+// 	DslHalt: '',
 // 	AluDivide: '',
 // 	AluMultiply: '',
 // 	AluNegate: '',
@@ -126,7 +161,9 @@ export const DslCodeToComment: SMap<string> = {};
 _.forOwn(_DslOpcodes, (op, key) => {
 	const arr = op(0, 0);
 	const code = arr[0];
-	DslCodeToComment[code] = key;
+	// the syscall helpers share Syscall's code
+	if (DslCodeToComment[code] === undefined)
+		DslCodeToComment[code] = key;
 });
 
 const _transformedDslOpcodes = _.mapValues(_DslOpcodes, transformer);
@@ -2,14 +2,14 @@ import wasm from 'rust-asm';
 import { dsl2machine } from '../language/compilers';
 import { GetSyscallWithNumber, SyscallResult } from './syscalls';
 
-let MEM_SIZE: number = -1;
+let PAGE_SIZE: number = -1;
 
 /**
- * Returns a block of memory
- * @param n the block number
+ * Returns a view of a page of memory, empty if the page is not mapped
+ * @param n the page number
  */
-export function GetBlock(n: number): CombinedArray {
-	return wasm.;
+export function GetBlock(n: number): Uint32Array {
+	return wasm.r_GetPage(n);
 }
 
 // ------------------------------------------------------------------------------------
@@ -36,9 +36,9 @@ export function syscall(code: number, param: number): SyscallResult {
  */
 export function Initialize(text: string) {
 	let exports = wasm;
-	exports.r_Initialize();
-	UpdateMemoryBlockSize();
-	GetBlock(0).set(dsl2machine(text).slice(0, MEM_SIZE), 1);
+	// the program starts at address 1
+	exports.r_Initialize(new Uint32Array([0, ...dsl2machine(text)]));
+	UpdatePageSize();
 }
 
 /**
@@ -52,11 +52,43 @@ export function Continue() {
 
 /**
  * If the rust processor is paused, a single operation will be performed.
+ * A call is run until it returns, unless something inside it stops the processor first.
  */
 export function StepOver() {
 	wasm.r_StepOver();
 }
 
+/**
+ * If the rust processor is paused, a single operation will be performed, following calls.
+ */
+export function StepInto() {
+	wasm.r_StepInto();
+}
+
+/**
+ * Runs until the current call returns to its caller.
+ * Returns the number of operations performed.
+ */
+export function StepOut(): number {
+	return wasm.r_StepOut();
+}
+
+/**
+ * Performs n operations of the running core, stopping early at breakpoints.
+ * Returns the number of operations performed, including other cores.
+ */
+export function StepN(n: number): number {
+	return wasm.r_StepN(n);
+}
+
+/**
+ * Runs until the instruction pointer reaches the address, like a breakpoint that only lasts for this run.
+ * Returns the number of operations performed.
+ */
+export function RunTo(address: number): number {
+	return wasm.r_RunTo(address);
+}
+
 /**
  * Returns the current instruction pointer of the rust processor.
  */
@@ -87,10 +119,10 @@ export function GetIsBreakpoint(b: number) {
 }
 
 /**
- * Gets the memory block size from rust
+ * Gets the memory page size from rust
  */
-export function UpdateMemoryBlockSize() {
-	MEM_SIZE = wasm.r_GetMemoryBlockSize();
+export function UpdatePageSize() {
+	PAGE_SIZE = wasm.r_GetPageSize();
 }
 
 /**
@@ -106,6 +138,6 @@ export function GetWasmMemoryLocation(location: number): number {
 // 	setMemoryLocation,
 // 	GetBlock,
 // 	GetMemoryBuffer,
-// 	MemSize: () => MEM_SIZE,
+// 	PageSize: () => PAGE_SIZE,
 // 	CombinedArray,
 // });
\ No newline at end of file
@@ -66,7 +66,7 @@ const messageTypes: discriminantToHandler<MainToWorker, 'type', handler> = {
 		);
 	},
 	'get-block'(data, respond) {
-		const block = new Uint32Array(GetBlock(data.blockNum).getCombined()).buffer;
+		const block = new Uint32Array(GetBlock(data.blockNum)).buffer;
 		respond(
 			'block',
 			{
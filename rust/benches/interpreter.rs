@@ -0,0 +1,70 @@
+#[macro_use]
+extern crate criterion;
+extern crate rust_asm;
+
+use criterion::{BatchSize, Criterion};
+use rust_asm::Machine;
+
+const ITERATIONS: u32 = 10_000;
+
+// counts memory[200] up to memory[203], writing it to memory[201] every time
+fn counting_loop() -> Vec<u32> {
+	let mut image = vec![0; 204];
+	image[1..21].copy_from_slice(&[
+		1, 200, // 1: counter -> bus
+		25, // 3: push onto the ALU
+		24, 1, // 4: 1 -> bus
+		9, // 6: add
+		16, // 7: hi -> bus
+		2, 200, // 8: bus -> counter
+		2, 201, // 10: bus -> memory[201]
+		25, // 12: push the counter
+		1, 203, // 13: limit -> bus
+		25, // 15: push the limit
+		29, 4, // 16: counter < limit
+		14, 1, // 18: goto 1 if it is
+		100, // 20: halt
+	]);
+	image[203] = ITERATIONS;
+	return image;
+}
+
+fn setup(image: &[u32], cache: bool, history: bool, jit: bool) -> Machine {
+	let mut machine = Machine::new();
+	machine.set_decode_cache(cache);
+	machine.set_jit(jit);
+	if history {
+		machine.set_history_limit(1024);
+	}
+	machine.initialize(image);
+	return machine;
+}
+
+fn interpreter(c: &mut Criterion) {
+	let image = counting_loop();
+	let mut group = c.benchmark_group("counting loop");
+	// history is off by default, with it on its bookkeeping costs more than the instructions
+	let variants = [
+		("interpreter", true, false, false),
+		("interpreter, no decode cache", false, false, false),
+		("jit", true, false, true),
+		("interpreter, history", true, true, false),
+		("interpreter, history, no decode cache", false, true, false),
+		("jit, history", true, true, true),
+	];
+	for &(name, cache, history, jit) in variants.iter() {
+		group.bench_function(name, |b| b.iter_batched(
+			|| setup(&image, cache, history, jit),
+			|mut machine| {
+				machine.run();
+				assert_eq!(machine.read_memory(200), ITERATIONS);
+				return machine;
+			},
+			BatchSize::LargeInput,
+		));
+	}
+	group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,185 @@
+// headless runner for machine code images.
+//
+// usage: runner [--text] [--input <file>] [--output <file>] [--trace <file>] [--serial <address>] <image>
+//
+// the image is the same list of words that r_Initialize accepts,
+// stored as little endian u32s, or as whitespace separated numbers with --text
+// ('#' starts a comment that runs to the end of the line).
+//
+// syscalls are handled by NativeSyscallHost, which reads --input for file input buffers
+// and writes file output buffers to --output. terminal input buffers read stdin, and the
+// runner stops when the program waits for more once stdin has reached EOF
+//
+// --trace records every executed instruction and writes the trace when the program stops,
+// as newline delimited JSON if the file ends in .ndjson or .jsonl, in the binary format otherwise
+//
+// --serial maps a serial port at the address (data) and the one after it (status),
+// bytes the program sends to it are written to stdout
+//
+// exit codes: 0 on halt, 1 on bad arguments or io errors, 2 on fault,
+// 3 when stdin closed while the program waited for input
+
+extern crate rust_asm;
+
+use rust_asm::{Machine, NativeSyscallHost, SerialPort};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+const EXIT_HALT: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_FAULT: i32 = 2;
+const EXIT_INPUT_CLOSED: i32 = 3;
+
+struct Options {
+	image: String,
+	text: bool,
+	input: Option<String>,
+	output: Option<String>,
+	trace: Option<String>,
+	serial: Option<u32>,
+}
+
+fn parse_args() -> Result<Options, String> {
+	let mut image = None;
+	let mut text = false;
+	let mut input = None;
+	let mut output = None;
+	let mut trace = None;
+	let mut serial = None;
+
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--text" => text = true,
+			"--input" => input = Some(args.next().ok_or("--input needs a file")?),
+			"--output" => output = Some(args.next().ok_or("--output needs a file")?),
+			"--trace" => trace = Some(args.next().ok_or("--trace needs a file")?),
+			"--serial" => {
+				let address = args.next().ok_or("--serial needs an address")?;
+				serial = Some(address.parse().map_err(|_| format!("`{}` is not an address", address))?);
+			},
+			_ if image.is_none() && !arg.starts_with("--") => image = Some(arg),
+			_ => return Err(format!("unexpected argument `{}`", arg)),
+		}
+	}
+
+	return match image {
+		Some(image) => Ok(Options { image, text, input, output, trace, serial }),
+		None => Err("usage: runner [--text] [--input <file>] [--output <file>] [--trace <file>] [--serial <address>] <image>".to_string()),
+	};
+}
+
+fn load_image(options: &Options) -> Result<Vec<u32>, String> {
+	let bytes = fs::read(&options.image).map_err(|e| format!("could not read `{}`: {}", options.image, e))?;
+
+	if !options.text {
+		if bytes.len() % 4 != 0 {
+			return Err(format!("`{}` is not a whole number of 32 bit words", options.image));
+		}
+		return Ok(bytes.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect());
+	}
+
+	let mut words = Vec::new();
+	for line in String::from_utf8_lossy(&bytes).lines() {
+		let code = line.split('#').next().unwrap_or("");
+		for word in code.split_whitespace() {
+			let value: i64 = word.parse().map_err(|_| format!("`{}` is not a number", word))?;
+			words.push(value as u32);
+		}
+	}
+	return Ok(words);
+}
+
+fn main() {
+	let options = match parse_args() {
+		Ok(options) => options,
+		Err(message) => {
+			eprintln!("{}", message);
+			process::exit(EXIT_ERROR);
+		},
+	};
+
+	let image = match load_image(&options) {
+		Ok(image) => image,
+		Err(message) => {
+			eprintln!("{}", message);
+			process::exit(EXIT_ERROR);
+		},
+	};
+
+	let input_file = match options.input {
+		Some(ref path) => match fs::read(path) {
+			Ok(contents) => contents,
+			Err(e) => {
+				eprintln!("could not read `{}`: {}", path, e);
+				process::exit(EXIT_ERROR);
+			},
+		},
+		None => Vec::new(),
+	};
+
+	let mut host = NativeSyscallHost::new().with_input_file(input_file);
+	if let Some(path) = options.output {
+		host = host.with_output_file(PathBuf::from(path));
+	}
+
+	let mut machine = Machine::with_syscall_host(Box::new(host));
+	machine.initialize(&image);
+	let serial = SerialPort::new();
+	if let Some(address) = options.serial {
+		if let Err(e) = machine.map_device(address, address.saturating_add(1), Box::new(serial.clone())) {
+			eprintln!("could not map the serial port: {}", e);
+			process::exit(EXIT_ERROR);
+		}
+	}
+	if options.trace.is_some() {
+		machine.set_trace_limit(usize::MAX);
+		machine.start_trace();
+	}
+
+	let mut input_closed = false;
+	loop {
+		machine.run();
+		machine.refresh_syscall_host();
+		let sent = serial.take_output();
+		if !sent.is_empty() {
+			let mut stdout = io::stdout();
+			let _ = stdout.write_all(&sent);
+			let _ = stdout.flush();
+		}
+		if !machine.is_paused() {
+			break;
+		}
+		if machine.syscall_input_closed() {
+			input_closed = true;
+			break;
+		}
+	}
+
+	if let Some(ref path) = options.trace {
+		let contents = if path.ends_with(".ndjson") || path.ends_with(".jsonl") {
+			machine.export_trace_json().into_bytes()
+		}
+		else {
+			machine.export_trace_binary()
+		};
+		if let Err(e) = fs::write(path, contents) {
+			eprintln!("could not write `{}`: {}", path, e);
+			process::exit(EXIT_ERROR);
+		}
+	}
+
+	if let Some(fault) = machine.fault() {
+		eprintln!("fault at {}: {}", machine.last_fault_address(), fault);
+		process::exit(EXIT_FAULT);
+	}
+	if input_closed {
+		eprintln!("stdin closed while the program waited for input");
+		process::exit(EXIT_INPUT_CLOSED);
+	}
+
+	process::exit(EXIT_HALT);
+}
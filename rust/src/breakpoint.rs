@@ -0,0 +1,385 @@
+use std::fmt;
+
+use crate::{storage, Processor};
+
+// a breakpoint with everything optional attached to it.
+// a plain breakpoint has no condition, no ignore count and no log message
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoint {
+	pub condition: Option<Condition>,
+	// the first ignore_count hits (with the condition true) do not stop
+	pub ignore_count: u32,
+	pub hit_count: u32,
+	// logpoints add their message to the log instead of pausing
+	pub log_message: Option<Template>,
+}
+
+impl Breakpoint {
+	// counts a hit if the condition holds.
+	// returns true if the processor should pause
+	pub(crate) fn hit(&mut self, p: &Processor, log: &mut Vec<String>) -> bool {
+		if !self.condition_holds(p) {
+			return false;
+		}
+		self.hit_count += 1;
+		if self.hit_count <= self.ignore_count {
+			return false;
+		}
+		if let Some(ref message) = self.log_message {
+			log.push(message.format(p));
+			return false;
+		}
+		return true;
+	}
+
+	pub(crate) fn condition_holds(&self, p: &Processor) -> bool {
+		return match self.condition {
+			Some(ref condition) => condition.eval(p),
+			None => true,
+		};
+	}
+
+	pub fn is_logpoint(&self) -> bool {
+		return self.log_message.is_some();
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionError {
+	// byte offset into the source
+	pub position: usize,
+	pub message: &'static str,
+}
+
+impl fmt::Display for ConditionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return write!(f, "{} at {}", self.message, self.position);
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Register {
+	Bus,
+	Next,
+	StackPointer,
+	Hi,
+	Lo,
+	Compare,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+	Equal,
+	NotEqual,
+	Greater,
+	GreaterOrEqual,
+	Lesser,
+	LesserOrEqual,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+	Number(storage),
+	Register(Register),
+	Memory(Box<Expr>),
+	Compare(Box<Expr>, CompareOp, Box<Expr>),
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+	// comparisons are done on the values as signed integers, like the ALU in int mode
+	fn eval(&self, p: &Processor) -> storage {
+		return match *self {
+			Expr::Number(n) => n,
+			Expr::Register(register) => match register {
+				Register::Bus => p.bus,
+				Register::Next => p.next,
+				Register::StackPointer => p.stack_pointer,
+				Register::Hi => p.alu.hi,
+				Register::Lo => p.alu.lo,
+				Register::Compare => p.alu.compare_result as storage,
+			},
+			// a virtual address with paging on, like the program sees it. unmapped reads 0
+			Expr::Memory(ref address) => p.translate_quietly(address.eval(p)).map_or(0, |address| p._peek_memory_loc(address)),
+			Expr::Compare(ref a, op, ref b) => {
+				let a = a.eval(p) as i32;
+				let b = b.eval(p) as i32;
+				let result = match op {
+					CompareOp::Equal => a == b,
+					CompareOp::NotEqual => a != b,
+					CompareOp::Greater => a > b,
+					CompareOp::GreaterOrEqual => a >= b,
+					CompareOp::Lesser => a < b,
+					CompareOp::LesserOrEqual => a <= b,
+				};
+				result as storage
+			},
+			Expr::And(ref a, ref b) => (a.eval(p) != 0 && b.eval(p) != 0) as storage,
+			Expr::Or(ref a, ref b) => (a.eval(p) != 0 || b.eval(p) != 0) as storage,
+		};
+	}
+}
+
+// a parsed breakpoint condition, for example `bus == 5 && mem[0x800] > 10`.
+//	values: decimal or 0x numbers, bus, ip, sp, hi, lo, compare, mem[value]
+//	comparisons: == != > >= < <=
+//	combined with && and ||, && binds tighter. a bare value is true when it is not 0
+//	at most MAX_DEPTH operators and brackets can be nested
+#[derive(Debug, Clone)]
+pub struct Condition {
+	source: String,
+	expr: Expr,
+}
+
+impl Condition {
+	pub fn parse(source: &str) -> Result<Condition, ConditionError> {
+		let mut parser = Parser { source: source.as_bytes(), at: 0, depth: 0 };
+		let expr = parser.or()?;
+		parser.skip_spaces();
+		if parser.at != parser.source.len() {
+			return Err(parser.error("unexpected input"));
+		}
+		return Ok(Condition {
+			source: source.to_string(),
+			expr,
+		});
+	}
+
+	pub fn source(&self) -> &str {
+		return &self.source;
+	}
+
+	pub(crate) fn eval(&self, p: &Processor) -> bool {
+		return self.expr.eval(p) != 0;
+	}
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+	Text(String),
+	Value(Expr),
+}
+
+// a logpoint message. anything between braces is evaluated like a condition
+// and printed in decimal, e.g. `bus is {bus}, counter is {mem[0x800]}`.
+// {{ and }} print a literal brace
+#[derive(Debug, Clone)]
+pub struct Template {
+	source: String,
+	pieces: Vec<Piece>,
+}
+
+impl Template {
+	pub fn parse(source: &str) -> Result<Template, ConditionError> {
+		let bytes = source.as_bytes();
+		let mut pieces = Vec::new();
+		let mut text = String::new();
+		let mut i = 0;
+		while i < bytes.len() {
+			match bytes[i] {
+				b'{' if bytes.get(i + 1) == Some(&b'{') => {
+					text.push('{');
+					i += 2;
+				},
+				b'}' if bytes.get(i + 1) == Some(&b'}') => {
+					text.push('}');
+					i += 2;
+				},
+				b'{' => {
+					let end = match source[i..].find('}') {
+						Some(end) => i + end,
+						None => return Err(ConditionError { position: i, message: "unclosed {" }),
+					};
+					let expr = Condition::parse(&source[i + 1..end]).map_err(|e| ConditionError {
+						position: e.position + i + 1,
+						message: e.message,
+					})?.expr;
+					if !text.is_empty() {
+						pieces.push(Piece::Text(std::mem::take(&mut text)));
+					}
+					pieces.push(Piece::Value(expr));
+					i = end + 1;
+				},
+				b'}' => return Err(ConditionError { position: i, message: "unmatched }" }),
+				_ => {
+					// copy a whole character so multi-byte text survives
+					let len = source[i..].chars().next().map_or(1, |c| c.len_utf8());
+					text.push_str(&source[i..i + len]);
+					i += len;
+				},
+			}
+		}
+		if !text.is_empty() {
+			pieces.push(Piece::Text(text));
+		}
+		return Ok(Template {
+			source: source.to_string(),
+			pieces,
+		});
+	}
+
+	pub fn source(&self) -> &str {
+		return &self.source;
+	}
+
+	pub(crate) fn format(&self, p: &Processor) -> String {
+		let mut out = String::new();
+		for piece in self.pieces.iter() {
+			match piece {
+				Piece::Text(text) => out.push_str(text),
+				Piece::Value(expr) => out.push_str(&(expr.eval(p) as i32).to_string()),
+			}
+		}
+		return out;
+	}
+}
+
+// the deepest a condition can nest. evaluating it recurses that deep
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+	source: &'a [u8],
+	at: usize,
+	// how deep the expression being parsed is nested
+	depth: usize,
+}
+
+impl<'a> Parser<'a> {
+	fn error(&self, message: &'static str) -> ConditionError {
+		return ConditionError { position: self.at, message };
+	}
+
+	fn skip_spaces(&mut self) {
+		while self.at < self.source.len() && self.source[self.at].is_ascii_whitespace() {
+			self.at += 1;
+		}
+	}
+
+	// consumes the token if it is next
+	fn eat(&mut self, token: &str) -> bool {
+		self.skip_spaces();
+		if self.source[self.at..].starts_with(token.as_bytes()) {
+			self.at += token.len();
+			return true;
+		}
+		return false;
+	}
+
+	// one level deeper, undone with leave
+	fn enter(&mut self) -> Result<(), ConditionError> {
+		if self.depth >= MAX_DEPTH {
+			return Err(self.error("nested too deep"));
+		}
+		self.depth += 1;
+		return Ok(());
+	}
+
+	fn leave(&mut self, levels: usize) {
+		self.depth -= levels;
+	}
+
+	// a chain of operators nests one level for each of them
+	fn or(&mut self) -> Result<Expr, ConditionError> {
+		let mut expr = self.and()?;
+		let mut levels = 0;
+		while self.eat("||") {
+			self.enter()?;
+			levels += 1;
+			expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+		}
+		self.leave(levels);
+		return Ok(expr);
+	}
+
+	fn and(&mut self) -> Result<Expr, ConditionError> {
+		let mut expr = self.compare()?;
+		let mut levels = 0;
+		while self.eat("&&") {
+			self.enter()?;
+			levels += 1;
+			expr = Expr::And(Box::new(expr), Box::new(self.compare()?));
+		}
+		self.leave(levels);
+		return Ok(expr);
+	}
+
+	fn compare(&mut self) -> Result<Expr, ConditionError> {
+		let a = self.value()?;
+		// two character operators first so >= is not read as >
+		let ops = [
+			("==", CompareOp::Equal),
+			("!=", CompareOp::NotEqual),
+			(">=", CompareOp::GreaterOrEqual),
+			("<=", CompareOp::LesserOrEqual),
+			(">", CompareOp::Greater),
+			("<", CompareOp::Lesser),
+		];
+		for (token, op) in ops.iter() {
+			if self.eat(token) {
+				let b = self.value()?;
+				return Ok(Expr::Compare(Box::new(a), *op, Box::new(b)));
+			}
+		}
+		return Ok(a);
+	}
+
+	fn value(&mut self) -> Result<Expr, ConditionError> {
+		self.skip_spaces();
+		if self.eat("(") {
+			self.enter()?;
+			let expr = self.or()?;
+			if !self.eat(")") {
+				return Err(self.error("expected )"));
+			}
+			self.leave(1);
+			return Ok(expr);
+		}
+
+		let start = self.at;
+		while self.at < self.source.len() && (self.source[self.at].is_ascii_alphanumeric() || self.source[self.at] == b'_') {
+			self.at += 1;
+		}
+		let word = std::str::from_utf8(&self.source[start..self.at]).unwrap();
+		if word.is_empty() {
+			return Err(self.error("expected a value"));
+		}
+
+		if word.as_bytes()[0].is_ascii_digit() {
+			let number = match word.strip_prefix("0x") {
+				Some(hex) => u32::from_str_radix(hex, 16),
+				None => word.parse::<u32>(),
+			};
+			return number.map(Expr::Number).map_err(|_| ConditionError {
+				position: start,
+				message: "invalid number",
+			});
+		}
+
+		let register = match word {
+			"bus" => Register::Bus,
+			"ip" | "next" => Register::Next,
+			"sp" => Register::StackPointer,
+			"hi" => Register::Hi,
+			"lo" => Register::Lo,
+			"compare" => Register::Compare,
+			"mem" => {
+				if !self.eat("[") {
+					return Err(self.error("expected ["));
+				}
+				self.enter()?;
+				let address = self.or()?;
+				if !self.eat("]") {
+					return Err(self.error("expected ]"));
+				}
+				self.leave(1);
+				return Ok(Expr::Memory(Box::new(address)));
+			},
+			_ => {
+				self.at = start;
+				return Err(self.error("unknown name"));
+			},
+		};
+		return Ok(Expr::Register(register));
+	}
+}
@@ -0,0 +1,155 @@
+use crate::{storage, location, Program, Processor, ProcessorStatus, ALU, CallFrame, STACK_SIZE};
+use crate::mmu::Mmu;
+use crate::snapshot::{self, Reader, Writer, SnapshotError};
+
+// the registers of one core. all cores share the rest of Processor: memory, devices,
+// permissions, the heap, pending interrupts and watchpoints.
+//
+// the running core's registers are the Processor's own, the others are parked in
+// Program::Cores. the slot of the running core is only a placeholder, swapped in
+// when another core runs. cores take turns one step at a time, in core order,
+// skipping halted ones
+pub(crate) struct Core {
+	next: location,
+	bus: storage,
+	alu: ALU,
+	stack_pointer: location,
+	stack_base: location,
+	stack_limit: location,
+	frames: Vec<CallFrame>,
+	supervisor: bool,
+	interrupts_enabled: bool,
+	mmu: Mmu,
+	// not swapped, this is always the core's own
+	pub(crate) halted: bool,
+}
+
+impl Core {
+	// a core that starts at entry with a stack of STACK_SIZE words below stack_base
+	pub(crate) fn new(entry: location, stack_base: location) -> Core {
+		Core {
+			next: entry,
+			bus: 0,
+			alu: ALU::new(),
+			stack_pointer: stack_base,
+			stack_base,
+			stack_limit: stack_base.saturating_sub(STACK_SIZE),
+			frames: Vec::new(),
+			supervisor: true,
+			interrupts_enabled: false,
+			mmu: Mmu::new(),
+			halted: false,
+		}
+	}
+
+	pub(crate) fn next(&self) -> location {
+		return self.next;
+	}
+
+	pub(crate) fn bus(&self) -> storage {
+		return self.bus;
+	}
+
+	pub(crate) fn stack_pointer(&self) -> location {
+		return self.stack_pointer;
+	}
+
+	pub(crate) fn supervisor(&self) -> bool {
+		return self.supervisor;
+	}
+
+	// layout: halted u8, next, bus, stack pointer, base and limit, frames and the ALU as for
+	// the processor, supervisor u8, interrupts enabled u8, paging u8, page table base and length
+	pub(crate) fn save(&self, w: &mut Writer) {
+		w.u8(self.halted as u8);
+		w.u32(self.next);
+		w.u32(self.bus);
+		w.u32(self.stack_pointer);
+		w.u32(self.stack_base);
+		w.u32(self.stack_limit);
+		snapshot::write_frames(w, &self.frames);
+		snapshot::write_alu(w, &self.alu);
+		w.u8(self.supervisor as u8);
+		w.u8(self.interrupts_enabled as u8);
+		w.u8(self.mmu.paging as u8);
+		w.u32(self.mmu.page_table_base);
+		w.u32(self.mmu.page_table_length);
+	}
+
+	pub(crate) fn load(r: &mut Reader) -> Result<Core, SnapshotError> {
+		let halted = r.bool()?;
+		let mut core = Core::new(0, 0);
+		core.halted = halted;
+		core.next = r.u32()?;
+		core.bus = r.u32()?;
+		core.stack_pointer = r.u32()?;
+		core.stack_base = r.u32()?;
+		core.stack_limit = r.u32()?;
+		snapshot::check_stack(core.stack_pointer, core.stack_base, core.stack_limit)?;
+		core.frames = snapshot::read_frames(r)?;
+		core.alu = snapshot::read_alu(r)?;
+		core.supervisor = r.bool()?;
+		core.interrupts_enabled = r.bool()?;
+		core.mmu.paging = r.bool()?;
+		core.mmu.page_table_base = r.u32()?;
+		core.mmu.page_table_length = r.u32()?;
+		return Ok(core);
+	}
+}
+
+impl Processor {
+	fn swap_registers(&mut self, core: &mut Core) {
+		std::mem::swap(&mut self.next, &mut core.next);
+		std::mem::swap(&mut self.bus, &mut core.bus);
+		std::mem::swap(&mut self.alu, &mut core.alu);
+		std::mem::swap(&mut self.stack_pointer, &mut core.stack_pointer);
+		std::mem::swap(&mut self.stack_base, &mut core.stack_base);
+		std::mem::swap(&mut self.stack_limit, &mut core.stack_limit);
+		std::mem::swap(&mut self.frames, &mut core.frames);
+		std::mem::swap(&mut self.supervisor, &mut core.supervisor);
+		std::mem::swap(&mut self.interrupts_enabled, &mut core.interrupts_enabled);
+		std::mem::swap(&mut self.mmu, &mut core.mmu);
+	}
+}
+
+// returns the id of the new core
+pub(crate) fn add_core(program: &mut Program, entry: location, stack_base: location) -> u32 {
+	program.Cores.push(Core::new(entry, stack_base));
+	return program.Cores.len() as u32 - 1;
+}
+
+// makes core the running one
+pub(crate) fn switch_to(program: &mut Program, core: u32) {
+	let current = program.Processor.core_id;
+	if current == core {
+		return;
+	}
+	let p = &mut program.Processor;
+	p.swap_registers(&mut program.Cores[current as usize]);
+	p.swap_registers(&mut program.Cores[core as usize]);
+	p.core_id = core;
+	// another core may have written its page table
+	p.mmu.flush();
+}
+
+// after each step, the next core that has not halted runs.
+// a faulting core stays the running one, for the debugger
+pub(crate) fn schedule(program: &mut Program) {
+	let count = program.Cores.len() as u32;
+	if count < 2 || program.Processor.status == ProcessorStatus::Faulted {
+		return;
+	}
+	let current = program.Processor.core_id;
+	let next = (1..=count)
+		.map(|offset| (current + offset) % count)
+		.find(|&core| !program.Cores[core as usize].halted);
+	if let Some(core) = next {
+		switch_to(program, core);
+	}
+}
+
+// marks the running core halted. returns true if other cores are still running
+pub(crate) fn halt(program: &mut Program) -> bool {
+	program.Cores[program.Processor.core_id as usize].halted = true;
+	return program.Cores.iter().any(|core| !core.halted);
+}
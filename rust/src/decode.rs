@@ -0,0 +1,142 @@
+use crate::{storage, location, Processor, Opcode};
+use crate::permissions::PAGE_SIZE;
+
+// more pages than this and the cache starts over
+const CACHED_PAGES: usize = 64;
+
+// an instruction as the interpreter runs it. both parameter words are always read,
+// step only uses as many as the opcode takes
+#[derive(Clone, Copy)]
+pub(crate) struct Decoded {
+	// the entry is stale unless this matches DecodeCache::generation
+	generation: u32,
+	pub(crate) op: storage,
+	pub(crate) opcode: Option<Opcode>,
+	pub(crate) params: [storage; 2],
+}
+
+const EMPTY: Decoded = Decoded {
+	generation: 0,
+	op: 0,
+	opcode: None,
+	params: [0, 0],
+};
+
+type DecodedPage = [Decoded; PAGE_SIZE as usize];
+
+// decoded instructions by physical address, one table for each page that code has run
+// from. an entry is only made for an instruction that was mapped and executable, so a hit
+// skips those checks as well as reading memory.
+// writes through the processor invalidate the instructions they touch, and changing
+// permissions or releasing pages invalidates everything. anything else that changes
+// memory (syscall hosts, the JS side writing into the wasm memory, snapshots) is covered
+// by invalidate_all, which is called whenever a run starts and after every syscall.
+// entries are physical, so the MMU only matters for instructions whose parameters are
+// on the next page, and those are not cached while paging is on
+pub(crate) struct DecodeCache {
+	enabled: bool,
+	generation: u32,
+	pages: Vec<(u32, Box<DecodedPage>)>,
+	// the index in pages of the last page looked up
+	last: usize,
+}
+
+impl DecodeCache {
+	pub(crate) fn new() -> DecodeCache {
+		DecodeCache {
+			enabled: true,
+			generation: 1,
+			pages: Vec::new(),
+			last: 0,
+		}
+	}
+
+	pub(crate) fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+		self.invalidate_all();
+	}
+
+	pub(crate) fn invalidate_all(&mut self) {
+		self.generation = self.generation.wrapping_add(1);
+		if self.generation == 0 {
+			// 0 marks entries that were never filled, so start over
+			self.pages.clear();
+			self.generation = 1;
+		}
+	}
+
+	// a write to the address can change the instruction there,
+	// or a parameter of the two instructions before it
+	pub(crate) fn invalidate(&mut self, address: location) {
+		if self.pages.is_empty() {
+			return;
+		}
+		for back in 0..3 {
+			let address = match address.checked_sub(back) {
+				Some(address) => address,
+				None => break,
+			};
+			if let Some(index) = self.find(address / PAGE_SIZE) {
+				self.pages[index].1[(address % PAGE_SIZE) as usize].generation = 0;
+			}
+		}
+	}
+
+	fn find(&mut self, page: u32) -> Option<usize> {
+		if self.pages.get(self.last).is_some_and(|&(number, _)| number == page) {
+			return Some(self.last);
+		}
+		let index = self.pages.iter().position(|&(number, _)| number == page)?;
+		self.last = index;
+		return Some(index);
+	}
+
+	pub(crate) fn get(&mut self, address: location) -> Option<Decoded> {
+		if !self.enabled {
+			return None;
+		}
+		let index = self.find(address / PAGE_SIZE)?;
+		let entry = self.pages[index].1[(address % PAGE_SIZE) as usize];
+		if entry.generation != self.generation {
+			return None;
+		}
+		return Some(entry);
+	}
+
+	pub(crate) fn insert(&mut self, address: location, decoded: Decoded) {
+		if !self.enabled {
+			return;
+		}
+		let page = address / PAGE_SIZE;
+		let index = match self.find(page) {
+			Some(index) => index,
+			None => {
+				if self.pages.len() >= CACHED_PAGES {
+					self.pages.clear();
+				}
+				self.pages.push((page, Box::new([EMPTY; PAGE_SIZE as usize])));
+				self.pages.len() - 1
+			},
+		};
+		self.pages[index].1[(address % PAGE_SIZE) as usize] = Decoded {
+			generation: self.generation,
+			..decoded
+		};
+	}
+}
+
+impl Processor {
+	// the instruction at the address, straight from memory
+	pub(crate) fn fetch(&self, address: location) -> Decoded {
+		let op = self._peek_memory_loc(address);
+		return Decoded {
+			generation: 0,
+			op,
+			opcode: Opcode::from_code(op),
+			params: [
+				self._peek_memory_loc(address.wrapping_add(1)),
+				self._peek_memory_loc(address.wrapping_add(2)),
+			],
+		};
+	}
+}
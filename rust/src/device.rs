@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::{storage, location};
+use crate::interrupt::INTERRUPT_LINES;
+
+// something mapped into the address space, see Machine::map_device.
+// offsets are from the start of the mapping.
+// only loads and stores made by instructions reach a device. instruction fetch,
+// the debugger and syscall hosts see the memory underneath, since a read can have
+// side effects (reading a keyboard takes the key)
+pub trait Device: Send {
+	fn read(&mut self, offset: location) -> storage;
+	fn write(&mut self, offset: location, value: storage);
+
+	// called once for every executed instruction.
+	// returns an interrupt line to raise, see interrupt.rs
+	fn tick(&mut self) -> Option<u32> {
+		return None;
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapError {
+	// end is before start
+	EmptyRange,
+	// the range overlaps the mapping with this id
+	Overlaps(u32),
+}
+
+impl fmt::Display for MapError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return match *self {
+			MapError::EmptyRange => write!(f, "the range is empty"),
+			MapError::Overlaps(id) => write!(f, "the range overlaps device {}", id),
+		};
+	}
+}
+
+// a device over the addresses start..=end
+struct Mapping {
+	id: u32,
+	start: location,
+	end: location,
+	device: Box<dyn Device>,
+}
+
+// routes loads and stores inside a mapping to its device
+pub(crate) struct DeviceBus {
+	mappings: Vec<Mapping>,
+	next_id: u32,
+}
+
+impl DeviceBus {
+	pub(crate) fn new() -> DeviceBus {
+		DeviceBus {
+			mappings: Vec::new(),
+			next_id: 0,
+		}
+	}
+
+	pub(crate) fn map(&mut self, start: location, end: location, device: Box<dyn Device>) -> Result<u32, MapError> {
+		if end < start {
+			return Err(MapError::EmptyRange);
+		}
+		if let Some(other) = self.mappings.iter().find(|m| start <= m.end && m.start <= end) {
+			return Err(MapError::Overlaps(other.id));
+		}
+		self.next_id += 1;
+		self.mappings.push(Mapping {
+			id: self.next_id,
+			start,
+			end,
+			device,
+		});
+		return Ok(self.next_id);
+	}
+
+	pub(crate) fn unmap(&mut self, id: u32) -> Option<Box<dyn Device>> {
+		let index = self.mappings.iter().position(|m| m.id == id)?;
+		return Some(self.mappings.remove(index).device);
+	}
+
+	// compiled blocks skip ticking when nothing is mapped, see jit_wasm.rs
+	#[cfg(target_arch = "wasm32")]
+	pub(crate) fn is_empty(&self) -> bool {
+		return self.mappings.is_empty();
+	}
+
+	// (id, start, end) for each mapping
+	pub(crate) fn mappings(&self) -> Vec<(u32, location, location)> {
+		return self.mappings.iter().map(|m| (m.id, m.start, m.end)).collect();
+	}
+
+	// the interrupt lines raised by the devices, as a bit set
+	pub(crate) fn tick(&mut self) -> u32 {
+		let mut raised = 0;
+		for mapping in self.mappings.iter_mut() {
+			if let Some(line) = mapping.device.tick() {
+				if line < INTERRUPT_LINES {
+					raised |= 1 << line;
+				}
+			}
+		}
+		return raised;
+	}
+
+	// None if no device is mapped at the address
+	pub(crate) fn read(&mut self, address: location) -> Option<storage> {
+		let mapping = self.mappings.iter_mut().find(|m| m.start <= address && address <= m.end)?;
+		return Some(mapping.device.read(address - mapping.start));
+	}
+
+	// false if no device is mapped at the address
+	pub(crate) fn write(&mut self, address: location, value: storage) -> bool {
+		return match self.mappings.iter_mut().find(|m| m.start <= address && address <= m.end) {
+			Some(mapping) => {
+				mapping.device.write(address - mapping.start, value);
+				true
+			},
+			None => false,
+		};
+	}
+}
+
+// a serial port with two registers.
+//	0: write sends a byte, read takes the next received byte or 0 if there is none
+//	1: read gives the number of received bytes waiting
+// clones share the same port, so a clone kept outside the machine can talk to it
+#[derive(Clone, Default)]
+pub struct SerialPort {
+	sent: Arc<Mutex<Vec<u8>>>,
+	received: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl SerialPort {
+	pub fn new() -> SerialPort {
+		return SerialPort::default();
+	}
+
+	// what the program has sent so far
+	pub fn output(&self) -> Vec<u8> {
+		return self.sent.lock().unwrap().clone();
+	}
+
+	pub fn take_output(&self) -> Vec<u8> {
+		return std::mem::take(&mut *self.sent.lock().unwrap());
+	}
+
+	// queues bytes for the program to read
+	pub fn send(&self, bytes: &[u8]) {
+		self.received.lock().unwrap().extend(bytes.iter().cloned());
+	}
+}
+
+impl Device for SerialPort {
+	fn read(&mut self, offset: location) -> storage {
+		let mut received = self.received.lock().unwrap();
+		return match offset {
+			0 => received.pop_front().map_or(0, |byte| byte as storage),
+			1 => received.len() as storage,
+			_ => 0,
+		};
+	}
+
+	fn write(&mut self, offset: location, value: storage) {
+		if offset == 0 {
+			self.sent.lock().unwrap().push(value as u8);
+		}
+	}
+}
+
+// a keyboard with two registers.
+//	0: read takes the next key code, or 0 if no key is waiting
+//	1: read gives the number of keys waiting
+// clones share the same keyboard
+#[derive(Clone, Default)]
+pub struct Keyboard {
+	keys: Arc<Mutex<VecDeque<storage>>>,
+}
+
+impl Keyboard {
+	pub fn new() -> Keyboard {
+		return Keyboard::default();
+	}
+
+	pub fn press(&self, key: storage) {
+		self.keys.lock().unwrap().push_back(key);
+	}
+}
+
+impl Device for Keyboard {
+	fn read(&mut self, offset: location) -> storage {
+		let mut keys = self.keys.lock().unwrap();
+		return match offset {
+			0 => keys.pop_front().unwrap_or(0),
+			1 => keys.len() as storage,
+			_ => 0,
+		};
+	}
+
+	fn write(&mut self, _offset: location, _value: storage) {}
+}
+
+// a framebuffer of width * height words, one per pixel, row by row.
+// map it over width * height addresses. clones share the same pixels.
+// None when width * height does not fit in the address space
+#[derive(Clone)]
+pub struct Screen {
+	width: u32,
+	height: u32,
+	pixels: Arc<Mutex<Vec<storage>>>,
+}
+
+impl Screen {
+	pub fn new(width: u32, height: u32) -> Option<Screen> {
+		let size = width.checked_mul(height)?;
+		return Some(Screen {
+			width,
+			height,
+			pixels: Arc::new(Mutex::new(vec![0; size as usize])),
+		});
+	}
+
+	pub fn width(&self) -> u32 {
+		return self.width;
+	}
+
+	pub fn height(&self) -> u32 {
+		return self.height;
+	}
+
+	// 0 outside the screen
+	pub fn pixel(&self, x: u32, y: u32) -> storage {
+		if x >= self.width || y >= self.height {
+			return 0;
+		}
+		return self.pixels.lock().unwrap()[(y * self.width + x) as usize];
+	}
+
+	pub fn pixels(&self) -> Vec<storage> {
+		return self.pixels.lock().unwrap().clone();
+	}
+}
+
+impl Device for Screen {
+	fn read(&mut self, offset: location) -> storage {
+		return self.pixels.lock().unwrap().get(offset as usize).cloned().unwrap_or(0);
+	}
+
+	fn write(&mut self, offset: location, value: storage) {
+		if let Some(pixel) = self.pixels.lock().unwrap().get_mut(offset as usize) {
+			*pixel = value;
+		}
+	}
+}
+
+// an interval timer counting executed instructions. registers:
+//	0: the period. writing it restarts the timer, 0 stops it
+//	1: the interrupt line raised every period, 0 to start with
+//	2: instructions left until the next interrupt, read only
+#[derive(Default)]
+pub struct Timer {
+	period: storage,
+	line: storage,
+	remaining: storage,
+}
+
+impl Timer {
+	pub fn new() -> Timer {
+		return Timer::default();
+	}
+}
+
+impl Device for Timer {
+	fn read(&mut self, offset: location) -> storage {
+		return match offset {
+			0 => self.period,
+			1 => self.line,
+			2 => self.remaining,
+			_ => 0,
+		};
+	}
+
+	fn write(&mut self, offset: location, value: storage) {
+		match offset {
+			0 => {
+				self.period = value;
+				self.remaining = value;
+			},
+			1 => self.line = value,
+			_ => {},
+		}
+	}
+
+	fn tick(&mut self) -> Option<u32> {
+		if self.period == 0 {
+			return None;
+		}
+		self.remaining -= 1;
+		if self.remaining > 0 {
+			return None;
+		}
+		self.remaining = self.period;
+		return Some(self.line);
+	}
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use crate::{storage, location, Processor, Opcode, OperandKind};
+
+// one decoded instruction. words that are not a known opcode decode
+// as a single data word with no mnemonic.
+// addresses are virtual: with paging on they are translated the way the
+// running core would, without faulting or touching the TLB
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+	pub address: location,
+	pub opcode: storage,
+	// None for words that are not a known opcode
+	pub decoded: Option<Opcode>,
+	pub mnemonic: Option<&'static str>,
+	pub params: Vec<storage>,
+	// false if the address has no valid page table entry, the word then decodes as
+	// data with opcode 0. parameters on an unmapped page read as 0
+	pub mapped: bool,
+}
+
+impl Instruction {
+	// number of words the instruction takes, including the opcode
+	pub fn size(&self) -> u32 {
+		return 1 + self.params.len() as u32;
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if !self.mapped {
+			return write!(f, "{}: unmapped", self.address);
+		}
+		match self.mnemonic {
+			Some(mnemonic) => write!(f, "{}: {}", self.address, mnemonic)?,
+			None => return write!(f, "{}: .word {}", self.address, self.opcode),
+		}
+		let kinds = self.decoded.map_or(&[][..], |opcode| opcode.info().operands);
+		for (param, kind) in self.params.iter().zip(kinds.iter()) {
+			match kind {
+				OperandKind::Offset => write!(f, " {}", *param as i32)?,
+				_ => write!(f, " {}", param)?,
+			}
+		}
+		return Ok(());
+	}
+}
+
+// the word at a virtual address, None if it is not mapped
+fn peek(p: &Processor, address: location) -> Option<storage> {
+	return p.translate_quietly(address).map(|physical| p._peek_memory_loc(physical));
+}
+
+pub(crate) fn decode(p: &Processor, address: location) -> Instruction {
+	let (opcode, mapped) = match peek(p, address) {
+		Some(opcode) => (opcode, true),
+		None => (0, false),
+	};
+	return match Opcode::from_code(opcode).filter(|_| mapped) {
+		Some(decoded) => Instruction {
+			address,
+			opcode,
+			decoded: Some(decoded),
+			mnemonic: Some(decoded.mnemonic()),
+			params: (1..=decoded.operand_count()).map(|i| peek(p, address.wrapping_add(i)).unwrap_or(0)).collect(),
+			mapped,
+		},
+		None => Instruction {
+			address,
+			opcode,
+			decoded: None,
+			mnemonic: None,
+			params: Vec::new(),
+			mapped,
+		},
+	};
+}
+
+// decodes the instructions starting in start..end. the last one may run past end
+pub(crate) fn disassemble(p: &Processor, start: location, end: location) -> Vec<Instruction> {
+	let mut instructions = Vec::new();
+	let mut address = start;
+	while address < end {
+		let instruction = decode(p, address);
+		match address.checked_add(instruction.size()) {
+			Some(next) => address = next,
+			None => {
+				instructions.push(instruction);
+				break;
+			},
+		}
+		instructions.push(instruction);
+	}
+	return instructions;
+}
+
+// one line per instruction, see Instruction's Display
+pub(crate) fn to_text(instructions: &[Instruction]) -> String {
+	let mut out = String::new();
+	for instruction in instructions {
+		out.push_str(&instruction.to_string());
+		out.push('\n');
+	}
+	return out;
+}
+
+// [address, opcode, param count, params...] for each instruction.
+// unknown opcodes have a param count of 0, unmapped words are opcode 0 with none
+pub(crate) fn to_words(instructions: &[Instruction]) -> Vec<u32> {
+	let mut words = Vec::new();
+	for instruction in instructions {
+		words.push(instruction.address);
+		words.push(instruction.opcode);
+		words.push(instruction.params.len() as u32);
+		words.extend_from_slice(&instruction.params);
+	}
+	return words;
+}
+
+pub fn mnemonic(opcode: storage) -> Option<&'static str> {
+	return Opcode::from_code(opcode).map(|decoded| decoded.mnemonic());
+}
@@ -0,0 +1,693 @@
+// the wasm-bindgen exports driving the main machine, and the program-level
+// operations behind them that Machine shares. the names are the ones the
+// TS side calls, which predate the rust naming conventions
+#![allow(non_snake_case)]
+
+use wasm_bindgen::prelude::*;
+use std::sync::Mutex;
+
+use crate::*;
+
+// the machine driven by the r_ exports below.
+// additional machines can be created from JS or rust with `Machine::new`
+lazy_static! {
+	static ref MAIN_MACHINE: Mutex<Machine> = Mutex::new(Machine::new());
+}
+
+fn withProgram<T>(f: impl FnOnce(&mut Program) -> T) -> T {
+	let mut machine = MAIN_MACHINE.lock().unwrap();
+	return f(&mut machine.program);
+}
+
+#[wasm_bindgen]
+pub fn r_SetBreakpoint(n: jsint) {
+	withProgram(|program| SetBreakpoint(n as u32, program));
+}
+
+#[wasm_bindgen]
+pub fn r_RemoveBreakpoint(n: jsint) {
+	withProgram(|program| RemoveBreakpoint(n as u32, program));
+}
+
+#[wasm_bindgen]
+pub fn r_GetIsBreakpoint(n: jsint) -> bool {
+	return withProgram(|program| GetIsBreakpoint(n as u32, program));
+}
+
+// only stops at the breakpoint when the condition holds, see Condition for the syntax.
+// creates the breakpoint if there is none, an empty condition removes it.
+// returns false if the condition does not parse
+#[wasm_bindgen]
+pub fn r_SetBreakpointCondition(n: jsint, condition: &str) -> bool {
+	return withProgram(|program| SetBreakpointCondition(n as u32, condition, program).is_ok());
+}
+
+// the first count hits of the breakpoint do not stop. returns false if there is no breakpoint
+#[wasm_bindgen]
+pub fn r_SetBreakpointIgnoreCount(n: jsint, count: u32) -> bool {
+	return withProgram(|program| SetBreakpointIgnoreCount(n as u32, count, program));
+}
+
+// how many times the breakpoint was reached with its condition true
+#[wasm_bindgen]
+pub fn r_GetBreakpointHitCount(n: jsint) -> u32 {
+	return withProgram(|program| GetBreakpointHitCount(n as u32, program));
+}
+
+// turns the breakpoint into a logpoint, which adds the message to the log
+// instead of pausing. see Template for the syntax, an empty message makes it a breakpoint again.
+// returns false if the message does not parse
+#[wasm_bindgen]
+pub fn r_SetLogpoint(n: jsint, message: &str) -> bool {
+	return withProgram(|program| SetLogpoint(n as u32, message, program).is_ok());
+}
+
+// the logpoint messages since the last call
+#[wasm_bindgen]
+pub fn r_TakeBreakpointLog() -> Vec<String> {
+	return withProgram(|program| std::mem::take(&mut program.BreakpointLog));
+}
+
+// a view of memory page n, see memory.rs. the page is allocated so it can be written
+// through the view. empty if the page is not mapped
+#[wasm_bindgen]
+pub fn r_GetPage(n: u32) -> js_sys::Uint32Array {
+	return withProgram(|program| GetPage(n, program));
+}
+
+#[wasm_bindgen]
+pub fn r_Continue() {
+	withProgram(Continue);
+}
+
+// runs at most max_steps instructions, so the worker can handle messages
+// between slices. returns the number of instructions executed,
+// r_GetLastRunReason says why it returned
+#[wasm_bindgen]
+pub fn r_RunFor(max_steps: u32) -> jsint {
+	return withProgram(|program| program.run_budget(Some(max_steps)).0 as jsint);
+}
+
+// see RunReason::code
+#[wasm_bindgen]
+pub fn r_GetLastRunReason() -> jsint {
+	return withProgram(|program| program.LastRunReason.code());
+}
+
+// stops a time sliced run, the next r_RunFor or r_Continue resumes it
+#[wasm_bindgen]
+pub fn r_Pause() {
+	withProgram(Pause);
+}
+
+// executes one instruction, or a whole call when the instruction is a CALL
+#[wasm_bindgen]
+pub fn r_StepOver() {
+	withProgram(StepOver);
+}
+
+// executes exactly one instruction, following calls
+#[wasm_bindgen]
+pub fn r_StepInto() {
+	withProgram(StepInto);
+}
+
+// runs until the current call returns to its caller.
+// returns the number of instructions executed
+#[wasm_bindgen]
+pub fn r_StepOut() -> jsint {
+	return withProgram(|program| StepOut(program).0 as jsint);
+}
+
+// executes n instructions of the running core, stopping early at breakpoints.
+// returns the number of instructions executed, other cores' included
+#[wasm_bindgen]
+pub fn r_StepN(n: u32) -> jsint {
+	return withProgram(|program| StepN(n, program).0 as jsint);
+}
+
+// runs until the instruction pointer reaches the address, as if there was a
+// breakpoint there for this run only. returns the number of instructions executed
+#[wasm_bindgen]
+pub fn r_RunTo(address: jsint) -> jsint {
+	return withProgram(|program| RunTo(address as location, program).0 as jsint);
+}
+
+#[wasm_bindgen]
+pub fn r_Initialize(init: &[storage]) {
+	let mut machine = MAIN_MACHINE.lock().unwrap();
+	*machine = Machine::new();
+	machine.initialize(init);
+}
+
+#[wasm_bindgen]
+pub fn r_GetInstructionPointer() -> jsint {
+	return withProgram(|program| program.Processor.next as jsint);
+}
+
+#[wasm_bindgen]
+pub fn r_GetProcessorStatus() -> jsint {
+	return withProgram(|program| GetProcessorStatus(program));
+}
+
+#[wasm_bindgen]
+pub fn r_GetStackPointer() -> jsint {
+	return withProgram(|program| program.Processor.stack_pointer as jsint);
+}
+
+// the active call frames, outermost first, flattened into
+// [call site, target, return address, stack pointer] for each frame
+#[wasm_bindgen]
+pub fn r_GetCallStack() -> Vec<u32> {
+	return withProgram(|program| GetCallStack(program));
+}
+
+// the whole machine state, except for the syscall host, as a versioned binary blob
+#[wasm_bindgen]
+pub fn r_SaveSnapshot() -> Vec<u8> {
+	return withProgram(|program| snapshot::save(program));
+}
+
+// returns false, leaving the machine untouched, if the snapshot can't be read
+#[wasm_bindgen]
+pub fn r_RestoreSnapshot(bytes: &[u8]) -> bool {
+	return withProgram(|program| snapshot::restore(program, bytes).is_ok());
+}
+
+// pauses the processor after an instruction accesses an address in start..=end.
+// kind is 1 for reads, 2 for writes, 3 for both. returns the watchpoint id, or 0 for a bad kind
+#[wasm_bindgen]
+pub fn r_AddWatchpoint(start: u32, end: u32, kind: u32) -> u32 {
+	return withProgram(|program| AddWatchpoint(start, end, kind, program));
+}
+
+#[wasm_bindgen]
+pub fn r_RemoveWatchpoint(id: u32) -> bool {
+	return withProgram(|program| program.Processor.remove_watchpoint(id));
+}
+
+// the last watchpoint hit as [watchpoint id, address, access kind, old value, new value, instruction],
+// empty if no watchpoint has been hit
+#[wasm_bindgen]
+pub fn r_GetLastWatchHit() -> Vec<u32> {
+	return withProgram(|program| GetLastWatchHit(program));
+}
+
+// starts recording executed instructions, see r_AddTraceFilter to only record some of them
+#[wasm_bindgen]
+pub fn r_StartTrace() {
+	withProgram(|program| program.Tracer.start());
+}
+
+// stops recording, the trace is kept until r_ClearTrace
+#[wasm_bindgen]
+pub fn r_StopTrace() {
+	withProgram(|program| program.Tracer.stop());
+}
+
+#[wasm_bindgen]
+pub fn r_ClearTrace() {
+	withProgram(|program| program.Tracer.clear());
+}
+
+// only instructions at addresses in one of the added ranges (inclusive) are recorded
+#[wasm_bindgen]
+pub fn r_AddTraceFilter(start: u32, end: u32) {
+	withProgram(|program| program.Tracer.add_filter(start, end));
+}
+
+#[wasm_bindgen]
+pub fn r_ClearTraceFilters() {
+	withProgram(|program| program.Tracer.clear_filters());
+}
+
+// how many instructions the trace keeps, older ones are dropped
+#[wasm_bindgen]
+pub fn r_SetTraceLimit(limit: usize) {
+	withProgram(|program| program.Tracer.set_limit(limit));
+}
+
+#[wasm_bindgen]
+pub fn r_GetTraceLength() -> jsint {
+	return withProgram(|program| program.Tracer.len() as jsint);
+}
+
+// the compact binary format described in trace.rs
+#[wasm_bindgen]
+pub fn r_ExportTraceBinary() -> Vec<u8> {
+	return withProgram(|program| program.Tracer.export_binary());
+}
+
+// newline delimited JSON, one object per instruction
+#[wasm_bindgen]
+pub fn r_ExportTraceJson() -> String {
+	return withProgram(|program| program.Tracer.export_json());
+}
+
+// the instructions starting in start..end as text, one per line
+#[wasm_bindgen]
+pub fn r_Disassemble(start: u32, end: u32) -> String {
+	return withProgram(|program| disasm::to_text(&disasm::disassemble(&program.Processor, start, end)));
+}
+
+// the instructions starting in start..end as [address, opcode, param count, params...] each
+#[wasm_bindgen]
+pub fn r_DisassembleWords(start: u32, end: u32) -> Vec<u32> {
+	return withProgram(|program| disasm::to_words(&disasm::disassemble(&program.Processor, start, end)));
+}
+
+// the decoded instruction cache is on by default, turning it off is only useful for comparisons
+#[wasm_bindgen]
+pub fn r_SetDecodeCache(enabled: bool) {
+	withProgram(|program| program.Processor.decode_cache.set_enabled(enabled));
+}
+
+// the compiled tier, see jit.rs. off by default.
+// compiled blocks only run while tracing and watchpoints are off
+#[wasm_bindgen]
+pub fn r_SetJit(enabled: bool) {
+	withProgram(|program| program.Jit.set_enabled(enabled));
+}
+
+#[wasm_bindgen]
+pub fn r_GetJitBlockCount() -> jsint {
+	return withProgram(|program| program.Jit.block_count() as jsint);
+}
+
+// false if the line does not exist, see INTERRUPT_LINES
+#[wasm_bindgen]
+pub fn r_RaiseInterrupt(line: u32) -> bool {
+	return withProgram(|program| program.Processor.raise_interrupt(line));
+}
+
+// [enabled, pending lines as a bit set]
+#[wasm_bindgen]
+pub fn r_GetInterruptState() -> Vec<u32> {
+	return withProgram(|program| vec![program.Processor.interrupts_enabled as u32, program.Processor.pending_interrupts]);
+}
+
+// sets the permissions of every page overlapping start..=end, see permissions.rs
+#[wasm_bindgen]
+pub fn r_SetPermissions(start: u32, end: u32, bits: u32) {
+	withProgram(|program| SetPermissions(start, end, bits, program));
+}
+
+#[wasm_bindgen]
+pub fn r_GetPagePermissions(address: u32) -> u32 {
+	return withProgram(|program| program.Processor.page_permissions(address));
+}
+
+// with checks on, freed heap allocations are quarantined to catch double frees and use after free
+#[wasm_bindgen]
+pub fn r_SetHeapChecks(enabled: bool) {
+	withProgram(|program| SetHeapChecks(enabled, program));
+}
+
+// [address, size] for each live heap allocation
+#[wasm_bindgen]
+pub fn r_GetHeapAllocations() -> Vec<u32> {
+	return withProgram(|program| GetHeapAllocations(program));
+}
+
+// a core starting at entry with its stack below stack_base, returns its id. see cores.rs
+#[wasm_bindgen]
+pub fn r_AddCore(entry: u32, stack_base: u32) -> u32 {
+	return withProgram(|program| cores::add_core(program, entry, stack_base));
+}
+
+// [core count, running core]
+#[wasm_bindgen]
+pub fn r_GetCores() -> Vec<u32> {
+	return withProgram(|program| vec![program.Cores.len() as u32, program.Processor.core_id]);
+}
+
+// [next, bus, stack pointer, supervisor, halted], or empty for a core that does not exist
+#[wasm_bindgen]
+pub fn r_GetCoreState(core: u32) -> Vec<u32> {
+	return withProgram(|program| GetCoreState(program, core));
+}
+
+// user mode cannot run privileged instructions, see trap.rs
+#[wasm_bindgen]
+pub fn r_SetSupervisor(supervisor: bool) {
+	withProgram(|program| program.Processor.supervisor = supervisor);
+}
+
+// the page table used with paging on, see mmu.rs
+#[wasm_bindgen]
+pub fn r_SetPageTable(base: u32, length: u32) {
+	withProgram(|program| program.Processor.set_page_table(base, length));
+}
+
+#[wasm_bindgen]
+pub fn r_SetPaging(enabled: bool) {
+	withProgram(|program| program.Processor.set_paging(enabled));
+}
+
+// [supervisor, paging, page table base, page table length, TLB hits, TLB misses]
+#[wasm_bindgen]
+pub fn r_GetMmuState() -> Vec<u32> {
+	return withProgram(|program| GetMmuState(program));
+}
+
+// [physical address], or empty if the address has no valid page table entry
+#[wasm_bindgen]
+pub fn r_Translate(address: u32) -> Vec<u32> {
+	return withProgram(|program| program.Processor.translate_quietly(address).into_iter().collect());
+}
+
+// [id, start, end] for each mapped device
+#[wasm_bindgen]
+pub fn r_GetDeviceMappings() -> Vec<u32> {
+	return withProgram(|program| GetDeviceMappings(program));
+}
+
+// empty for unknown opcodes
+#[wasm_bindgen]
+pub fn r_GetMnemonic(opcode: u32) -> String {
+	return mnemonic(opcode).unwrap_or("").to_string();
+}
+
+// the instruction set as a JSON array of {code, mnemonic, operands, description},
+// operands being a list of operand kind names
+#[wasm_bindgen]
+pub fn r_GetInstructionSet() -> String {
+	return isa::to_json();
+}
+
+// undoes the last step, returns false if there is no history left
+#[wasm_bindgen]
+pub fn r_StepBack() -> bool {
+	return withProgram(history::step_back);
+}
+
+// steps back to the previous breakpoint, returns the number of steps undone
+#[wasm_bindgen]
+pub fn r_ReverseContinue() -> jsint {
+	return withProgram(|program| history::reverse_continue(program) as jsint);
+}
+
+// how many steps are kept for r_StepBack, 0 turns recording off. off by default
+#[wasm_bindgen]
+pub fn r_SetHistoryLimit(limit: u32) {
+	withProgram(|program| program.History.set_limit(limit as usize));
+}
+
+#[wasm_bindgen]
+pub fn r_GetHistoryLength() -> jsint {
+	return withProgram(|program| program.History.len() as jsint);
+}
+
+// returns 0 if the processor has not faulted, otherwise the code of the fault
+#[wasm_bindgen]
+pub fn r_GetLastFault() -> jsint {
+	return withProgram(|program| GetLastFault(program));
+}
+
+// the address of the instruction that faulted
+#[wasm_bindgen]
+pub fn r_GetLastFaultAddress() -> jsint {
+	return withProgram(|program| program.Processor.fault_address as jsint);
+}
+
+// the opcode or memory address that caused the last fault
+#[wasm_bindgen]
+pub fn r_GetLastFaultDetail() -> jsint {
+	return withProgram(|program| GetLastFaultDetail(program));
+}
+
+#[wasm_bindgen]
+pub fn r_EnableBreakpoints() {
+	withProgram(|program| program.DoBreakpoints = true);
+}
+
+#[wasm_bindgen]
+pub fn r_DisableBreakpoints() {
+	withProgram(|program| program.DoBreakpoints = false);
+}
+
+#[wasm_bindgen]
+pub fn r_GetPageSize() -> jsint {
+	return permissions::PAGE_SIZE as jsint;
+}
+
+// the number of mapped pages, addresses from pages * page size on are unmapped
+#[wasm_bindgen]
+pub fn r_SetMemoryPages(pages: u32) {
+	withProgram(|program| program.Processor.memory.set_page_count(pages));
+}
+
+// [mapped pages, allocated pages]
+#[wasm_bindgen]
+pub fn r_GetMemoryPages() -> Vec<u32> {
+	return withProgram(|program| vec![program.Processor.memory.page_count(), program.Processor.memory.allocated_pages()]);
+}
+
+// 0 to read unmapped addresses as 0, 1 to fault
+#[wasm_bindgen]
+pub fn r_SetUnmappedAccess(mode: u32) -> bool {
+	return withProgram(|program| match UnmappedAccess::from_code(mode) {
+		Some(mode) => {
+			program.Processor.memory.set_unmapped_access(mode);
+			true
+		},
+		None => false,
+	});
+}
+
+#[wasm_bindgen]
+pub fn r_string() -> String {
+	"hello".into()
+}
+
+#[wasm_bindgen]
+pub fn r_GetWasmMemoryLocation(location: jsint) -> jsint {
+	return withProgram(|program| program.Processor._get_pointer(location as u32));
+}
+
+// with no call to return from, this steps one instruction and says why it stopped
+pub(crate) fn StepOut(program: &mut Program) -> (u32, RunReason) {
+	let depth = program.Processor.frames.len();
+	if depth == 0 {
+		return StepN(1, program);
+	}
+	return program.run_until(None, |p| p.frames.len() < depth);
+}
+
+pub(crate) fn StepN(n: u32, program: &mut Program) -> (u32, RunReason) {
+	if n == 0 {
+		return (0, RunReason::Reached);
+	}
+	let mut remaining = n;
+	return program.run_until(None, |_| {
+		remaining -= 1;
+		return remaining == 0;
+	});
+}
+
+pub(crate) fn RunTo(address: location, program: &mut Program) -> (u32, RunReason) {
+	return program.run_until(None, |p| p.next == address);
+}
+
+pub(crate) fn GetProcessorStatus(program: &Program) -> jsint {
+	return match program.Processor.status {
+		ProcessorStatus::Paused => 0,
+		ProcessorStatus::Halted => 1,
+		ProcessorStatus::NotStarted => 2,
+		ProcessorStatus::Running => 3,
+		ProcessorStatus::Empty => 4,
+		ProcessorStatus::Faulted => 5,
+	}
+}
+
+pub(crate) fn GetCallStack(program: &Program) -> Vec<u32> {
+	let mut frames = Vec::new();
+	for frame in program.Processor.frames.iter() {
+		frames.push(frame.call_site);
+		frames.push(frame.target);
+		frames.push(frame.return_address);
+		frames.push(frame.stack_pointer);
+	}
+	return frames;
+}
+
+pub(crate) fn AddWatchpoint(start: location, end: location, kind: u32, program: &mut Program) -> u32 {
+	return match WatchKind::from_code(kind) {
+		Some(kind) => program.Processor.add_watchpoint(start, end, kind),
+		None => 0,
+	};
+}
+
+pub(crate) fn GetLastWatchHit(program: &Program) -> Vec<u32> {
+	return match program.Processor.watch_hit {
+		Some(hit) => vec![hit.watchpoint, hit.address, hit.access.code(), hit.old, hit.new, hit.instruction],
+		None => Vec::new(),
+	};
+}
+
+pub(crate) fn SetPermissions(start: location, end: location, bits: u32, program: &mut Program) {
+	let (start, end) = (start.min(end), start.max(end));
+	program.Processor.permissions.set(start / permissions::PAGE_SIZE, end / permissions::PAGE_SIZE, bits);
+	program.Processor.decode_cache.invalidate_all();
+}
+
+// turning checks off releases the quarantine outside of any step, which history can not undo
+pub(crate) fn SetHeapChecks(enabled: bool, program: &mut Program) {
+	if !enabled && program.Processor.heap.checks_enabled() {
+		program.History.clear();
+	}
+	program.Processor.set_heap_checks(enabled);
+}
+
+pub(crate) fn GetHeapAllocations(program: &Program) -> Vec<u32> {
+	let mut words = Vec::new();
+	for (address, size) in program.Processor.heap.allocations() {
+		words.push(address);
+		words.push(size);
+	}
+	return words;
+}
+
+pub(crate) fn GetCoreState(program: &Program, core: u32) -> Vec<u32> {
+	let parked = match program.Cores.get(core as usize) {
+		Some(parked) => parked,
+		None => return Vec::new(),
+	};
+	let p = &program.Processor;
+	if core == p.core_id {
+		return vec![p.next, p.bus, p.stack_pointer, p.supervisor as u32, parked.halted as u32];
+	}
+	return vec![parked.next(), parked.bus(), parked.stack_pointer(), parked.supervisor() as u32, parked.halted as u32];
+}
+
+pub(crate) fn GetMmuState(program: &Program) -> Vec<u32> {
+	let p = &program.Processor;
+	return vec![
+		p.supervisor as u32,
+		p.mmu.paging as u32,
+		p.mmu.page_table_base,
+		p.mmu.page_table_length,
+		p.mmu.tlb_hits,
+		p.mmu.tlb_misses,
+	];
+}
+
+pub(crate) fn GetDeviceMappings(program: &Program) -> Vec<u32> {
+	let mut words = Vec::new();
+	for (id, start, end) in program.Processor.devices.mappings() {
+		words.push(id);
+		words.push(start);
+		words.push(end);
+	}
+	return words;
+}
+
+pub(crate) fn GetLastFault(program: &Program) -> jsint {
+	return match program.Processor.fault {
+		Some(fault) => fault.code(),
+		None => 0,
+	}
+}
+
+pub(crate) fn GetLastFaultDetail(program: &Program) -> jsint {
+	return match program.Processor.fault {
+		Some(fault) => fault.detail() as jsint,
+		None => 0,
+	}
+}
+
+pub(crate) fn GetPage(n: u32, program: &mut Program) -> js_sys::Uint32Array {
+	let page: &[storage] = match program.Processor.memory.page_mut(n) {
+		Some(page) => page,
+		None => &[],
+	};
+	unsafe { js_sys::Uint32Array::view(page) }
+}
+
+pub(crate) fn SetBreakpoint(point: u32, program: &mut Program) {
+	program.Breakpoints.entry(point).or_default();
+}
+
+pub(crate) fn RemoveBreakpoint(point: u32, program: &mut Program) {
+	program.Breakpoints.remove(&point);
+}
+
+pub(crate) fn GetIsBreakpoint(point: u32, program: &mut Program) -> bool {
+	return program.Breakpoints.contains_key(&point);
+}
+
+pub(crate) fn SetBreakpointCondition(point: u32, condition: &str, program: &mut Program) -> Result<(), ConditionError> {
+	let condition = match condition.trim() {
+		"" => None,
+		source => Some(Condition::parse(source)?),
+	};
+	program.Breakpoints.entry(point).or_default().condition = condition;
+	return Ok(());
+}
+
+pub(crate) fn SetBreakpointIgnoreCount(point: u32, count: u32, program: &mut Program) -> bool {
+	return match program.Breakpoints.get_mut(&point) {
+		Some(breakpoint) => {
+			breakpoint.ignore_count = count;
+			true
+		},
+		None => false,
+	};
+}
+
+pub(crate) fn GetBreakpointHitCount(point: u32, program: &Program) -> u32 {
+	return program.Breakpoints.get(&point).map_or(0, |breakpoint| breakpoint.hit_count);
+}
+
+pub(crate) fn SetLogpoint(point: u32, message: &str, program: &mut Program) -> Result<(), ConditionError> {
+	let message = match message {
+		"" => None,
+		source => Some(Template::parse(source)?),
+	};
+	program.Breakpoints.entry(point).or_default().log_message = message;
+	return Ok(());
+}
+
+pub(crate) fn Continue(program: &mut Program) {
+	run(program);
+}
+
+pub(crate) fn Pause(program: &mut Program) {
+	match program.Processor.status {
+		ProcessorStatus::Running | ProcessorStatus::NotStarted => {
+			program.Processor.status = ProcessorStatus::Paused;
+		},
+		_ => {},
+	}
+}
+
+pub(crate) fn StepOver(program: &mut Program) {
+	let p = &program.Processor;
+	let resumable = p.status == ProcessorStatus::Paused || p.status == ProcessorStatus::NotStarted;
+	// with paging on, the instruction is at the physical address next maps to
+	let opcode = p.translate_quietly(p.next).and_then(|address| Opcode::from_code(p._peek_memory_loc(address)));
+	if resumable && opcode == Some(Opcode::Call) {
+		// run until the call has returned, or the callee stops on its own
+		let depth = p.frames.len();
+		let return_address = p.next.wrapping_add(2);
+		program.run_until(None, |p| p.frames.len() <= depth && p.next == return_address);
+		return;
+	}
+	StepInto(program);
+}
+
+pub(crate) fn StepInto(program: &mut Program) {
+	program.BreakpointStop = None;
+	program.Processor.decode_cache.invalidate_all();
+	program.Processor.mmu.flush();
+	let status = program.Processor.status;
+	if status != ProcessorStatus::Paused && status != ProcessorStatus::NotStarted {
+		return;
+	}
+	if step(program, false) {
+		cores::schedule(program);
+	}
+	if program.Processor.status == ProcessorStatus::NotStarted {
+		program.Processor.status = ProcessorStatus::Paused;
+	}
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::{storage, location};
+
+// the reasons the processor can stop abnormally.
+// a fault stops the machine the same way a halt does,
+// but it is recorded so the debugger can show what went wrong.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+	// the opcode at the faulting address is not part of the instruction set
+	InvalidOpcode(storage),
+	// a write to an address that is not inside any allocated memory block
+	MemoryOutOfBounds(location),
+	// integer division (or remainder) with a zero divisor
+	DivideByZero,
+	// a jump to an address that is not inside any allocated memory block.
+	// there is no misaligned jump: addresses count whole words and instructions
+	// can start at any of them
+	JumpOutOfBounds(location),
+	// execution ran past the end of allocated memory
+	InstructionOutOfBounds(location),
+	// a push or call with a full stack
+	StackOverflow(location),
+	// a pop or return with an empty stack
+	StackUnderflow(location),
+	// a data read from a page without the read permission
+	ReadViolation(location),
+	// a write to a page without the write permission
+	WriteViolation(location),
+	// an instruction on a page without the execute permission
+	ExecuteViolation(location),
+	// freeing a heap allocation that was already freed. only detected with heap checks on
+	DoubleFree(location),
+	// freeing an address that is not the start of a heap allocation
+	InvalidFree(location),
+	// a load or store to a freed heap allocation, with heap checks on
+	UseAfterFree(location),
+	// a page fault with paging on and no trap handler for it, or one taken while entering
+	// the handler. the detail is the virtual address
+	PageFault(location),
+	// a supervisor only instruction in user mode, the detail is the opcode
+	PrivilegeViolation(storage),
+	// a trap other than a page fault with no handler for it, the detail is the cause
+	NoTrapHandler(u32),
+}
+
+impl Fault {
+	// numeric code used by the r_ exports, 0 is reserved for "no fault"
+	pub fn code(&self) -> i32 {
+		return match self {
+			Fault::InvalidOpcode(_) => 1,
+			Fault::MemoryOutOfBounds(_) => 2,
+			Fault::DivideByZero => 3,
+			Fault::JumpOutOfBounds(_) => 4,
+			Fault::InstructionOutOfBounds(_) => 5,
+			Fault::StackOverflow(_) => 6,
+			Fault::StackUnderflow(_) => 7,
+			Fault::ReadViolation(_) => 8,
+			Fault::WriteViolation(_) => 9,
+			Fault::ExecuteViolation(_) => 10,
+			Fault::DoubleFree(_) => 11,
+			Fault::InvalidFree(_) => 12,
+			Fault::UseAfterFree(_) => 13,
+			Fault::PageFault(_) => 14,
+			Fault::PrivilegeViolation(_) => 15,
+			Fault::NoTrapHandler(_) => 16,
+		};
+	}
+
+	// the inverse of code() and detail()
+	pub fn from_code(code: i32, detail: storage) -> Option<Fault> {
+		return match code {
+			1 => Some(Fault::InvalidOpcode(detail)),
+			2 => Some(Fault::MemoryOutOfBounds(detail)),
+			3 => Some(Fault::DivideByZero),
+			4 => Some(Fault::JumpOutOfBounds(detail)),
+			5 => Some(Fault::InstructionOutOfBounds(detail)),
+			6 => Some(Fault::StackOverflow(detail)),
+			7 => Some(Fault::StackUnderflow(detail)),
+			8 => Some(Fault::ReadViolation(detail)),
+			9 => Some(Fault::WriteViolation(detail)),
+			10 => Some(Fault::ExecuteViolation(detail)),
+			11 => Some(Fault::DoubleFree(detail)),
+			12 => Some(Fault::InvalidFree(detail)),
+			13 => Some(Fault::UseAfterFree(detail)),
+			14 => Some(Fault::PageFault(detail)),
+			15 => Some(Fault::PrivilegeViolation(detail)),
+			16 => Some(Fault::NoTrapHandler(detail)),
+			_ => None,
+		};
+	}
+
+	// the opcode or address that caused the fault, if there is one
+	pub fn detail(&self) -> storage {
+		return match *self {
+			Fault::InvalidOpcode(op) => op,
+			Fault::MemoryOutOfBounds(address) => address,
+			Fault::DivideByZero => 0,
+			Fault::JumpOutOfBounds(address) => address,
+			Fault::InstructionOutOfBounds(address) => address,
+			Fault::StackOverflow(sp) => sp,
+			Fault::StackUnderflow(sp) => sp,
+			Fault::ReadViolation(address) => address,
+			Fault::WriteViolation(address) => address,
+			Fault::ExecuteViolation(address) => address,
+			Fault::DoubleFree(address) => address,
+			Fault::InvalidFree(address) => address,
+			Fault::UseAfterFree(address) => address,
+			Fault::PageFault(address) => address,
+			Fault::PrivilegeViolation(op) => op,
+			Fault::NoTrapHandler(cause) => cause,
+		};
+	}
+}
+
+impl fmt::Display for Fault {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return match *self {
+			Fault::InvalidOpcode(op) => write!(f, "invalid opcode {}", op),
+			Fault::MemoryOutOfBounds(address) => write!(f, "memory access out of bounds at {}", address),
+			Fault::DivideByZero => write!(f, "divide by zero"),
+			Fault::JumpOutOfBounds(address) => write!(f, "jump out of bounds to {}", address),
+			Fault::InstructionOutOfBounds(address) => write!(f, "instruction fetch out of bounds at {}", address),
+			Fault::StackOverflow(sp) => write!(f, "stack overflow with stack pointer {}", sp),
+			Fault::StackUnderflow(sp) => write!(f, "stack underflow with stack pointer {}", sp),
+			Fault::ReadViolation(address) => write!(f, "read from {} without the read permission", address),
+			Fault::WriteViolation(address) => write!(f, "write to {} without the write permission", address),
+			Fault::ExecuteViolation(address) => write!(f, "instruction at {} without the execute permission", address),
+			Fault::DoubleFree(address) => write!(f, "heap allocation at {} freed twice", address),
+			Fault::InvalidFree(address) => write!(f, "free of {}, which is not a heap allocation", address),
+			Fault::UseAfterFree(address) => write!(f, "access to {} after it was freed", address),
+			Fault::PageFault(address) => write!(f, "page fault at virtual address {} with no handler", address),
+			Fault::PrivilegeViolation(op) => write!(f, "opcode {} in user mode", op),
+			Fault::NoTrapHandler(cause) => write!(f, "trap with cause {} and no handler", cause),
+		};
+	}
+}
@@ -0,0 +1,384 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{location, Processor, Fault, MAX_PAGES};
+use crate::permissions::PAGE_SIZE;
+use crate::snapshot::{Reader, Writer, SnapshotError};
+
+// small allocations are rounded up to one of these sizes, in words. each page used for
+// small allocations is split into slots of a single size class
+pub const SIZE_CLASSES: [u32; 8] = [4, 8, 16, 32, 64, 128, 256, 512];
+// with checks on, this many freed allocations are kept out of reuse
+const QUARANTINE_LENGTH: usize = 64;
+
+// the allocator behind opcodes 43 and 44. it takes whole pages past the end of memory,
+// the same way opcode 20 does, so the heap and blocks mapped by the program never overlap.
+// allocations bigger than the largest size class get pages of their own, and those pages
+// are released when they are freed.
+// the contents of a new allocation are whatever the memory held, they are not cleared.
+//
+// with checks on, freed allocations are quarantined for a while: loads and stores to them
+// fault with UseAfterFree, and freeing one again faults with DoubleFree
+#[derive(Default)]
+pub(crate) struct Heap {
+	checks: bool,
+	// live allocations and their size in words, after rounding
+	allocated: BTreeMap<location, u32>,
+	// free slots for each size class, the last one is used first
+	free_slots: Vec<Vec<location>>,
+	// runs of free pages, first page to page count
+	free_pages: BTreeMap<u32, u32>,
+	// freed allocations that are not reused yet, oldest first. only with checks
+	quarantine: VecDeque<(location, u32)>,
+	quarantined: BTreeMap<location, u32>,
+	// what the current step changed, oldest first. only while recording for history
+	recording: bool,
+	changes: Vec<HeapChange>,
+}
+
+// one change to the allocator and what undoing it needs. a step that allocates or frees
+// makes a few of these, so history does not have to copy the whole heap
+pub(crate) enum HeapChange {
+	Allocated(location),
+	Freed(location, u32),
+	// slots pushed onto a size class
+	SlotsAdded(usize, u32),
+	SlotTaken(usize, location),
+	// the free page run starting at the page, before the change
+	FreePages(u32, Option<u32>),
+	Quarantined,
+	// the oldest allocation left quarantine
+	Unquarantined(location, u32),
+}
+
+// whether size words from address fit below the end of the last page
+fn in_memory(address: location, size: u32) -> bool {
+	return address as u64 + size as u64 <= MAX_PAGES as u64 * PAGE_SIZE as u64;
+}
+
+fn size_class(size: u32) -> Option<usize> {
+	return SIZE_CLASSES.iter().position(|&class| class >= size);
+}
+
+impl Heap {
+	pub(crate) fn new() -> Heap {
+		Heap {
+			free_slots: vec![Vec::new(); SIZE_CLASSES.len()],
+			..Heap::default()
+		}
+	}
+
+	pub(crate) fn checks_enabled(&self) -> bool {
+		return self.checks;
+	}
+
+	// (address, size) for each live allocation, in address order
+	pub(crate) fn allocations(&self) -> Vec<(location, u32)> {
+		return self.allocated.iter().map(|(&address, &size)| (address, size)).collect();
+	}
+
+	// true if the address is inside a quarantined allocation
+	pub(crate) fn is_quarantined(&self, address: location) -> bool {
+		return match self.quarantined.range(..=address).next_back() {
+			Some((&start, &size)) => address - start < size,
+			None => false,
+		};
+	}
+
+	fn log(&mut self, change: HeapChange) {
+		if self.recording {
+			self.changes.push(change);
+		}
+	}
+
+	// for history. recording lasts until end_step
+	pub(crate) fn record(&mut self) {
+		self.recording = true;
+	}
+
+	// the changes since record, oldest first
+	pub(crate) fn changes(&mut self) -> std::vec::Drain<'_, HeapChange> {
+		return self.changes.drain(..);
+	}
+
+	pub(crate) fn end_step(&mut self) {
+		self.recording = false;
+		self.changes.clear();
+	}
+
+	// changes have to be undone newest first
+	pub(crate) fn undo(&mut self, change: HeapChange) {
+		match change {
+			HeapChange::Allocated(address) => {
+				self.allocated.remove(&address);
+			},
+			HeapChange::Freed(address, size) => {
+				self.allocated.insert(address, size);
+			},
+			HeapChange::SlotsAdded(class, count) => {
+				let slots = &mut self.free_slots[class];
+				slots.truncate(slots.len().saturating_sub(count as usize));
+			},
+			HeapChange::SlotTaken(class, address) => self.free_slots[class].push(address),
+			HeapChange::FreePages(first, run) => {
+				match run {
+					Some(run) => self.free_pages.insert(first, run),
+					None => self.free_pages.remove(&first),
+				};
+			},
+			HeapChange::Quarantined => {
+				if let Some((address, _)) = self.quarantine.pop_back() {
+					self.quarantined.remove(&address);
+				}
+			},
+			HeapChange::Unquarantined(address, size) => {
+				self.quarantine.push_front((address, size));
+				self.quarantined.insert(address, size);
+			},
+		}
+	}
+
+	fn set_free_pages(&mut self, first: u32, run: Option<u32>) {
+		let old = match run {
+			Some(run) => self.free_pages.insert(first, run),
+			None => self.free_pages.remove(&first),
+		};
+		self.log(HeapChange::FreePages(first, old));
+	}
+
+	// first fit
+	fn take_free_pages(&mut self, count: u32) -> Option<u32> {
+		let (&first, &run) = self.free_pages.iter().find(|&(_, &run)| run >= count)?;
+		self.set_free_pages(first, None);
+		if run > count {
+			self.set_free_pages(first + count, Some(run - count));
+		}
+		return Some(first);
+	}
+
+	// merged with the runs on either side
+	fn return_pages(&mut self, first: u32, count: u32) {
+		let mut first = first;
+		let mut count = count;
+		if let Some((&before, &run)) = self.free_pages.range(..first).next_back() {
+			if before + run == first {
+				self.set_free_pages(before, None);
+				first = before;
+				count += run;
+			}
+		}
+		if let Some(&run) = self.free_pages.get(&(first + count)) {
+			self.set_free_pages(first + count, None);
+			count += run;
+		}
+		self.set_free_pages(first, Some(count));
+	}
+
+	fn add_slot(&mut self, class: usize, address: location) {
+		self.free_slots[class].push(address);
+		self.log(HeapChange::SlotsAdded(class, 1));
+	}
+
+	fn take_slot(&mut self, class: usize) -> Option<location> {
+		let address = self.free_slots[class].pop()?;
+		self.log(HeapChange::SlotTaken(class, address));
+		return Some(address);
+	}
+
+	// layout, all u32 unless noted:
+	//	checks u8, allocation count, then address and size for each,
+	//	for each size class the slot count followed by the slots,
+	//	free page run count, then first page and page count for each,
+	//	quarantine length, then address and size for each, oldest first
+	pub(crate) fn save(&self, w: &mut Writer) {
+		w.u8(self.checks as u8);
+		w.u32(self.allocated.len() as u32);
+		for (&address, &size) in self.allocated.iter() {
+			w.u32(address);
+			w.u32(size);
+		}
+		for slots in self.free_slots.iter() {
+			w.u32(slots.len() as u32);
+			for &slot in slots.iter() {
+				w.u32(slot);
+			}
+		}
+		w.u32(self.free_pages.len() as u32);
+		for (&first, &count) in self.free_pages.iter() {
+			w.u32(first);
+			w.u32(count);
+		}
+		w.u32(self.quarantine.len() as u32);
+		for &(address, size) in self.quarantine.iter() {
+			w.u32(address);
+			w.u32(size);
+		}
+	}
+
+	pub(crate) fn load(r: &mut Reader) -> Result<Heap, SnapshotError> {
+		let mut heap = Heap::new();
+		heap.checks = r.bool()?;
+		for _ in 0..r.u32()? {
+			let address = r.u32()?;
+			let size = r.u32()?;
+			if !in_memory(address, size) {
+				return Err(SnapshotError::Invalid("heap allocation"));
+			}
+			heap.allocated.insert(address, size);
+		}
+		for slots in heap.free_slots.iter_mut() {
+			for _ in 0..r.u32()? {
+				slots.push(r.u32()?);
+			}
+		}
+		for _ in 0..r.u32()? {
+			let first = r.u32()?;
+			let count = r.u32()?;
+			if first as u64 + count as u64 > MAX_PAGES as u64 {
+				return Err(SnapshotError::Invalid("free pages"));
+			}
+			heap.free_pages.insert(first, count);
+		}
+		for _ in 0..r.u32()? {
+			let address = r.u32()?;
+			let size = r.u32()?;
+			if !in_memory(address, size) {
+				return Err(SnapshotError::Invalid("heap allocation"));
+			}
+			heap.quarantine.push_back((address, size));
+			heap.quarantined.insert(address, size);
+		}
+		return Ok(heap);
+	}
+}
+
+impl Processor {
+	// opcode 43. 0 if the size is 0 or there is no room left
+	pub(crate) fn allocate(&mut self, size: u32) -> location {
+		self.record_heap();
+		if size == 0 {
+			return 0;
+		}
+		let (address, rounded) = match size_class(size) {
+			Some(class) => {
+				if self.heap.free_slots[class].is_empty() {
+					let page = match self.take_heap_pages(1) {
+						Some(page) => page,
+						None => return 0,
+					};
+					// pushed backwards so the lowest slot is used first
+					let slot_size = SIZE_CLASSES[class];
+					for slot in (0..PAGE_SIZE / slot_size).rev() {
+						self.heap.free_slots[class].push(page * PAGE_SIZE + slot * slot_size);
+					}
+					self.heap.log(HeapChange::SlotsAdded(class, PAGE_SIZE / slot_size));
+				}
+				(self.heap.take_slot(class).unwrap(), SIZE_CLASSES[class])
+			},
+			None => {
+				let count = size.div_ceil(PAGE_SIZE);
+				match self.take_heap_pages(count) {
+					Some(page) => (page * PAGE_SIZE, count * PAGE_SIZE),
+					None => return 0,
+				}
+			},
+		};
+		self.heap.allocated.insert(address, rounded);
+		self.heap.log(HeapChange::Allocated(address));
+		return address;
+	}
+
+	// opcode 44. freeing 0 does nothing
+	pub(crate) fn free(&mut self, address: location) {
+		if address == 0 {
+			return;
+		}
+		self.record_heap();
+		let size = match self.heap.allocated.remove(&address) {
+			Some(size) => {
+				self.heap.log(HeapChange::Freed(address, size));
+				size
+			},
+			None if self.heap.quarantined.contains_key(&address) => {
+				self.raise(Fault::DoubleFree(address));
+				return;
+			},
+			None => {
+				self.raise(Fault::InvalidFree(address));
+				return;
+			},
+		};
+		if !self.heap.checks {
+			self.release_allocation(address, size);
+			return;
+		}
+		self.heap.quarantine.push_back((address, size));
+		self.heap.quarantined.insert(address, size);
+		self.heap.log(HeapChange::Quarantined);
+		if self.heap.quarantine.len() > QUARANTINE_LENGTH {
+			let (oldest, size) = self.heap.quarantine.pop_front().unwrap();
+			self.heap.quarantined.remove(&oldest);
+			self.heap.log(HeapChange::Unquarantined(oldest, size));
+			self.release_allocation(oldest, size);
+		}
+	}
+
+	// turning checks off releases everything in quarantine
+	pub(crate) fn set_heap_checks(&mut self, checks: bool) {
+		self.heap.checks = checks;
+		if !checks {
+			while let Some((address, size)) = self.heap.quarantine.pop_front() {
+				self.release_allocation(address, size);
+			}
+			self.heap.quarantined.clear();
+		}
+	}
+
+	// history gets the changes, see HeapChange
+	fn record_heap(&mut self) {
+		if self.perStepRecordWrites {
+			self.heap.record();
+		}
+	}
+
+	fn release_allocation(&mut self, address: location, size: u32) {
+		if let Some(class) = size_class(size) {
+			self.heap.add_slot(class, address);
+			return;
+		}
+		let first = address / PAGE_SIZE;
+		let count = size / PAGE_SIZE;
+		for page in first..first + count {
+			self.release_page(page);
+		}
+		self.heap.return_pages(first, count);
+	}
+
+	// the page reads as zeros and takes no space until it is written again.
+	// its words are kept for history so stepping back brings them back
+	fn release_page(&mut self, page: u32) {
+		if self.perStepRecordWrites {
+			if let Some(words) = self.memory.page(page) {
+				let start = page * PAGE_SIZE;
+				for (offset, &value) in words.iter().enumerate() {
+					if value != 0 {
+						self.perStepReleasedWords.push((start + offset as location, value));
+					}
+				}
+			}
+		}
+		self.memory.release_page(page);
+		self.decode_cache.invalidate_all();
+	}
+
+	// pages for the heap, mapping more past the end of memory if no free run is long enough.
+	// page 0 is never used, so 0 can mean no allocation
+	fn take_heap_pages(&mut self, count: u32) -> Option<u32> {
+		if let Some(first) = self.heap.take_free_pages(count) {
+			return Some(first);
+		}
+		let first = self.memory.page_count().max(1);
+		let end = first.checked_add(count).filter(|&end| end <= MAX_PAGES)?;
+		self.memory.set_page_count(end);
+		return Some(first);
+	}
+}
@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+
+use crate::{storage, location, Program, ProcessorStatus, ALU, CallFrame, Fault};
+use crate::cores;
+use crate::heap::HeapChange;
+
+// how many steps can be undone unless changed with r_SetHistoryLimit.
+// recording costs more than running the instructions, so it is off until a debugger asks
+pub const DEFAULT_HISTORY_LIMIT: usize = 0;
+
+// everything a single step can change, as it was before the step.
+// memory written by syscall hosts and the state of devices are not recorded
+pub struct UndoRecord {
+	// the core that ran the step. its halted flag was false, or it would not have run
+	core: u32,
+	next: location,
+	bus: storage,
+	status: ProcessorStatus,
+	alu: ALU,
+	fault: Option<Fault>,
+	fault_address: location,
+	stack_pointer: location,
+	// CALL pushes one frame and RET pops one, so the length and the top frame are enough
+	frames_len: usize,
+	top_frame: Option<CallFrame>,
+	memory_pages: u32,
+	interrupts_enabled: bool,
+	pending_interrupts: u32,
+	supervisor: bool,
+	// the MMU registers. the TLB is flushed instead
+	paging: bool,
+	page_table_base: location,
+	page_table_length: u32,
+	// the page opcode 42 changed and its old permissions
+	old_permissions: Option<(location, u32)>,
+	// how many entries of History::writes and History::heap_changes belong to this step
+	writes_len: usize,
+	heap_changes_len: usize,
+}
+
+// bounded, the oldest records are dropped first
+pub struct History {
+	records: VecDeque<UndoRecord>,
+	// old values of written memory for all the records, oldest first.
+	// kept in one place so recording a step does not allocate
+	writes: VecDeque<(location, storage)>,
+	// what opcodes 43 and 44 changed in the allocator, the same way
+	heap_changes: VecDeque<HeapChange>,
+	limit: usize,
+}
+
+impl History {
+	pub fn new() -> History {
+		History {
+			records: VecDeque::new(),
+			writes: VecDeque::new(),
+			heap_changes: VecDeque::new(),
+			limit: DEFAULT_HISTORY_LIMIT,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		return self.limit > 0;
+	}
+
+	pub fn len(&self) -> usize {
+		return self.records.len();
+	}
+
+	pub fn set_limit(&mut self, limit: usize) {
+		self.limit = limit;
+		while self.records.len() > limit {
+			self.drop_oldest();
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.records.clear();
+		self.writes.clear();
+		self.heap_changes.clear();
+	}
+
+	fn drop_oldest(&mut self) {
+		if let Some(record) = self.records.pop_front() {
+			self.writes.drain(..record.writes_len);
+			self.heap_changes.drain(..record.heap_changes_len);
+		}
+	}
+
+	// released are the words of pages released after the writes, restored before them
+	fn push(&mut self, mut record: UndoRecord, writes: &[(location, storage)], released: &[(location, storage)], heap_changes: impl Iterator<Item = HeapChange>) {
+		if self.records.len() >= self.limit {
+			self.drop_oldest();
+		}
+		record.writes_len = writes.len() + released.len();
+		self.writes.extend(writes);
+		self.writes.extend(released);
+		let before = self.heap_changes.len();
+		self.heap_changes.extend(heap_changes);
+		record.heap_changes_len = self.heap_changes.len() - before;
+		self.records.push_back(record);
+	}
+}
+
+// call before the processor steps. memory writes are collected by the processor
+pub(crate) fn begin_step(program: &mut Program) -> Option<UndoRecord> {
+	if !program.History.is_enabled() {
+		return None;
+	}
+	let p = &mut program.Processor;
+	p.perStepRecordWrites = true;
+	return Some(UndoRecord {
+		core: p.core_id,
+		next: p.next,
+		bus: p.bus,
+		status: p.status,
+		alu: p.alu.clone(),
+		fault: p.fault,
+		fault_address: p.fault_address,
+		stack_pointer: p.stack_pointer,
+		frames_len: p.frames.len(),
+		top_frame: p.frames.last().cloned(),
+		memory_pages: p.memory.page_count(),
+		interrupts_enabled: p.interrupts_enabled,
+		pending_interrupts: p.pending_interrupts,
+		supervisor: p.supervisor,
+		paging: p.mmu.paging,
+		page_table_base: p.mmu.page_table_base,
+		page_table_length: p.mmu.page_table_length,
+		old_permissions: None,
+		writes_len: 0,
+		heap_changes_len: 0,
+	});
+}
+
+// writes are the old values collected by the processor during the step
+pub(crate) fn end_step(program: &mut Program, record: Option<UndoRecord>, writes: &[(location, storage)], released: &[(location, storage)]) {
+	if let Some(mut record) = record {
+		record.old_permissions = program.Processor.perStepOldPermissions;
+		program.History.push(record, writes, released, program.Processor.heap.changes());
+	}
+}
+
+// undoes the most recent step. returns false if there is nothing to undo
+pub(crate) fn step_back(program: &mut Program) -> bool {
+	let record = match program.History.records.pop_back() {
+		Some(record) => record,
+		None => return false,
+	};
+	program.BreakpointStop = None;
+
+	cores::switch_to(program, record.core);
+	program.Cores[record.core as usize].halted = false;
+	let p = &mut program.Processor;
+	// newest first, so an address written twice ends up with its oldest value
+	for _ in 0..record.writes_len {
+		if let Some((location, value)) = program.History.writes.pop_back() {
+			p._poke_memory_loc(location, value);
+		}
+	}
+	if p.memory.page_count() != record.memory_pages {
+		p.memory.set_page_count(record.memory_pages);
+		p.decode_cache.invalidate_all();
+	}
+	p.frames.truncate(record.frames_len);
+	if p.frames.len() < record.frames_len {
+		p.frames.extend(record.top_frame);
+	}
+
+	p.next = record.next;
+	p.bus = record.bus;
+	p.alu = record.alu;
+	p.fault = record.fault;
+	p.fault_address = record.fault_address;
+	p.stack_pointer = record.stack_pointer;
+	p.interrupts_enabled = record.interrupts_enabled;
+	p.pending_interrupts = record.pending_interrupts;
+	p.supervisor = record.supervisor;
+	p.mmu.paging = record.paging;
+	p.mmu.page_table_base = record.page_table_base;
+	p.mmu.page_table_length = record.page_table_length;
+	p.mmu.flush();
+	if let Some((address, bits)) = record.old_permissions {
+		p.set_page_permissions(address, bits);
+	}
+	// newest first as well
+	for _ in 0..record.heap_changes_len {
+		if let Some(change) = program.History.heap_changes.pop_back() {
+			p.heap.undo(change);
+		}
+	}
+	p.perStepFault = None;
+	p.perStepTrap = None;
+	p.status = match record.status {
+		ProcessorStatus::NotStarted => ProcessorStatus::NotStarted,
+		_ => ProcessorStatus::Paused,
+	};
+	return true;
+}
+
+// steps back until the instruction pointer is on a breakpoint whose condition holds,
+// or history runs out. logpoints and hit counts are ignored.
+// returns the number of steps undone
+pub(crate) fn reverse_continue(program: &mut Program) -> u32 {
+	let mut steps = 0;
+	while step_back(program) {
+		steps += 1;
+		if !program.DoBreakpoints {
+			continue;
+		}
+		if let Some(breakpoint) = program.Breakpoints.get(&program.Processor.next) {
+			if !breakpoint.is_logpoint() && breakpoint.condition_holds(&program.Processor) {
+				program.BreakpointStop = Some(program.Processor.next);
+				break;
+			}
+		}
+	}
+	return steps;
+}
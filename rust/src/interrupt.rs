@@ -0,0 +1,83 @@
+use crate::{location, Processor, CallFrame, Fault, STACK_BASE, STACK_SIZE};
+use crate::trap::{TRAP_INTERRUPT, TRAP_VECTORS};
+
+// number of interrupt lines, line n is bit n of Processor::pending_interrupts
+pub const INTERRUPT_LINES: u32 = 32;
+// the vector table, one handler address per line, just below the stack.
+// a line whose entry is 0 has no handler, it stays pending until one is set
+pub const INTERRUPT_TABLE: location = STACK_BASE - STACK_SIZE - INTERRUPT_LINES;
+
+impl Processor {
+	// false if the line does not exist
+	pub(crate) fn raise_interrupt(&mut self, line: u32) -> bool {
+		if line >= INTERRUPT_LINES {
+			return false;
+		}
+		self.pending_interrupts |= 1 << line;
+		return true;
+	}
+
+	// the line that would be taken before the next instruction, lowest line first,
+	// with the address of its handler
+	pub(crate) fn next_interrupt(&self) -> Option<(u32, location)> {
+		if !self.interrupts_enabled || self.pending_interrupts == 0 {
+			return None;
+		}
+		return (0..INTERRUPT_LINES)
+			.filter(|line| self.pending_interrupts & (1 << line) != 0)
+			.map(|line| (line, self.interrupt_handler(line)))
+			.find(|&(_, handler)| handler != 0);
+	}
+
+	// in user mode every line goes to the interrupt trap vector
+	fn interrupt_handler(&self, line: u32) -> location {
+		if !self.supervisor {
+			return self._peek_memory_loc(TRAP_VECTORS + TRAP_INTERRUPT);
+		}
+		return self._peek_memory_loc(INTERRUPT_TABLE + line);
+	}
+
+	// like a call from the interrupted instruction: the address of the instruction is
+	// pushed, interrupts are disabled and the handler runs. opcode 41 returns
+	// in user mode it is a trap instead, with the line as the detail
+	pub(crate) fn enter_interrupt(&mut self, line: u32, handler: location) {
+		if !self.supervisor {
+			self.pending_interrupts &= !(1 << line);
+			self.perStepTrap = Some((TRAP_INTERRUPT, line));
+			return;
+		}
+		if !self.mmu.paging && !self._is_allocated(handler) {
+			self.raise(Fault::JumpOutOfBounds(handler));
+			return;
+		}
+		let interrupted = self.next;
+		if !self.push(interrupted) {
+			return;
+		}
+		self.pending_interrupts &= !(1 << line);
+		self.interrupts_enabled = false;
+		self.frames.push(CallFrame {
+			call_site: interrupted,
+			target: handler,
+			return_address: interrupted,
+			stack_pointer: self.stack_pointer,
+		});
+		self.jump(handler);
+		self.dontMoveParamPointer();
+	}
+
+	// opcode 41
+	pub(crate) fn return_from_interrupt(&mut self) {
+		if let Some(return_address) = self.pop() {
+			self.frames.pop();
+			self.interrupts_enabled = true;
+			self.jump(return_address);
+			self.dontMoveParamPointer();
+		}
+	}
+
+	// called once for every executed instruction
+	pub(crate) fn tick_devices(&mut self) {
+		self.pending_interrupts |= self.devices.tick();
+	}
+}
@@ -0,0 +1,184 @@
+use std::fmt::Write as _;
+
+use crate::storage;
+
+// what an operand word means, for tooling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandKind {
+	// an absolute memory address
+	Address,
+	// a signed offset from the address of the instruction
+	Offset,
+	// a plain value
+	Immediate,
+	// a syscall code, see syscall.rs
+	SyscallCode,
+	// 0 ==, 1 !=, 2 >, 3 >=, 4 <, 5 <=
+	CompareMode,
+}
+
+impl OperandKind {
+	pub fn name(&self) -> &'static str {
+		return match self {
+			OperandKind::Address => "address",
+			OperandKind::Offset => "offset",
+			OperandKind::Immediate => "immediate",
+			OperandKind::SyscallCode => "syscall",
+			OperandKind::CompareMode => "compare_mode",
+		};
+	}
+}
+
+#[derive(Debug)]
+pub struct OpcodeInfo {
+	pub opcode: Opcode,
+	pub code: storage,
+	pub mnemonic: &'static str,
+	pub operands: &'static [OperandKind],
+	pub description: &'static str,
+}
+
+// defines Opcode and INSTRUCTION_SET from the same list, so they cannot disagree.
+// entries must stay in the same order as the enum, info() indexes by discriminant
+macro_rules! instruction_set {
+	($($name:ident = $code:expr, $mnemonic:expr, [$($operand:ident),*], $description:expr;)*) => {
+		#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+		pub enum Opcode {
+			$($name,)*
+		}
+
+		pub static INSTRUCTION_SET: &[OpcodeInfo] = &[
+			$(OpcodeInfo {
+				opcode: Opcode::$name,
+				code: $code,
+				mnemonic: $mnemonic,
+				operands: &[$(OperandKind::$operand),*],
+				description: $description,
+			},)*
+		];
+
+		impl Opcode {
+			pub fn from_code(code: storage) -> Option<Opcode> {
+				return match code {
+					$($code => Some(Opcode::$name),)*
+					_ => None,
+				};
+			}
+		}
+	};
+}
+
+instruction_set! {
+	Noop = 0, "Noop", [], "does nothing";
+	LoadValueAtAddressIntoBus = 1, "LoadValueAtAddressIntoBus", [Address], "memory[parameter] -> bus";
+	SaveValueInBusToLocation = 2, "SaveValueInBusToLocation", [Address], "bus -> memory[parameter]";
+	LoadWithConstantOffsetFromHereToBus = 3, "LoadWithConstantOffsetFromHereToBus", [Offset], "memory[current + parameter] -> bus";
+	SaveFromBusWithConstantOffsetFromHere = 4, "SaveFromBusWithConstantOffsetFromHere", [Offset], "bus -> memory[current + parameter]";
+	LoadWithConstantOffsetToBus = 5, "LoadWithConstantOffsetToBus", [Address, Immediate], "memory[parameter 1 + parameter 2] -> bus";
+	SaveFromBusWithConstantOffset = 6, "SaveFromBusWithConstantOffset", [Address, Immediate], "bus -> memory[parameter 1 + parameter 2]";
+	LoadWithBusAsConstantOffsetFromHere = 7, "LoadWithBusAsConstantOffsetFromHere", [], "memory[current + bus] -> bus";
+	SaveWithBusAsConstantOffsetFromHere = 8, "SaveWithBusAsConstantOffsetFromHere", [], "bus -> memory[current + bus]";
+	AluDoAdd = 9, "AluDoAdd", [], "push the bus onto the ALU, then a + b -> hi";
+	AluNegate = 10, "AluNegate", [], "push the bus onto the ALU, then -a -> hi";
+	AluMultiply = 11, "AluMultiply", [], "push the bus onto the ALU, then a * b -> hi and lo";
+	AluDivide = 12, "AluDivide", [], "push the bus onto the ALU, then a / b -> lo and a % b -> hi. faults on an integer divide by zero";
+	JumpToBus = 13, "JumpToBus", [], "goto the address on the bus";
+	BranchTo = 14, "BranchTo", [Address], "goto parameter if the last comparison was true";
+	LinkIfBranched = 15, "LinkIfBranched", [], "current -> bus if the last comparison was true";
+	AluHiToBus = 16, "AluHiToBus", [], "ALU hi -> bus";
+	AluLoToBus = 17, "AluLoToBus", [], "ALU lo -> bus";
+	AluToInt = 18, "AluToInt", [], "switch the ALU to int mode, keeping the bits of its values";
+	AluToFloat = 19, "AluToFloat", [], "switch the ALU to float mode, keeping the bits of its values";
+	NewBlock = 20, "NewBlock", [], "map another 32K words past the end of memory";
+	Syscall = 21, "Syscall", [SyscallCode], "syscall with parameter as the code and the bus as the argument, the result -> bus";
+	Halt = 22, "Halt", [], "stop the processor";
+	Pause = 23, "Pause", [], "pause the processor, it can be resumed";
+	LoadImmediateToBus = 24, "LoadImmediateToBus", [Immediate], "parameter -> bus";
+	AluPushFromBus = 25, "AluPushFromBus", [], "push the bus onto the ALU";
+	LoadWithVariableOffsetToBus = 26, "LoadWithVariableOffsetToBus", [Address, Address], "memory[parameter 1 + memory[parameter 2]] -> bus";
+	SaveFromBusWithVariableOffset = 27, "SaveFromBusWithVariableOffset", [Address, Address], "bus -> memory[parameter 1 + memory[parameter 2]]";
+	GetCurrentPosition = 28, "GetCurrentPosition", [], "current -> bus";
+	AluDoComparisonWithMode = 29, "AluDoComparisonWithMode", [CompareMode], "compare b with a using the mode, the result is used by BranchTo and LinkIfBranched";
+	Or = 30, "Or", [], "push the bus onto the ALU, then a | b -> a";
+	And = 31, "And", [], "push the bus onto the ALU, then a & b -> a";
+	ShiftLeft = 32, "ShiftLeft", [], "push the bus onto the ALU, then a << b -> a";
+	ShiftRight = 33, "ShiftRight", [], "push the bus onto the ALU, then a >> b -> a";
+	Push = 34, "Push", [], "push the bus onto the stack";
+	Pop = 35, "Pop", [], "pop the stack -> bus";
+	Call = 36, "Call", [Address], "push current + 2 onto the stack, goto parameter";
+	Return = 37, "Return", [], "pop the stack, goto the popped address";
+	GetStackPointer = 38, "GetStackPointer", [], "stack pointer -> bus";
+	EnableInterrupts = 39, "EnableInterrupts", [], "let pending interrupts be taken, from the next instruction on";
+	DisableInterrupts = 40, "DisableInterrupts", [], "stop interrupts from being taken, raised ones stay pending";
+	ReturnFromInterrupt = 41, "ReturnFromInterrupt", [], "pop the stack, goto the popped address and enable interrupts";
+	SetPermissions = 42, "SetPermissions", [Address, Immediate], "set the permissions of the page holding parameter 1 to parameter 2. 1 read, 2 write, 4 execute";
+	Allocate = 43, "Allocate", [], "allocate bus words on the heap, the address -> bus. 0 if there is no room";
+	Free = 44, "Free", [], "free the heap allocation at the bus address";
+	SetPageTable = 45, "SetPageTable", [Immediate], "the page table is at the physical address in the bus and has parameter entries";
+	EnablePaging = 46, "EnablePaging", [], "translate addresses through the page table, from the next instruction on";
+	DisablePaging = 47, "DisablePaging", [], "use physical addresses, from the next instruction on";
+	ReturnFromTrap = 48, "ReturnFromTrap", [], "pop the mode and then the address from the stack, restore the mode and goto the address";
+	Trap = 49, "Trap", [Immediate], "enter supervisor mode at the system call trap vector with the parameter as the detail, returning to current + 2";
+	GetCoreId = 50, "GetCoreId", [], "the id of the core running the instruction -> bus";
+	DslHalt = 100, "DslHalt", [], "stop the processor, as emitted by the DSL compiler";
+}
+
+impl Opcode {
+	pub fn info(&self) -> &'static OpcodeInfo {
+		return &INSTRUCTION_SET[*self as usize];
+	}
+
+	pub fn code(&self) -> storage {
+		return self.info().code;
+	}
+
+	pub fn mnemonic(&self) -> &'static str {
+		return self.info().mnemonic;
+	}
+
+	// number of parameter words after the opcode
+	pub fn operand_count(&self) -> u32 {
+		return self.info().operands.len() as u32;
+	}
+
+	// faults with PrivilegeViolation in user mode
+	pub fn is_privileged(&self) -> bool {
+		return matches!(self,
+			Opcode::NewBlock | Opcode::Syscall
+			| Opcode::EnableInterrupts | Opcode::DisableInterrupts | Opcode::ReturnFromInterrupt
+			| Opcode::SetPermissions
+			| Opcode::SetPageTable | Opcode::EnablePaging | Opcode::DisablePaging | Opcode::ReturnFromTrap);
+	}
+}
+
+// a JSON string literal
+pub fn json_string(s: &str) -> String {
+	let mut out = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			c if (c as u32) < 0x20 => {
+				let _ = write!(out, "\\u{:04x}", c as u32);
+			},
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	return out;
+}
+
+// the instruction set as a JSON array, for generating tooling on the JS side
+pub fn to_json() -> String {
+	let mut out = String::from("[");
+	for (i, info) in INSTRUCTION_SET.iter().enumerate() {
+		if i > 0 {
+			out.push(',');
+		}
+		let operands: Vec<String> = info.operands.iter().map(|kind| json_string(kind.name())).collect();
+		let _ = write!(out, "{{\"code\":{},\"mnemonic\":{},\"operands\":[{}],\"privileged\":{},\"description\":{}}}",
+			info.code, json_string(info.mnemonic), operands.join(","), info.opcode.is_privileged(), json_string(info.description));
+	}
+	out.push(']');
+	return out;
+}
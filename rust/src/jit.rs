@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{storage, location, Program, Processor, Opcode, end_recorded_step};
+use crate::history::{self, UndoRecord};
+#[cfg(target_arch = "wasm32")]
+use crate::jit_wasm;
+use crate::permissions::{EXECUTE, PAGE_SIZE};
+
+// how many times a block is entered by the interpreter before it is compiled
+pub const HOT_THRESHOLD: u32 = 16;
+// the longest block, in instructions
+const MAX_BLOCK_LENGTH: usize = 64;
+
+// one instruction with its parameters baked in
+type Op = Box<dyn Fn(&mut Processor) + Send + Sync>;
+
+pub(crate) struct CompiledInstruction {
+	pub(crate) address: location,
+	pub(crate) run: Op,
+	// words including the opcode, the instruction pointer moves by this much unless it jumped
+	pub(crate) size: u32,
+	// a store can overwrite the block it is in
+	writes: bool,
+}
+
+// straight line code from start up to and including the first jump, branch, call, return
+// or permission change.
+// syscalls, halts and pauses are never compiled, a block ends before them.
+// a block also stops early when an interrupt is raised, so the interpreter can take it
+pub(crate) struct Block {
+	pub(crate) start: location,
+	// address of the last instruction. a block never crosses a page,
+	// this is only kept so permission checks do not depend on that
+	last: location,
+	// the words the block was compiled from, it only runs while memory still holds them
+	words: Vec<storage>,
+	pub(crate) instructions: Vec<CompiledInstruction>,
+	// the block as a WebAssembly function, when it could be made
+	#[cfg(target_arch = "wasm32")]
+	function: Option<jit_wasm::Function>,
+}
+
+impl Block {
+	pub(crate) fn len(&self) -> usize {
+		return self.instructions.len();
+	}
+
+	fn contains(&self, address: location) -> bool {
+		return address >= self.start && ((address - self.start) as usize) < self.words.len();
+	}
+
+	// false once anything changed the code, the block is compiled again later
+	fn is_current(&self, p: &Processor) -> bool {
+		let offset = (self.start % PAGE_SIZE) as usize;
+		return match p.memory.page(self.start / PAGE_SIZE) {
+			Some(page) => page[offset..offset + self.words.len()] == self.words[..],
+			// a page that was never written holds noops
+			None => p.memory.is_mapped(self.start) && self.words.iter().all(|&word| word == 0),
+		};
+	}
+}
+
+enum Entry {
+	// times the interpreter has started a step here
+	Counting(u32),
+	Compiled(Arc<Block>),
+	// the first instruction is one the interpreter always runs
+	Interpreted,
+}
+
+// the compiled tier. a hot block is turned into a list of closures with the parameters
+// baked in, which run without fetching, decoding or dispatching each instruction.
+// built for WebAssembly, the block is also compiled into a WebAssembly function of its own,
+// see jit_wasm.rs, and the closures only run the instructions that function does not inline.
+// everywhere else the closures are the compiled tier.
+//
+// off by default. blocks record history like the interpreter does, but only run while
+// tracing and watchpoints are off, and never over an enabled breakpoint.
+// anything else is left to the interpreter
+pub(crate) struct Jit {
+	enabled: bool,
+	entries: HashMap<location, Entry>,
+}
+
+impl Jit {
+	pub(crate) fn new() -> Jit {
+		Jit {
+			enabled: false,
+			entries: HashMap::new(),
+		}
+	}
+
+	pub(crate) fn is_enabled(&self) -> bool {
+		return self.enabled;
+	}
+
+	pub(crate) fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+		self.clear();
+	}
+
+	pub(crate) fn clear(&mut self) {
+		self.entries.clear();
+	}
+
+	// number of compiled blocks
+	pub(crate) fn block_count(&self) -> usize {
+		return self.entries.values().filter(|entry| matches!(entry, Entry::Compiled(_))).count();
+	}
+}
+
+// runs the block at the instruction pointer, compiling it if it has become hot.
+// returns None if the caller should interpret a step instead, otherwise the number of
+// instructions run and whether reached stopped the block
+pub(crate) fn run_block(program: &mut Program, budget: Option<u32>, mut reached: Option<&mut (dyn FnMut(&Processor) -> bool + '_)>) -> Option<(u32, bool)> {
+	if program.Tracer.is_enabled() || !program.Processor.watchpoints.is_empty() {
+		return None;
+	}
+	// the interpreter takes interrupts, translates addresses with paging on,
+	// checks privileges in user mode and switches cores after every step
+	let p = &program.Processor;
+	if p.next_interrupt().is_some() || p.mmu.paging || !p.supervisor || program.Cores.len() > 1 {
+		return None;
+	}
+
+	let start = program.Processor.next;
+	let block = match program.Jit.entries.get_mut(&start) {
+		Some(Entry::Compiled(block)) => block.clone(),
+		Some(Entry::Interpreted) => return None,
+		Some(Entry::Counting(count)) => {
+			*count += 1;
+			if *count < HOT_THRESHOLD {
+				return None;
+			}
+			let entry = match compile(&program.Processor, start) {
+				Some(block) => Entry::Compiled(Arc::new(block)),
+				None => Entry::Interpreted,
+			};
+			program.Jit.entries.insert(start, entry);
+			return None;
+		},
+		None => {
+			program.Jit.entries.insert(start, Entry::Counting(1));
+			return None;
+		},
+	};
+
+	if program.DoBreakpoints && program.Breakpoints.keys().any(|&address| block.contains(address)) {
+		return None;
+	}
+	let p = &mut program.Processor;
+	// the interpreter raises the fault
+	if !p.can_access(block.start, EXECUTE) || !p.can_access(block.last, EXECUTE) {
+		return None;
+	}
+	if !block.is_current(p) {
+		program.Jit.entries.insert(start, Entry::Counting(0));
+		return None;
+	}
+
+	p.watch_triggered = false;
+	#[cfg(target_arch = "wasm32")]
+	if let Some(function) = &block.function {
+		return Some(jit_wasm::run(program, &block, function, budget.unwrap_or(u32::MAX), reached));
+	}
+	let limit = budget.map_or(block.len(), |budget| block.len().min(budget as usize));
+	let mut executed = 0;
+	for index in 0..limit {
+		let record = history::begin_step(program);
+		(block.instructions[index].run)(&mut program.Processor);
+		executed += 1;
+		if let Some(stopped) = end_instruction(program, &block, index, record, reached.as_deref_mut()) {
+			return Some((executed, stopped));
+		}
+	}
+	return Some((executed, false));
+}
+
+// the same bookkeeping as the end of Processor::step, after an instruction of the block ran.
+// returns Some if the block stops after it, with whether reached stopped it
+pub(crate) fn end_instruction(program: &mut Program, block: &Block, index: usize, record: Option<UndoRecord>, reached: Option<&mut (dyn FnMut(&Processor) -> bool + '_)>) -> Option<bool> {
+	let instruction = &block.instructions[index];
+	let p = &mut program.Processor;
+	p.tick_devices();
+	let fault = p.perStepFault.take();
+	match fault {
+		Some(fault) => {
+			p.perStepDontMove = false;
+			p.fault(instruction.address, fault);
+		},
+		None if p.perStepDontMove => p.perStepDontMove = false,
+		None => p.next = p.next.wrapping_add(instruction.size),
+	}
+	if record.is_some() {
+		end_recorded_step(program, record, None);
+	}
+	if fault.is_some() {
+		return Some(false);
+	}
+
+	let p = &program.Processor;
+	if reached.is_some_and(|reached| reached(p)) {
+		return Some(true);
+	}
+	if instruction.writes && !block.is_current(p) {
+		// the block wrote over its own code, the rest of it is stale
+		return Some(false);
+	}
+	if p.next_interrupt().is_some() {
+		return Some(false);
+	}
+	return None;
+}
+
+fn compile(p: &Processor, start: location) -> Option<Block> {
+	if !p._is_allocated(start) {
+		return None;
+	}
+	// a block never crosses into the next page. in u64, the end of the last page is 2^32
+	let page_end = (start / PAGE_SIZE) as u64 * PAGE_SIZE as u64 + PAGE_SIZE as u64;
+	let mut address = start;
+	let mut last = start;
+	let mut instructions = Vec::new();
+	let mut targets = Vec::new();
+	while instructions.len() < MAX_BLOCK_LENGTH {
+		let opcode = match Opcode::from_code(p._peek_memory_loc(address)) {
+			Some(opcode) => opcode,
+			None => break,
+		};
+		let size = 1 + opcode.operand_count();
+		if address as u64 + size as u64 > page_end {
+			break;
+		}
+		let params = [
+			p._peek_memory_loc(address.wrapping_add(1)),
+			p._peek_memory_loc(address.wrapping_add(2)),
+		];
+		let run = match compile_instruction(opcode, params) {
+			Some(run) => run,
+			None => break,
+		};
+		last = address;
+		targets.push(store_target(opcode, address, params[0]));
+		instructions.push(CompiledInstruction {
+			address,
+			run,
+			size,
+			writes: writes_memory(opcode),
+		});
+		// the last instruction can end exactly at 2^32
+		address = address.wrapping_add(size);
+		if ends_block(opcode) || address == 0 {
+			break;
+		}
+	}
+
+	if instructions.is_empty() {
+		return None;
+	}
+	let length = instructions.iter().map(|instruction| instruction.size).sum::<u32>();
+	let words: Vec<storage> = (0..length).map(|offset| p._peek_memory_loc(start + offset)).collect();
+	// a store to a fixed address outside the block cannot overwrite it
+	for (instruction, target) in instructions.iter_mut().zip(targets) {
+		if target.is_some_and(|target| target.wrapping_sub(start) >= length) {
+			instruction.writes = false;
+		}
+	}
+	return Some(Block {
+		start,
+		last,
+		#[cfg(target_arch = "wasm32")]
+		function: jit_wasm::compile(&words, &instructions),
+		words,
+		instructions,
+	});
+}
+
+// each closure does what the matching arm of Processor::step does
+fn compile_instruction(opcode: Opcode, params: [storage; 2]) -> Option<Op> {
+	let [a, b] = params;
+	let run: Op = match opcode {
+		Opcode::Noop => Box::new(|_| {}),
+		Opcode::LoadValueAtAddressIntoBus => Box::new(move |p| p.load_location(a)),
+		Opcode::SaveValueInBusToLocation => Box::new(move |p| p.set_location(a)),
+		Opcode::LoadWithConstantOffsetFromHereToBus => Box::new(move |p| p.load_location_relative(a)),
+		Opcode::SaveFromBusWithConstantOffsetFromHere => Box::new(move |p| p.set_location_relative(a)),
+		Opcode::LoadWithConstantOffsetToBus => Box::new(move |p| p.load_with_constant_offset_to_bus(a, b)),
+		Opcode::SaveFromBusWithConstantOffset => Box::new(move |p| p.save_with_constant_offset_from_bus(a, b)),
+		Opcode::LoadWithBusAsConstantOffsetFromHere => Box::new(|p| p.load_location_relative_with_bus()),
+		Opcode::SaveWithBusAsConstantOffsetFromHere => Box::new(|p| p.save_location_relative_with_bus()),
+		Opcode::AluDoAdd => Box::new(|p| p.add()),
+		Opcode::AluNegate => Box::new(|p| p.negate()),
+		Opcode::AluMultiply => Box::new(|p| p.multiply()),
+		Opcode::AluDivide => Box::new(|p| p.divide()),
+		Opcode::JumpToBus => Box::new(|p| {
+			let b = p.bus;
+			p.jump(b);
+			p.dontMoveParamPointer();
+		}),
+		Opcode::BranchTo => Box::new(move |p| {
+			if p.alu.compare_result {
+				p.dontMoveParamPointer();
+				p.jump(a);
+			}
+		}),
+		Opcode::LinkIfBranched => Box::new(|p| {
+			if p.alu.compare_result {
+				p.bus = p.next;
+			}
+		}),
+		Opcode::AluHiToBus => Box::new(|p| p.get_hi()),
+		Opcode::AluLoToBus => Box::new(|p| p.get_lo()),
+		Opcode::AluToInt => Box::new(|p| p.alu_to_int()),
+		Opcode::AluToFloat => Box::new(|p| p.alu_to_float()),
+		Opcode::NewBlock => Box::new(|p| p.grow_memory()),
+		Opcode::LoadImmediateToBus => Box::new(move |p| p.load_immediate(a)),
+		Opcode::AluPushFromBus => Box::new(|p| p.push_to_alu()),
+		Opcode::LoadWithVariableOffsetToBus => Box::new(move |p| p.load_with_variable_offset_to_bus(a, b)),
+		Opcode::SaveFromBusWithVariableOffset => Box::new(move |p| p.save_with_variable_offset_from_bus(a, b)),
+		Opcode::GetCurrentPosition => Box::new(|p| p.bus = p.next),
+		Opcode::AluDoComparisonWithMode => Box::new(move |p| p.alu_compare_with_mode(a)),
+		Opcode::Or => Box::new(|p| p.or()),
+		Opcode::And => Box::new(|p| p.and()),
+		Opcode::ShiftLeft => Box::new(|p| p.shift_left()),
+		Opcode::ShiftRight => Box::new(|p| p.shift_right()),
+		Opcode::Push => Box::new(|p| {
+			let value = p.bus;
+			p.push(value);
+		}),
+		Opcode::Pop => Box::new(|p| {
+			if let Some(value) = p.pop() {
+				p.bus = value;
+			}
+		}),
+		Opcode::Call => Box::new(move |p| p.call(a)),
+		Opcode::Return => Box::new(|p| p.ret()),
+		Opcode::GetStackPointer => Box::new(|p| p.bus = p.stack_pointer),
+		Opcode::GetCoreId => Box::new(|p| p.bus = p.core_id),
+		Opcode::EnableInterrupts => Box::new(|p| p.interrupts_enabled = true),
+		Opcode::DisableInterrupts => Box::new(|p| p.interrupts_enabled = false),
+		Opcode::ReturnFromInterrupt => Box::new(|p| p.return_from_interrupt()),
+		Opcode::SetPermissions => Box::new(move |p| p.set_permissions_instruction(a, b)),
+		Opcode::Allocate => Box::new(|p| {
+			let size = p.bus;
+			p.bus = p.allocate(size);
+		}),
+		Opcode::Free => Box::new(|p| {
+			let address = p.bus;
+			p.free(address);
+		}),
+		// these stop the processor or leave it, the interpreter runs them
+		Opcode::Syscall | Opcode::Halt | Opcode::Pause | Opcode::DslHalt => return None,
+		// these change the mode or how addresses are translated
+		Opcode::SetPageTable | Opcode::EnablePaging | Opcode::DisablePaging | Opcode::ReturnFromTrap | Opcode::Trap => return None,
+	};
+	return Some(run);
+}
+
+fn ends_block(opcode: Opcode) -> bool {
+	return matches!(opcode,
+		Opcode::JumpToBus | Opcode::BranchTo | Opcode::Call | Opcode::Return
+		| Opcode::ReturnFromInterrupt | Opcode::SetPermissions);
+}
+
+// the address a store writes to, when it does not depend on the registers
+fn store_target(opcode: Opcode, address: location, param: storage) -> Option<location> {
+	return match opcode {
+		Opcode::SaveValueInBusToLocation => Some(param),
+		Opcode::SaveFromBusWithConstantOffsetFromHere => Some(address.wrapping_add(param)),
+		_ => None,
+	};
+}
+
+fn writes_memory(opcode: Opcode) -> bool {
+	return matches!(opcode,
+		Opcode::SaveValueInBusToLocation
+		| Opcode::SaveFromBusWithConstantOffsetFromHere
+		| Opcode::SaveFromBusWithConstantOffset
+		| Opcode::SaveWithBusAsConstantOffsetFromHere
+		| Opcode::SaveFromBusWithVariableOffset
+		| Opcode::Push
+		| Opcode::Call
+		// can release pages
+		| Opcode::Free);
+}
@@ -0,0 +1,492 @@
+use std::cell::RefCell;
+use std::mem::offset_of;
+
+use js_sys::{Object, Reflect, Uint8Array, WebAssembly};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{storage, location, Program, Processor, Opcode, ALU, ALUMode, ALUCompareMode};
+use crate::history::{self, UndoRecord};
+use crate::jit::{self, Block, CompiledInstruction};
+
+// the WebAssembly tier of the JIT, only built for WebAssembly.
+// every block gets a module of its own that imports the memory of this one and exports
+// one function, block(run, processor, limit, fast), which returns how many instructions ran.
+// it is put in the function table of this module and called through it like a function
+// pointer, so running a block does not go through JS.
+// the function works on the Processor in place. instructions that only touch registers
+// are inlined, anything that reads or writes memory, jumps or can fault calls back into
+// rust_asm_jit_step, which runs the closure of the instruction and does the bookkeeping
+// of the interpreter after it.
+// an inlined instruction only has to move the instruction pointer, unless history,
+// devices or a stepping command need to see every step. then it calls rust_asm_jit_after
+// for the bookkeeping too, and the block is as slow as its closures.
+// in that fast case a block that jumps back to its own start keeps running inside block,
+// which is what makes hot loops fast
+
+// the Processor fields the inlined instructions use
+const BUS: u32 = offset_of!(Processor, bus) as u32;
+const NEXT: u32 = offset_of!(Processor, next) as u32;
+const STACK_POINTER: u32 = offset_of!(Processor, stack_pointer) as u32;
+const CORE_ID: u32 = offset_of!(Processor, core_id) as u32;
+const ALU_FIELDS: u32 = offset_of!(Processor, alu) as u32;
+const VALUE_A: u32 = ALU_FIELDS + offset_of!(ALU, value_a_int) as u32;
+const VALUE_B: u32 = ALU_FIELDS + offset_of!(ALU, value_b_int) as u32;
+const HI: u32 = ALU_FIELDS + offset_of!(ALU, hi) as u32;
+const LO: u32 = ALU_FIELDS + offset_of!(ALU, lo) as u32;
+const COMPARE_RESULT: u32 = ALU_FIELDS + offset_of!(ALU, compare_result) as u32;
+const COMPARE_MODE: u32 = ALU_FIELDS + offset_of!(ALU, compare_mode) as u32;
+const MODE: u32 = ALU_FIELDS + offset_of!(ALU, mode) as u32;
+
+// the parameters of block, then its local
+const RUN: u32 = 0;
+const PROCESSOR: u32 = 1;
+const LIMIT: u32 = 2;
+const FAST: u32 = 3;
+// how many instructions ran before this pass through the block
+const BASE: u32 = 4;
+// the imported functions
+const STEP: u32 = 0;
+const AFTER: u32 = 1;
+
+// the WebAssembly opcodes used
+const UNREACHABLE: u8 = 0x00;
+const LOOP: u8 = 0x03;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0b;
+const BR: u8 = 0x0c;
+const RETURN: u8 = 0x0f;
+const CALL: u8 = 0x10;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const I32_LOAD: u8 = 0x28;
+const I32_LOAD8_U: u8 = 0x2d;
+const I32_STORE: u8 = 0x36;
+const I32_STORE8: u8 = 0x3a;
+const I32_CONST: u8 = 0x41;
+const I32_EQ: u8 = 0x46;
+const I32_NE: u8 = 0x47;
+const I32_LT_S: u8 = 0x48;
+const I32_GT_S: u8 = 0x4a;
+const I32_LE_S: u8 = 0x4c;
+const I32_GE_S: u8 = 0x4e;
+const I32_ADD: u8 = 0x6a;
+const I32_SUB: u8 = 0x6b;
+const I32_MUL: u8 = 0x6c;
+const I32_AND: u8 = 0x71;
+const I32_OR: u8 = 0x72;
+const I32_SHL: u8 = 0x74;
+const I32_SHR_S: u8 = 0x75;
+const EMPTY_TYPE: u8 = 0x40;
+const I32: u8 = 0x7f;
+
+type BlockFunction = extern "C" fn(u32, u32, u32, u32) -> u32;
+
+thread_local! {
+	// slots in the function table left by dropped blocks, the table cannot shrink
+	static FREE_SLOTS: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+}
+
+fn function_table() -> WebAssembly::Table {
+	return wasm_bindgen::function_table().unchecked_into();
+}
+
+// the slot of a compiled block function in the function table, cleared with the block
+pub(crate) struct Function(u32);
+
+impl Drop for Function {
+	fn drop(&mut self) {
+		let _ = function_table().set_raw(self.0, &JsValue::NULL);
+		FREE_SLOTS.with(|slots| slots.borrow_mut().push(self.0));
+	}
+}
+
+// what the imported functions need while a block runs. block gets a pointer to it
+struct BlockRun<'a, 'r> {
+	program: *mut Program,
+	block: &'a Block,
+	reached: Option<&'a mut (dyn FnMut(&Processor) -> bool + 'r)>,
+	limit: u32,
+	fast: bool,
+	stopped: bool,
+	// the history record of the instruction running now
+	record: Option<UndoRecord>,
+}
+
+// None if the module could not be made, then the block runs its closures
+pub(crate) fn compile(words: &[storage], instructions: &[CompiledInstruction]) -> Option<Function> {
+	let bytes = Uint8Array::from(&module(words, instructions)[..]);
+	let module = WebAssembly::Module::new(&bytes.into()).ok()?;
+	let exports = wasm_bindgen::exports();
+	let env = Object::new();
+	Reflect::set(&env, &"memory".into(), &wasm_bindgen::memory()).ok()?;
+	Reflect::set(&env, &"step".into(), &Reflect::get(&exports, &"rust_asm_jit_step".into()).ok()?).ok()?;
+	Reflect::set(&env, &"after".into(), &Reflect::get(&exports, &"rust_asm_jit_after".into()).ok()?).ok()?;
+	let imports = Object::new();
+	Reflect::set(&imports, &"env".into(), &env).ok()?;
+	let instance = WebAssembly::Instance::new(&module, &imports).ok()?;
+	let function = Reflect::get(&instance.exports(), &"block".into()).ok()?.dyn_into::<js_sys::Function>().ok()?;
+
+	let table = function_table();
+	let slot = match FREE_SLOTS.with(|slots| slots.borrow_mut().pop()) {
+		Some(slot) => slot,
+		None => table.grow(1).ok()?,
+	};
+	if table.set(slot, &function).is_err() {
+		FREE_SLOTS.with(|slots| slots.borrow_mut().push(slot));
+		return None;
+	}
+	return Some(Function(slot));
+}
+
+// runs up to limit instructions, returns the same as jit::run_block. a block that jumps
+// back to its start can run more than once
+pub(crate) fn run(program: &mut Program, block: &Block, function: &Function, limit: u32, reached: Option<&mut (dyn FnMut(&Processor) -> bool + '_)>) -> (u32, bool) {
+	let fast = reached.is_none() && !program.History.is_enabled() && program.Processor.devices.is_empty();
+	let record = history::begin_step(program);
+	let program: *mut Program = program;
+	let processor = unsafe { std::ptr::addr_of_mut!((*program).Processor) };
+	let mut run = BlockRun {
+		program,
+		block,
+		reached,
+		limit,
+		fast,
+		stopped: false,
+		record,
+	};
+	// a function pointer in WebAssembly is its index in the function table
+	let block_function: BlockFunction = unsafe { std::mem::transmute(function.0 as usize) };
+	let executed = block_function(&mut run as *mut BlockRun as u32, processor as u32, limit, fast as u32);
+	return (executed, run.stopped);
+}
+
+// runs instruction index of the block, for the instructions block does not inline.
+// executed counts it too. returns 1 if the block stops after it
+#[no_mangle]
+pub extern "C" fn rust_asm_jit_step(run: u32, index: u32, executed: u32) -> u32 {
+	{
+		let run = unsafe { &mut *(run as *mut BlockRun) };
+		let program = unsafe { &mut *run.program };
+		(run.block.instructions[index as usize].run)(&mut program.Processor);
+	}
+	return rust_asm_jit_after(run, index, executed);
+}
+
+// the bookkeeping after instruction index of the block ran. returns 1 if the block stops there.
+// after the last one, 0 means the block starts over
+#[no_mangle]
+pub extern "C" fn rust_asm_jit_after(run: u32, index: u32, executed: u32) -> u32 {
+	let run = unsafe { &mut *(run as *mut BlockRun) };
+	let program = unsafe { &mut *run.program };
+	let record = run.record.take();
+	if let Some(stopped) = jit::end_instruction(program, run.block, index as usize, record, run.reached.as_deref_mut()) {
+		run.stopped = stopped;
+		return 1;
+	}
+	if executed >= run.limit {
+		return 1;
+	}
+	// only without the bookkeeping run_block does before each block
+	let last = index as usize + 1 == run.block.len();
+	if last && !(run.fast && program.Processor.next == run.block.start) {
+		return 1;
+	}
+	run.record = history::begin_step(program);
+	return 0;
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u32) {
+	loop {
+		let byte = (value & 0x7f) as u8;
+		value >>= 7;
+		if value == 0 {
+			out.push(byte);
+			return;
+		}
+		out.push(byte | 0x80);
+	}
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i32) {
+	loop {
+		let byte = (value & 0x7f) as u8;
+		value >>= 7;
+		if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+			out.push(byte);
+			return;
+		}
+		out.push(byte | 0x80);
+	}
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+	unsigned(out, name.len() as u32);
+	out.extend_from_slice(name.as_bytes());
+}
+
+fn section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+	out.push(id);
+	unsigned(out, content.len() as u32);
+	out.extend_from_slice(content);
+}
+
+// the body of block, one instruction at a time
+struct Body {
+	code: Vec<u8>,
+}
+
+impl Body {
+	fn op(&mut self, op: u8) {
+		self.code.push(op);
+	}
+
+	fn constant(&mut self, value: u32) {
+		self.op(I32_CONST);
+		signed(&mut self.code, value as i32);
+	}
+
+	fn local(&mut self, local: u32) {
+		self.op(LOCAL_GET);
+		unsigned(&mut self.code, local);
+	}
+
+	fn memory(&mut self, op: u8, align: u32, offset: u32) {
+		self.op(op);
+		unsigned(&mut self.code, align);
+		unsigned(&mut self.code, offset);
+	}
+
+	// the field at offset in the Processor onto the stack
+	fn load(&mut self, offset: u32) {
+		self.local(PROCESSOR);
+		self.memory(I32_LOAD, 2, offset);
+	}
+
+	fn load_byte(&mut self, offset: u32) {
+		self.local(PROCESSOR);
+		self.memory(I32_LOAD8_U, 0, offset);
+	}
+
+	fn store(&mut self, offset: u32, value: impl FnOnce(&mut Body)) {
+		self.local(PROCESSOR);
+		value(self);
+		self.memory(I32_STORE, 2, offset);
+	}
+
+	fn store_byte(&mut self, offset: u32, value: impl FnOnce(&mut Body)) {
+		self.local(PROCESSOR);
+		value(self);
+		self.memory(I32_STORE8, 0, offset);
+	}
+
+	// applies op to the values at both offsets
+	fn binary(&mut self, first: u32, second: u32, op: u8) {
+		self.load(first);
+		self.load(second);
+		self.op(op);
+	}
+
+	// how many instructions ran once instruction index has
+	fn executed(&mut self, index: u32) {
+		self.local(BASE);
+		self.constant(index + 1);
+		self.op(I32_ADD);
+	}
+
+	// calls the imported function for instruction index, and returns from block if it says so
+	fn call(&mut self, function: u32, index: u32) {
+		self.local(RUN);
+		self.constant(index);
+		self.executed(index);
+		self.op(CALL);
+		unsigned(&mut self.code, function);
+		self.returns_if(index);
+	}
+
+	// returns from block after instruction index if the value on the stack is not 0
+	fn returns_if(&mut self, index: u32) {
+		self.op(IF);
+		self.op(EMPTY_TYPE);
+		self.executed(index);
+		self.op(RETURN);
+		self.op(END);
+	}
+
+	// the end of an inlined instruction. the instruction pointer is moved here when nothing
+	// else has to happen between instructions, and the block stops at the limit
+	fn end_inlined(&mut self, index: u32, next: location) {
+		self.local(FAST);
+		self.op(IF);
+		self.op(EMPTY_TYPE);
+		self.store(NEXT, |body| body.constant(next));
+		self.local(LIMIT);
+		self.executed(index);
+		self.op(I32_EQ);
+		self.returns_if(index);
+		self.op(ELSE);
+		self.call(AFTER, index);
+		self.op(END);
+	}
+
+	// the ALU in float mode is left to the closure
+	fn int_mode(&mut self, index: u32, inlined: impl FnOnce(&mut Body)) {
+		self.load_byte(MODE);
+		self.constant(ALUMode::int as u32);
+		self.op(I32_EQ);
+		self.op(IF);
+		self.op(EMPTY_TYPE);
+		inlined(self);
+		self.op(ELSE);
+		self.step(index);
+		self.op(END);
+	}
+
+	fn step(&mut self, index: u32) {
+		self.call(STEP, index);
+	}
+
+	// Processor::push_to_alu
+	fn push(&mut self) {
+		self.store(VALUE_B, |body| body.load(VALUE_A));
+		self.store(VALUE_A, |body| body.load(BUS));
+	}
+
+	// the same as the closure of the instruction, see jit::compile_instruction.
+	// false if the closure ran instead
+	fn instruction(&mut self, index: u32, address: location, opcode: Opcode, param: storage) -> bool {
+		match opcode {
+			Opcode::Noop => {},
+			Opcode::LoadImmediateToBus => self.store(BUS, |body| body.constant(param)),
+			Opcode::GetCurrentPosition => self.store(BUS, |body| body.constant(address)),
+			Opcode::GetStackPointer => self.store(BUS, |body| body.load(STACK_POINTER)),
+			Opcode::GetCoreId => self.store(BUS, |body| body.load(CORE_ID)),
+			Opcode::AluHiToBus => self.store(BUS, |body| body.load(HI)),
+			Opcode::AluLoToBus => self.store(BUS, |body| body.load(LO)),
+			Opcode::LinkIfBranched => {
+				self.load_byte(COMPARE_RESULT);
+				self.op(IF);
+				self.op(EMPTY_TYPE);
+				self.store(BUS, |body| body.constant(address));
+				self.op(END);
+			},
+			Opcode::AluPushFromBus => self.int_mode(index, |body| body.push()),
+			Opcode::AluDoAdd => self.int_mode(index, |body| {
+				body.push();
+				body.store(HI, |body| body.binary(VALUE_A, VALUE_B, I32_ADD));
+				body.store(LO, |body| body.constant(0));
+			}),
+			Opcode::AluNegate => self.int_mode(index, |body| {
+				body.push();
+				body.store(HI, |body| {
+					body.constant(0);
+					body.load(VALUE_A);
+					body.op(I32_SUB);
+				});
+				body.store(LO, |body| body.constant(0));
+			}),
+			// only the low word of the product is kept, as in ALU::multiply_int
+			Opcode::AluMultiply => self.int_mode(index, |body| {
+				body.push();
+				body.store(HI, |body| body.constant(0));
+				body.store(LO, |body| body.binary(VALUE_A, VALUE_B, I32_MUL));
+			}),
+			Opcode::Or | Opcode::And | Opcode::ShiftLeft | Opcode::ShiftRight => {
+				let op = match opcode {
+					Opcode::Or => I32_OR,
+					Opcode::And => I32_AND,
+					Opcode::ShiftLeft => I32_SHL,
+					_ => I32_SHR_S,
+				};
+				self.int_mode(index, |body| {
+					body.push();
+					body.store(VALUE_A, |body| body.binary(VALUE_A, VALUE_B, op));
+				});
+			},
+			// the modes of Processor::alu_compare_with_mode, anything else does nothing
+			Opcode::AluDoComparisonWithMode => {
+				let (mode, op) = match param {
+					0 => (ALUCompareMode::equal, I32_EQ),
+					1 => (ALUCompareMode::not_equal, I32_NE),
+					2 => (ALUCompareMode::greater_than, I32_GT_S),
+					3 => (ALUCompareMode::greater_than_or_equal, I32_GE_S),
+					4 => (ALUCompareMode::lesser_than, I32_LT_S),
+					5 => (ALUCompareMode::lesser_than_or_equal, I32_LE_S),
+					_ => return true,
+				};
+				self.int_mode(index, |body| {
+					body.store_byte(COMPARE_MODE, |body| body.constant(mode as u32));
+					body.store_byte(COMPARE_RESULT, |body| body.binary(VALUE_B, VALUE_A, op));
+				});
+			},
+			_ => {
+				self.step(index);
+				return false;
+			},
+		}
+		return true;
+	}
+}
+
+// the module for a block. words are the ones the block was compiled from
+fn module(words: &[storage], instructions: &[CompiledInstruction]) -> Vec<u8> {
+	// one i32 local, BASE
+	let mut body = Body { code: vec![1, 1, I32, LOOP, EMPTY_TYPE] };
+	let start = instructions[0].address;
+	let mut inlined = false;
+	for (index, instruction) in instructions.iter().enumerate() {
+		let index = index as u32;
+		let offset = instruction.address.wrapping_sub(start) as usize;
+		let param = words.get(offset + 1).copied().unwrap_or(0);
+		inlined = match Opcode::from_code(words[offset]) {
+			Some(opcode) => body.instruction(index, instruction.address, opcode, param),
+			None => {
+				body.step(index);
+				false
+			},
+		};
+		if inlined {
+			body.end_inlined(index, instruction.address.wrapping_add(instruction.size));
+		}
+	}
+	let last = instructions.len() as u32 - 1;
+	if inlined {
+		body.executed(last);
+		body.op(RETURN);
+	} else {
+		// rust_asm_jit_after let the block start over
+		body.executed(last);
+		body.op(LOCAL_SET);
+		unsigned(&mut body.code, BASE);
+		body.op(BR);
+		unsigned(&mut body.code, 0);
+	}
+	body.op(END);
+	body.op(UNREACHABLE);
+	body.op(END);
+
+	let mut out = b"\0asm\x01\0\0\0".to_vec();
+	// step and after are (run, index, executed) -> stop, block is (run, processor, limit, fast) -> executed
+	section(&mut out, 1, &[2, 0x60, 3, I32, I32, I32, 1, I32, 0x60, 4, I32, I32, I32, I32, 1, I32]);
+	let mut imports = vec![3];
+	name(&mut imports, "env");
+	name(&mut imports, "memory");
+	imports.extend_from_slice(&[2, 0, 0]);
+	name(&mut imports, "env");
+	name(&mut imports, "step");
+	imports.extend_from_slice(&[0, 0]);
+	name(&mut imports, "env");
+	name(&mut imports, "after");
+	imports.extend_from_slice(&[0, 0]);
+	section(&mut out, 2, &imports);
+	section(&mut out, 3, &[1, 1]);
+	let mut exports = vec![1];
+	name(&mut exports, "block");
+	exports.extend_from_slice(&[0, 2]);
+	section(&mut out, 7, &exports);
+	let mut code = vec![1];
+	unsigned(&mut code, body.code.len() as u32);
+	code.extend_from_slice(&body.code);
+	section(&mut out, 10, &code);
+	return out;
+}
@@ -2,13 +2,45 @@
 extern crate lazy_static;
 
 use wasm_bindgen::prelude::*;
-use std::ptr;
-use js_sys;
-use std::io::{BufRead, BufReader};
-use std::io::{self, Write};
-use std::sync::Mutex;
-use std::collections::{HashSet, HashMap};
-use std::os::raw::{c_double, c_float, c_int};
+use std::collections::HashMap;
+use std::os::raw::c_int;
+
+mod breakpoint;
+mod cores;
+mod decode;
+mod device;
+mod disasm;
+mod exports;
+mod fault;
+mod heap;
+mod history;
+mod interrupt;
+pub mod isa;
+mod jit;
+#[cfg(target_arch = "wasm32")]
+mod jit_wasm;
+mod memory;
+pub mod mmu;
+pub mod permissions;
+mod snapshot;
+pub mod syscall;
+mod trace;
+mod trap;
+mod watch;
+pub use breakpoint::{Breakpoint, Condition, ConditionError, Template};
+pub use device::{Device, MapError, SerialPort, Keyboard, Screen, Timer};
+pub use interrupt::{INTERRUPT_LINES, INTERRUPT_TABLE};
+pub use memory::{UnmappedAccess, MAX_PAGES};
+pub use heap::SIZE_CLASSES;
+pub use trap::{TRAP_VECTORS, TRAP_CAUSE, TRAP_ADDRESS, TRAP_PAGE_FAULT, TRAP_SYSTEM_CALL, TRAP_INTERRUPT};
+pub use disasm::{Instruction, mnemonic};
+pub use fault::Fault;
+pub use isa::{Opcode, OpcodeInfo, OperandKind, INSTRUCTION_SET};
+pub use snapshot::SnapshotError;
+pub use trace::TraceRecord;
+pub use watch::{WatchKind, WatchHit, Watchpoint};
+pub use exports::*;
+pub use syscall::{SyscallHost, SyscallMemory, JsSyscallHost, NativeSyscallHost, RecordingSyscallHost};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -31,42 +63,68 @@ fn set_panic_hook() {
 enum StopCode {
 	Pause,
 	Halt,
+	// the fault itself is kept on the processor
+	Fault,
 	None,
 }
 
-#[derive(PartialEq)]
+// why a run returned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunReason {
+	// the step budget ran out, the processor is still running
+	BudgetExhausted,
+	// the program paused itself
+	Paused,
+	Breakpoint,
+	Halted,
+	Faulted,
+	// nothing is loaded
+	Empty,
+	// a watchpoint paused the processor after the instruction that triggered it
+	Watchpoint,
+	// a step or run-to command got where it was going
+	Reached,
+}
+
+impl RunReason {
+	// numeric code used by the r_ exports
+	pub fn code(&self) -> jsint {
+		return match self {
+			RunReason::BudgetExhausted => 0,
+			RunReason::Paused => 1,
+			RunReason::Breakpoint => 2,
+			RunReason::Halted => 3,
+			RunReason::Faulted => 4,
+			RunReason::Empty => 5,
+			RunReason::Watchpoint => 6,
+			RunReason::Reached => 7,
+		};
+	}
+}
+
+#[derive(PartialEq, Clone, Copy)]
 enum ProcessorStatus {
 	Paused,
 	Halted,
 	NotStarted,
 	Running,
 	Empty,
+	Faulted,
 }
 
+// the words a machine starts with, and what opcode 20 adds
 const MEM_SIZE: usize = 1024 * 32;
 
-// lazy_static! {
-//     static ref MAIN_PROGRAM: Mutex<Program> = Mutex::new(Program::new());
-// }
-static mut MAIN_PROGRAM: Option<&mut Program> = None;
-
-fn getProgram() -> &mut Program {
-	unsafe {
-		match ptr::read(&MAIN_PROGRAM) {
-			Some(x) => x,
-			None => panic!(),
-		}
-	}
-}
-
-fn setProgram(T: &mut Program) {
-	unsafe {
-		ptr::replace(&mut MAIN_PROGRAM, Some(T));
-	}
-}
+// the default stack takes the top of the first memory block
+const STACK_SIZE: location = 1024;
+const STACK_BASE: location = MEM_SIZE as location;
 
+// lowercase like the primitives they stand for
+#[allow(non_camel_case_types)]
 type storage = u32;
+#[allow(non_camel_case_types)]
 type location = u32;
+#[allow(non_camel_case_types)]
 type jsint = c_int;
 
 #[wasm_bindgen]
@@ -74,229 +132,758 @@ extern {
 	fn js_syscall(code: jsint, param: jsint) -> jsint;
 }
 
+// an independent virtual machine.
+// each one owns its own processor, memory and breakpoints,
+// so several programs can be loaded and run side by side.
 #[wasm_bindgen]
-pub fn r_SetBreakpoint(n: jsint) {
-	let program = getProgram();
-	SetBreakpoint(n as u32, program);
+pub struct Machine {
+	program: Program,
 }
 
-#[wasm_bindgen]
-pub fn r_RemoveBreakpoint(n: jsint) {
-	let program = getProgram();
-	RemoveBreakpoint(n as u32, program);
+impl Default for Machine {
+	fn default() -> Machine {
+		return Machine::new();
+	}
 }
 
 #[wasm_bindgen]
-pub fn r_GetIsBreakpoint(n: jsint) -> bool {
-	let program = getProgram();
-	return GetIsBreakpoint(n as u32, program);
-}
+impl Machine {
+	#[wasm_bindgen(constructor)]
+	pub fn new() -> Machine {
+		Machine {
+			program: Program::new(),
+		}
+	}
 
-#[wasm_bindgen]
-pub fn r_GetBlock(n: usize) -> js_sys::Uint32Array {
-	let mem = getProgram().Processor.regions[n].memory;
-	unsafe { js_sys::Uint32Array::view(&mem) }
-} 
+	// loads the machine code at address 0, up to the end of mapped memory
+	pub fn initialize(&mut self, init: &[storage]) {
+		// the processor starts over as in a new machine, only what it was set up with is kept
+		let old = std::mem::replace(&mut self.program.Processor, Processor::new());
+		let processor = &mut self.program.Processor;
+		processor.syscall_host = old.syscall_host;
+		processor.devices = old.devices;
+		processor.watchpoints = old.watchpoints;
+		processor.next_watchpoint_id = old.next_watchpoint_id;
+		processor.decode_cache = old.decode_cache;
+		processor.decode_cache.invalidate_all();
+		processor.memory.set_page_count(old.memory.page_count());
+		processor.memory.set_unmapped_access(old.memory.unmapped_access());
+		self.program.Cores = vec![cores::Core::new(1, STACK_BASE)];
+		self.program.LastRunReason = RunReason::Empty;
+		self.program.BreakpointStop = None;
+
+		for (address, &value) in init.iter().enumerate() {
+			if !processor._poke_memory_loc(address as location, value) {
+				break;
+			}
+		}
 
-#[wasm_bindgen]
-pub fn r_Continue() {
-	let program = getProgram();
-	Continue(program);
-}
+		processor.status = ProcessorStatus::NotStarted;
+		self.program.History.clear();
+		self.program.Jit.clear();
+		self.program.BreakpointLog.clear();
+		self.program.Tracer.clear();
+		for breakpoint in self.program.Breakpoints.values_mut() {
+			breakpoint.hit_count = 0;
+		}
+	}
 
-#[wasm_bindgen]
-pub fn r_StepOver() {
-	let program = getProgram();
-	StepOver(program);
-}
+	pub fn set_breakpoint(&mut self, n: location) {
+		SetBreakpoint(n, &mut self.program);
+	}
 
-#[wasm_bindgen]
-pub fn r_Initialize(init: &[storage]) {
-	// create the new program
-	let mut prg = Program::new();
-	setProgram(&mut prg);
-	
+	pub fn remove_breakpoint(&mut self, n: location) {
+		RemoveBreakpoint(n, &mut self.program);
+	}
 
-	// initialize the processor
-	let mut processor = prg.Processor;
-	processor.add_region(MemoryBlock::new());
-	let x = processor.regions[0].memory;
-	x[..init.len()].copy_from_slice(&init);
+	pub fn is_breakpoint(&mut self, n: location) -> bool {
+		return GetIsBreakpoint(n, &mut self.program);
+	}
 
-	// set the status
-	processor.status = ProcessorStatus::NotStarted;
-	
-}
+	// same as r_SetBreakpointCondition
+	pub fn set_breakpoint_condition(&mut self, n: location, condition: &str) -> bool {
+		return SetBreakpointCondition(n, condition, &mut self.program).is_ok();
+	}
 
-#[wasm_bindgen]
-pub fn r_GetInstructionPointer() -> jsint {
-	let program = getProgram();
-	return program.Processor.next as jsint;
-}
+	pub fn set_breakpoint_ignore_count(&mut self, n: location, count: u32) -> bool {
+		return SetBreakpointIgnoreCount(n, count, &mut self.program);
+	}
 
-#[wasm_bindgen]
-pub fn r_GetProcessorStatus() -> jsint {
-	let program = getProgram();
-	return match program.Processor.status {
-		ProcessorStatus::Paused => 0,
-		ProcessorStatus::Halted => 1,
-		ProcessorStatus::NotStarted => 2,
-		ProcessorStatus::Running => 3,
-		ProcessorStatus::Empty => 4,
+	pub fn breakpoint_hit_count(&self, n: location) -> u32 {
+		return GetBreakpointHitCount(n, &self.program);
 	}
-}
 
-#[wasm_bindgen]
-pub fn r_EnableBreakpoints() {
-	let program = getProgram();
-	program.DoBreakpoints = true;
-}
+	// same as r_SetLogpoint
+	pub fn set_logpoint(&mut self, n: location, message: &str) -> bool {
+		return SetLogpoint(n, message, &mut self.program).is_ok();
+	}
 
-#[wasm_bindgen]
-pub fn r_DisableBreakpoints() {
-	let program = getProgram();
-	program.DoBreakpoints = false;
-}
+	pub fn take_breakpoint_log(&mut self) -> Vec<String> {
+		return std::mem::take(&mut self.program.BreakpointLog);
+	}
 
-#[wasm_bindgen]
-pub fn r_GetMemoryBlockSize() -> jsint {
-	return MEM_SIZE as jsint;
-}
+	pub fn enable_breakpoints(&mut self) {
+		self.program.DoBreakpoints = true;
+	}
 
-#[wasm_bindgen]
-pub fn r_string() -> String {
-	"hello".into()
-}
+	pub fn disable_breakpoints(&mut self) {
+		self.program.DoBreakpoints = false;
+	}
 
-#[wasm_bindgen]
-pub fn r_GetWasmMemoryLocation(location: jsint) -> jsint {
-	let program = getProgram();
-	return program.Processor._get_pointer(location as u32);
-}
+	pub fn get_page(&mut self, n: u32) -> js_sys::Uint32Array {
+		return GetPage(n, &mut self.program);
+	}
 
-fn run(program: &mut Program) -> jsint {
-	match program.Processor.status {
-		ProcessorStatus::Halted => {
-			return 0;
-		},
-		ProcessorStatus::Empty => {
-			return 0;
-		},
-		_ => { // paused, not started, running
-			let mut steps_taken = 0;
-			program.Processor.status = ProcessorStatus::Running;
-			while program.Processor.status == ProcessorStatus::Running {
-				step(&mut program);
-				steps_taken += 1;
-			}
+	// runs until the processor pauses or halts, returns the number of steps taken
+	pub fn run(&mut self) -> jsint {
+		return run(&mut self.program);
+	}
 
-			return steps_taken;
-		},
+	// same as r_RunFor
+	pub fn run_for(&mut self, max_steps: u32) -> jsint {
+		return self.program.run_budget(Some(max_steps)).0 as jsint;
+	}
+
+	pub fn last_run_reason(&self) -> jsint {
+		return self.program.LastRunReason.code();
+	}
+
+	pub fn pause(&mut self) {
+		Pause(&mut self.program);
+	}
+
+	pub fn step_over(&mut self) {
+		StepOver(&mut self.program);
+	}
+
+	pub fn step_into(&mut self) {
+		StepInto(&mut self.program);
+	}
+
+	// same as r_StepOut
+	pub fn step_out(&mut self) -> jsint {
+		return StepOut(&mut self.program).0 as jsint;
+	}
+
+	// same as r_StepN
+	pub fn step_n(&mut self, n: u32) -> jsint {
+		return StepN(n, &mut self.program).0 as jsint;
 	}
 
+	// same as r_RunTo
+	pub fn run_to(&mut self, address: location) -> jsint {
+		return RunTo(address, &mut self.program).0 as jsint;
+	}
+
+	pub fn instruction_pointer(&self) -> location {
+		return self.program.Processor.next;
+	}
+
+	pub fn bus(&self) -> storage {
+		return self.program.Processor.bus;
+	}
+
+	// same codes as r_GetProcessorStatus
+	pub fn status(&self) -> jsint {
+		return GetProcessorStatus(&self.program);
+	}
+
+	pub fn stack_pointer(&self) -> location {
+		return self.program.Processor.stack_pointer;
+	}
+
+	// same layout as r_GetCallStack
+	pub fn call_stack(&self) -> Vec<u32> {
+		return GetCallStack(&self.program);
+	}
+
+	// same codes as r_GetLastFault
+	pub fn last_fault(&self) -> jsint {
+		return GetLastFault(&self.program);
+	}
+
+	pub fn last_fault_address(&self) -> location {
+		return self.program.Processor.fault_address;
+	}
+
+	pub fn last_fault_detail(&self) -> jsint {
+		return GetLastFaultDetail(&self.program);
+	}
+
+	pub fn read_memory(&self, location: location) -> storage {
+		return self.program.Processor._peek_memory_loc(location);
+	}
+
+	pub fn write_memory(&mut self, location: location, value: storage) {
+		self.program.Processor._poke_memory_loc(location, value);
+	}
 }
 
-fn step(program: &mut Program) {
+impl Machine {
+	pub fn fault(&self) -> Option<Fault> {
+		return self.program.Processor.fault;
+	}
 
-	if program.DoBreakpoints {
-		if program.Breakpoints.contains(&program.Processor.next) {
-			program.Processor.status = ProcessorStatus::Paused;
-			return;
+	// same as r_AddWatchpoint
+	pub fn add_watchpoint(&mut self, start: location, end: location, kind: u32) -> u32 {
+		return AddWatchpoint(start, end, kind, &mut self.program);
+	}
+
+	pub fn remove_watchpoint(&mut self, id: u32) -> bool {
+		return self.program.Processor.remove_watchpoint(id);
+	}
+
+	// same layout as r_GetLastWatchHit
+	pub fn last_watch_hit(&self) -> Vec<u32> {
+		return GetLastWatchHit(&self.program);
+	}
+
+	pub fn start_trace(&mut self) {
+		self.program.Tracer.start();
+	}
+
+	pub fn stop_trace(&mut self) {
+		self.program.Tracer.stop();
+	}
+
+	pub fn clear_trace(&mut self) {
+		self.program.Tracer.clear();
+	}
+
+	pub fn add_trace_filter(&mut self, start: location, end: location) {
+		self.program.Tracer.add_filter(start, end);
+	}
+
+	pub fn clear_trace_filters(&mut self) {
+		self.program.Tracer.clear_filters();
+	}
+
+	pub fn set_trace_limit(&mut self, limit: usize) {
+		self.program.Tracer.set_limit(limit);
+	}
+
+	pub fn trace_length(&self) -> usize {
+		return self.program.Tracer.len();
+	}
+
+	pub fn export_trace_binary(&self) -> Vec<u8> {
+		return self.program.Tracer.export_binary();
+	}
+
+	pub fn export_trace_json(&self) -> String {
+		return self.program.Tracer.export_json();
+	}
+
+	// same as r_SetDecodeCache
+	pub fn set_decode_cache(&mut self, enabled: bool) {
+		self.program.Processor.decode_cache.set_enabled(enabled);
+	}
+
+	// same as r_SetJit
+	pub fn set_jit(&mut self, enabled: bool) {
+		self.program.Jit.set_enabled(enabled);
+	}
+
+	pub fn jit_block_count(&self) -> usize {
+		return self.program.Jit.block_count();
+	}
+
+	// maps the device over start..=end. loads and stores made by instructions in the
+	// range go to the device instead of memory, the range does not need to be allocated
+	pub fn map_device(&mut self, start: location, end: location, device: Box<dyn Device>) -> Result<u32, MapError> {
+		return self.program.Processor.devices.map(start, end, device);
+	}
+
+	// returns the device, or None if nothing is mapped with the id
+	pub fn unmap_device(&mut self, id: u32) -> Option<Box<dyn Device>> {
+		return self.program.Processor.devices.unmap(id);
+	}
+
+	// same as r_GetDeviceMappings
+	pub fn device_mappings(&self) -> Vec<u32> {
+		return GetDeviceMappings(&self.program);
+	}
+
+	// same as r_SetMemoryPages
+	pub fn set_memory_pages(&mut self, pages: u32) {
+		self.program.Processor.memory.set_page_count(pages);
+	}
+
+	pub fn memory_pages(&self) -> u32 {
+		return self.program.Processor.memory.page_count();
+	}
+
+	// pages that have been written to, the rest of memory takes no space
+	pub fn allocated_pages(&self) -> u32 {
+		return self.program.Processor.memory.allocated_pages();
+	}
+
+	pub fn set_unmapped_access(&mut self, mode: UnmappedAccess) {
+		self.program.Processor.memory.set_unmapped_access(mode);
+	}
+
+	// same as r_SetHeapChecks
+	pub fn set_heap_checks(&mut self, enabled: bool) {
+		SetHeapChecks(enabled, &mut self.program);
+	}
+
+	// (address, size) for each live heap allocation, sizes are rounded up
+	pub fn heap_allocations(&self) -> Vec<(location, u32)> {
+		return self.program.Processor.heap.allocations();
+	}
+
+	// same as r_SetPageTable
+	pub fn set_page_table(&mut self, base: location, length: u32) {
+		self.program.Processor.set_page_table(base, length);
+	}
+
+	pub fn set_paging(&mut self, enabled: bool) {
+		self.program.Processor.set_paging(enabled);
+	}
+
+	pub fn paging_enabled(&self) -> bool {
+		return self.program.Processor.mmu.paging;
+	}
+
+	pub fn is_supervisor(&self) -> bool {
+		return self.program.Processor.supervisor;
+	}
+
+	// same as r_AddCore
+	pub fn add_core(&mut self, entry: location, stack_base: location) -> u32 {
+		return cores::add_core(&mut self.program, entry, stack_base);
+	}
+
+	pub fn core_count(&self) -> u32 {
+		return self.program.Cores.len() as u32;
+	}
+
+	// the core the processor's registers belong to
+	pub fn current_core(&self) -> u32 {
+		return self.program.Processor.core_id;
+	}
+
+	// (next, bus), None for a core that does not exist
+	pub fn core_registers(&self, core: u32) -> Option<(location, storage)> {
+		let state = GetCoreState(&self.program, core);
+		return state.first().map(|&next| (next, state[1]));
+	}
+
+	pub fn is_core_halted(&self, core: u32) -> bool {
+		return self.program.Cores.get(core as usize).is_some_and(|core| core.halted);
+	}
+
+	// same as r_SetSupervisor
+	pub fn set_supervisor(&mut self, supervisor: bool) {
+		self.program.Processor.supervisor = supervisor;
+	}
+
+	// same as r_Translate
+	pub fn translate(&self, address: location) -> Option<location> {
+		return self.program.Processor.translate_quietly(address);
+	}
+
+	// (hits, misses)
+	pub fn tlb_stats(&self) -> (u32, u32) {
+		return (self.program.Processor.mmu.tlb_hits, self.program.Processor.mmu.tlb_misses);
+	}
+
+	// same as r_SetPermissions
+	pub fn set_permissions(&mut self, start: location, end: location, bits: u32) {
+		SetPermissions(start, end, bits, &mut self.program);
+	}
+
+	pub fn page_permissions(&self, address: location) -> u32 {
+		return self.program.Processor.page_permissions(address);
+	}
+
+	// same as r_RaiseInterrupt
+	pub fn raise_interrupt(&mut self, line: u32) -> bool {
+		return self.program.Processor.raise_interrupt(line);
+	}
+
+	pub fn interrupts_enabled(&self) -> bool {
+		return self.program.Processor.interrupts_enabled;
+	}
+
+	// a bit set, line n is bit n
+	pub fn pending_interrupts(&self) -> u32 {
+		return self.program.Processor.pending_interrupts;
+	}
+
+	// same as r_Disassemble
+	pub fn disassemble(&self, start: location, end: location) -> String {
+		return disasm::to_text(&disasm::disassemble(&self.program.Processor, start, end));
+	}
+
+	// same as r_DisassembleWords
+	pub fn disassemble_words(&self, start: location, end: location) -> Vec<u32> {
+		return disasm::to_words(&disasm::disassemble(&self.program.Processor, start, end));
+	}
+
+	pub fn step_back(&mut self) -> bool {
+		return history::step_back(&mut self.program);
+	}
+
+	pub fn reverse_continue(&mut self) -> u32 {
+		return history::reverse_continue(&mut self.program);
+	}
+
+	pub fn set_history_limit(&mut self, limit: usize) {
+		self.program.History.set_limit(limit);
+	}
+
+	pub fn history_length(&self) -> usize {
+		return self.program.History.len();
+	}
+
+	pub fn watch_hit(&self) -> Option<WatchHit> {
+		return self.program.Processor.watch_hit;
+	}
+
+	// like set_breakpoint_condition, with the reason the condition was rejected
+	pub fn try_set_breakpoint_condition(&mut self, n: location, condition: &str) -> Result<(), ConditionError> {
+		return SetBreakpointCondition(n, condition, &mut self.program);
+	}
+
+	pub fn try_set_logpoint(&mut self, n: location, message: &str) -> Result<(), ConditionError> {
+		return SetLogpoint(n, message, &mut self.program);
+	}
+
+	pub fn breakpoint(&self, n: location) -> Option<&Breakpoint> {
+		return self.program.Breakpoints.get(&n);
+	}
+
+	pub fn decode(&self, address: location) -> Instruction {
+		return disasm::decode(&self.program.Processor, address);
+	}
+
+	pub fn instructions(&self, start: location, end: location) -> Vec<Instruction> {
+		return disasm::disassemble(&self.program.Processor, start, end);
+	}
+
+	pub fn trace(&self) -> impl Iterator<Item = &TraceRecord> {
+		return self.program.Tracer.records();
+	}
+
+	pub fn save_snapshot(&self) -> Vec<u8> {
+		return snapshot::save(&self.program);
+	}
+
+	pub fn restore_snapshot(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+		return snapshot::restore(&mut self.program, bytes);
+	}
+
+	pub fn run_budget(&mut self, max_steps: Option<u32>) -> (u32, RunReason) {
+		return self.program.run_budget(max_steps);
+	}
+
+	pub fn with_syscall_host(host: Box<dyn SyscallHost>) -> Machine {
+		Machine {
+			program: Program::with_syscall_host(host),
 		}
-	} 
+	}
 
-	let stopCode = program.Processor.step();
+	// lets the syscall host move data between its buffers and memory,
+	// like the JS does between frames
+	pub fn refresh_syscall_host(&mut self) {
+		let processor = &mut self.program.Processor;
+		processor.syscall_host.refresh(&mut SyscallMemory::new(&mut processor.memory));
+	}
 
-	match stopCode {
-		StopCode::Halt => {
-			program.Processor.status = ProcessorStatus::Halted;
-		},
-		StopCode::Pause => {
-			program.Processor.status = ProcessorStatus::Paused;
-		},
-		StopCode::None => {
-			// continue
-		},
+	// see SyscallHost::input_closed
+	pub fn syscall_input_closed(&self) -> bool {
+		return self.program.Processor.syscall_host.input_closed();
 	}
-}
 
-fn syscall(code: storage, param: storage) -> i32 {
-	unsafe {
-		return js_syscall(code as jsint, param as c_int);
+	pub fn is_paused(&self) -> bool {
+		return self.program.Processor.status == ProcessorStatus::Paused;
 	}
-}
 
-fn SetBreakpoint(point: u32, program: &mut Program) {
-	if !program.Breakpoints.contains(&point) {
-		program.Breakpoints.insert(point);
+	pub fn is_halted(&self) -> bool {
+		return self.program.Processor.status == ProcessorStatus::Halted;
 	}
-}
 
-fn RemoveBreakpoint(point: u32, program: &mut Program) {
-	if program.Breakpoints.contains(&point) {
-		program.Breakpoints.remove(&point);
+	pub fn memory(&mut self) -> SyscallMemory<'_> {
+		return SyscallMemory::new(&mut self.program.Processor.memory);
 	}
 }
 
-fn GetIsBreakpoint(point: u32, program: &mut Program) -> bool {
-	return program.Breakpoints.contains(&point);
+fn run(program: &mut Program) -> jsint {
+	let (steps_taken, _) = program.run_budget(None);
+	return steps_taken as jsint;
 }
 
-fn Continue(program: &mut Program) {
-	run(program);
-}
+// returns false if a breakpoint stopped the step before anything was executed.
+// the caller switches cores afterwards, see cores::schedule
+fn step(program: &mut Program, check_breakpoints: bool) -> bool {
+
+	// a breakpoint is checked when its instruction is about to run,
+	// not when an interrupt is taken in front of it
+	if check_breakpoints && program.DoBreakpoints && program.Processor.next_interrupt().is_none() {
+		if let Some(breakpoint) = program.Breakpoints.get_mut(&program.Processor.next) {
+			if breakpoint.hit(&program.Processor, &mut program.BreakpointLog) {
+				program.Processor.status = ProcessorStatus::Paused;
+				return false;
+			}
+		}
+	}
+
+	let status = program.Processor.status;
+	let stop_code = if program.History.is_enabled() || program.Tracer.is_enabled() {
+		step_recorded(program)
+	}
+	else {
+		program.Processor.step()
+	};
 
-fn StepOver(program: &mut Program) {
-	match program.Processor.status {
-		ProcessorStatus::Paused => {
-			step(&mut program);
+	match stop_code {
+		StopCode::Halt => {
+			// the machine only halts once every core has
+			program.Processor.status = if cores::halt(program) { status } else { ProcessorStatus::Halted };
 		},
-		ProcessorStatus::NotStarted => {
-			step(&mut program);
+		StopCode::Pause => {
 			program.Processor.status = ProcessorStatus::Paused;
-		}
-		_ => {},
+		},
+		StopCode::Fault => {
+			program.Processor.status = ProcessorStatus::Faulted;
+		},
+		StopCode::None => {
+			// continue
+		},
 	}
+	return true;
+}
+
+// a step with the bookkeeping for history and the tracer. kept out of step,
+// which is the hot loop of the interpreter whenever both are off
+fn step_recorded(program: &mut Program) -> StopCode {
+	let record = history::begin_step(program);
+	let trace_start = trace::begin_step(program);
+	let stop_code = program.Processor.step();
+	end_recorded_step(program, record, trace_start);
+	return stop_code;
 }
 
+// hands what the step changed to history and the tracer, compiled blocks use it too
+fn end_recorded_step(program: &mut Program, record: Option<history::UndoRecord>, trace_start: Option<trace::TraceStart>) {
+	let mut writes = std::mem::take(&mut program.Processor.perStepWrites);
+	let mut released = std::mem::take(&mut program.Processor.perStepReleasedWords);
+	trace::end_step(program, trace_start, &writes);
+	history::end_step(program, record, &writes, &released);
+	writes.clear();
+	released.clear();
+	program.Processor.perStepWrites = writes;
+	program.Processor.perStepReleasedWords = released;
+	program.Processor.perStepRecordWrites = false;
+	program.Processor.perStepOldPermissions = None;
+	program.Processor.heap.end_step();
+}
+
+// Program and Processor keep their original field and method names
+#[allow(non_snake_case)]
 struct Program {
 	Processor: Processor,
-	Breakpoints: HashSet<u32>,
+	Breakpoints: HashMap<u32, Breakpoint>,
 	DoBreakpoints: bool,
+	// messages from logpoints, until they are taken
+	BreakpointLog: Vec<String>,
+	Tracer: trace::Tracer,
+	LastRunReason: RunReason,
+	// the breakpoint the processor is paused on. the next run does not stop on it again,
+	// anything else that moves the processor clears it
+	BreakpointStop: Option<location>,
+	History: history::History,
+	Jit: jit::Jit,
+	// the registers of every core, see cores.rs. there is always at least core 0
+	Cores: Vec<cores::Core>,
 }
+#[allow(non_snake_case)]
 impl Program {
 	fn new() -> Program {
-		let Processor = Processor::new();
-		let Breakpoints = HashSet::new();
+		return Program::with_syscall_host(Box::new(JsSyscallHost));
+	}
+
+	fn with_syscall_host(host: Box<dyn SyscallHost>) -> Program {
+		let mut Processor = Processor::new();
+		Processor.syscall_host = host;
+		let Breakpoints = HashMap::new();
 		let DoBreakpoints = false;
 		set_panic_hook();
 		Program {
 			Processor,
 			Breakpoints,
 			DoBreakpoints,
+			BreakpointLog: Vec::new(),
+			Tracer: trace::Tracer::new(),
+			LastRunReason: RunReason::Empty,
+			BreakpointStop: None,
+			History: history::History::new(),
+			Jit: jit::Jit::new(),
+			Cores: vec![cores::Core::new(1, STACK_BASE)],
 		}
 	}
+
+	// runs until the processor pauses, halts or faults, or until max_steps
+	// instructions have been executed. returns the number of instructions executed
+	fn run_budget(&mut self, max_steps: Option<u32>) -> (u32, RunReason) {
+		return self.run_with(max_steps, None);
+	}
+
+	// like run_budget, but also pauses with RunReason::Reached
+	// once reached returns true after an instruction. with several cores only the
+	// instructions of the core running now count, and it is left running when it gets there
+	fn run_until(&mut self, max_steps: Option<u32>, mut reached: impl FnMut(&Processor) -> bool) -> (u32, RunReason) {
+		return self.run_with(max_steps, Some(&mut reached));
+	}
+
+	// without reached, compiled blocks do not have to stop after every instruction to check it
+	fn run_with(&mut self, max_steps: Option<u32>, mut reached: Option<&mut (dyn FnMut(&Processor) -> bool + '_)>) -> (u32, RunReason) {
+		match self.Processor.status {
+			ProcessorStatus::Halted => return (0, RunReason::Halted),
+			ProcessorStatus::Faulted => return (0, RunReason::Faulted),
+			ProcessorStatus::Empty => return (0, RunReason::Empty),
+			_ => {},
+		}
+
+		// memory may have been changed from outside since the last run
+		self.Processor.decode_cache.invalidate_all();
+		self.Processor.mmu.flush();
+
+		// a processor paused by a breakpoint is sitting on it,
+		// so the first step after resuming does not check that one
+		let mut checkBreakpoints = self.BreakpointStop.take() != Some(self.Processor.next);
+		let mut steps_taken = 0;
+		let core = self.Processor.core_id;
+		self.Processor.status = ProcessorStatus::Running;
+		while self.Processor.status == ProcessorStatus::Running {
+			if let Some(max) = max_steps {
+				if steps_taken >= max {
+					self.LastRunReason = RunReason::BudgetExhausted;
+					return (steps_taken, RunReason::BudgetExhausted);
+				}
+			}
+			if self.Jit.is_enabled() {
+				let budget = max_steps.map(|max| max - steps_taken);
+				if let Some((executed, stopped)) = jit::run_block(self, budget, reached.as_deref_mut()) {
+					steps_taken += executed;
+					if stopped {
+						self.Processor.status = ProcessorStatus::Paused;
+						self.LastRunReason = RunReason::Reached;
+						return (steps_taken, RunReason::Reached);
+					}
+					checkBreakpoints = true;
+					continue;
+				}
+			}
+			if step(self, checkBreakpoints) {
+				steps_taken += 1;
+				let stepped = self.Processor.core_id;
+				if self.Processor.status == ProcessorStatus::Running && stepped == core
+					&& !self.Cores[core as usize].halted && reached.as_mut().is_some_and(|reached| reached(&self.Processor)) {
+					self.Processor.status = ProcessorStatus::Paused;
+					self.LastRunReason = RunReason::Reached;
+					return (steps_taken, RunReason::Reached);
+				}
+				cores::schedule(self);
+			}
+			else {
+				self.BreakpointStop = Some(self.Processor.next);
+				self.LastRunReason = RunReason::Breakpoint;
+				return (steps_taken, RunReason::Breakpoint);
+			}
+			checkBreakpoints = true;
+		}
+
+		let reason = match self.Processor.status {
+			ProcessorStatus::Halted => RunReason::Halted,
+			ProcessorStatus::Faulted => RunReason::Faulted,
+			_ if self.Processor.watch_triggered => RunReason::Watchpoint,
+			_ => RunReason::Paused,
+		};
+		self.LastRunReason = reason;
+		return (steps_taken, reason);
+	}
 }
 
+#[allow(non_snake_case)]
 struct Processor {
 	bus: storage,
 	alu: ALU,
 	next: location,
 	status: ProcessorStatus,
-	regions: Vec<MemoryBlock>,
+	memory: memory::Memory,
+
+	// the last fault raised, and the address of the instruction that raised it
+	fault: Option<Fault>,
+	fault_address: location,
+
+	syscall_host: Box<dyn SyscallHost>,
+
+	// the stack lives in memory, from stack_limit (exclusive) up to stack_base (exclusive).
+	// frames is only bookkeeping for the debugger, pushed by CALL and popped by RET
+	stack_pointer: location,
+	stack_base: location,
+	stack_limit: location,
+	frames: Vec<CallFrame>,
 
 	perStepParamPointer: u32,
 	perStepDontMove: bool,
+	perStepFault: Option<Fault>,
+	// old values of the memory written during the step, when history or the tracer wants them.
+	// the buffer is reused between steps
+	perStepRecordWrites: bool,
+	perStepWrites: Vec<(location, storage)>,
+	// the words of heap pages released during the step. only history restores them,
+	// the tracer only sees what the instruction wrote
+	perStepReleasedWords: Vec<(location, storage)>,
+	perStepWatchHit: Option<WatchHit>,
+	// both words after the opcode, as decoded
+	perStepParams: [storage; 2],
+
+	decode_cache: decode::DecodeCache,
+
+	// memory mapped devices, checked by the memory accessors before memory
+	devices: device::DeviceBus,
+
+	// see interrupt.rs. pending_interrupts has a bit set for each raised line
+	interrupts_enabled: bool,
+	pending_interrupts: u32,
+
+	// permission bits for each page, see permissions.rs
+	permissions: permissions::PagePermissions,
+	// the page changed by the step and its old permissions, when history wants them
+	perStepOldPermissions: Option<(location, u32)>,
+
+	// the allocator for opcodes 43 and 44, see heap.rs
+	heap: heap::Heap,
+
+	// user mode cannot run privileged instructions, see Opcode::is_privileged,
+	// and can only use user pages with paging on. see mmu.rs and trap.rs
+	supervisor: bool,
+	mmu: mmu::Mmu,
+	// the cause and detail of a trap raised during the step, taken at the end of it
+	perStepTrap: Option<(u32, storage)>,
+
+	// the core whose registers are the ones above, see cores.rs
+	core_id: u32,
+
+	// data breakpoints, checked by the memory accessors.
+	// watch_triggered is only set on the step that paused for watch_hit
+	watchpoints: Vec<Watchpoint>,
+	next_watchpoint_id: u32,
+	watch_hit: Option<WatchHit>,
+	watch_triggered: bool,
 }
 
+#[allow(non_snake_case)]
 impl Processor {
 	fn new() -> Processor {
 		let bus = 0;
 		let alu = ALU::new();
 		let next = 1;
 		let status = ProcessorStatus::Empty;
-		let mut regions: Vec<MemoryBlock> = Vec::new();
-		regions.push(MemoryBlock::new());
+		let memory = memory::Memory::new(MEM_SIZE as u32 / permissions::PAGE_SIZE);
 		let perStepParamPointer = 0;
 		let perStepDontMove = false;
 		Processor {
@@ -304,16 +891,47 @@ impl Processor {
 			alu,
 			next,
 			status,
-			regions,
+			memory,
+			fault: None,
+			fault_address: 0,
+			syscall_host: Box::new(JsSyscallHost),
+			stack_pointer: STACK_BASE,
+			stack_base: STACK_BASE,
+			stack_limit: STACK_BASE - STACK_SIZE,
+			frames: Vec::new(),
 			perStepParamPointer,
 			perStepDontMove,
+			perStepFault: None,
+			perStepRecordWrites: false,
+			perStepWrites: Vec::new(),
+			perStepReleasedWords: Vec::new(),
+			perStepParams: [0, 0],
+			decode_cache: decode::DecodeCache::new(),
+			devices: device::DeviceBus::new(),
+			interrupts_enabled: false,
+			pending_interrupts: 0,
+			permissions: permissions::PagePermissions::new(),
+			perStepOldPermissions: None,
+			heap: heap::Heap::new(),
+			supervisor: true,
+			mmu: mmu::Mmu::new(),
+			perStepTrap: None,
+			core_id: 0,
+			perStepWatchHit: None,
+			watchpoints: Vec::new(),
+			next_watchpoint_id: 0,
+			watch_hit: None,
+			watch_triggered: false,
 		}
 	}
 
 	fn getParam(&mut self) -> storage {
 		let n = self.next;
 		let perStepParamPointer = self.perStepParamPointer + 1;
-		let param: storage = self._get_memory_loc(n + perStepParamPointer);
+		let param: storage = match self.perStepParams.get(perStepParamPointer as usize - 1) {
+			Some(param) => *param,
+			None => self._peek_memory_loc(n.wrapping_add(perStepParamPointer)),
+		};
 		self.perStepParamPointer = perStepParamPointer;
 		return param;
 	}
@@ -322,208 +940,324 @@ impl Processor {
 		self.perStepDontMove = true;
 	}
 
+	// stops the current step with a fault.
+	// only the first fault raised during a step is kept
+	fn raise(&mut self, fault: Fault) {
+		if self.perStepFault.is_none() {
+			self.perStepFault = Some(fault);
+		}
+	}
+
 	// returns whether or not a breakpoint was hit
 	fn step(&mut self) -> StopCode {
 		let n = self.next;
 		let mut stopCode = StopCode::None;
 
         self.perStepParamPointer = 0;
+		self.perStepFault = None;
+		self.perStepTrap = None;
+		self.perStepWatchHit = None;
+		self.watch_triggered = false;
+
+		// taking an interrupt is a step of its own,
+		// the interrupted instruction runs once the handler returns
+		if let Some((line, handler)) = self.next_interrupt() {
+			self.enter_interrupt(line, handler);
+			return self.end_step(n, StopCode::None);
+		}
 
-		let op = self._get_memory_loc(n);
+		let physical = match self.translate(n, permissions::EXECUTE) {
+			Some(physical) => physical,
+			// a page fault, taken by end_step
+			None => return self.end_step(n, StopCode::None),
+		};
+		let decoded = match self.decode_cache.get(physical) {
+			Some(decoded) => decoded,
+			None => {
+				if !self._is_allocated(physical) {
+					return self.fault(n, Fault::InstructionOutOfBounds(physical));
+				}
+				if !self.can_access(physical, permissions::EXECUTE) {
+					return self.fault(n, Fault::ExecuteViolation(physical));
+				}
+				let decoded = match self.fetch_translated(n, physical) {
+					Some(decoded) => decoded,
+					None => return self.end_step(n, StopCode::None),
+				};
+				// parameters on the next page were translated on their own
+				if !self.mmu.paging || physical % permissions::PAGE_SIZE < permissions::PAGE_SIZE - 2 {
+					self.decode_cache.insert(physical, decoded);
+				}
+				decoded
+			},
+		};
+		let op = decoded.op;
+		if !self.supervisor && decoded.opcode.is_some_and(|opcode| opcode.is_privileged()) {
+			return self.fault(n, Fault::PrivilegeViolation(op));
+		}
+		self.perStepParams = decoded.params;
 
 		// 'parameter' is always an unsigned integer, and is type 'storage'
 		// 'as' means 'transmute the bytes to'
 		// 'current' is the current instruction pointer
 
-		// Opcodes:
-		//	0	NO-OP
-
-		//	1	memory[parameter] -> bus
-		//	2	bus -> memory[parameter]
-
-		//	3	memory[current + parameter as int] -> bus
-		//	4	bus -> memory[current + parameter as int]
-
-        //  5   Load with constant offset
-        //  6   Save with constant offset
-
-        //  7   memory[current + bus as int] -> bus
-        //  8   bus -> memory[current + bus as int]
-
-		//	9	ALU.add
-		//		result -> ALU.hi
-
-		//	10	ALU.negate
-		//		result -> ALU.hi
-
-		//	11	ALU.multiply
+		// see isa.rs for what each opcode does and how many parameters it takes
 
-		//	12	ALU.divide
-
-		//	13	jump -> goto current + parameter as int
-		//	14	jump bus > 0 -> goto current + parameter as int
-
-		//	16	ALU.hi -> bus
-		//	17	ALU.lo -> bus
-
-		//	18	ALU to int mode
-		//	19	ALU to float mode
-
-		//	20	new block, beginning address -> bus
-		//	21	syscall with parameter as code and bus as argument
-		//	22	halt
-		//	23	pause
-
-		//	24	parameter -> bus
-
-        //  25  bus -> push onto ALU
-
-		//	26	load with variable offset
-		//	27	save with variable offset
-
-		//	28	get current instruction counter
-
-		match op {
-			0 => {},
-            1 => {
+		match decoded.opcode {
+			Some(Opcode::Noop) => {},
+            Some(Opcode::LoadValueAtAddressIntoBus) => {
                 let param = self.getParam();
 				self.load_location(param);
             },
-			2 => {
+			Some(Opcode::SaveValueInBusToLocation) => {
 				let param = self.getParam();
 				self.set_location(param);
 			},
-			3 => {
+			Some(Opcode::LoadWithConstantOffsetFromHereToBus) => {
 				let param = self.getParam();
 				self.load_location_relative(param);
 			},
-			4 => {
+			Some(Opcode::SaveFromBusWithConstantOffsetFromHere) => {
 				let param = self.getParam();
 				self.set_location_relative(param);
 			},
-			5 => {
+			Some(Opcode::LoadWithConstantOffsetToBus) => {
 				let pointer = self.getParam();
 				let constant = self.getParam();
 				self.load_with_constant_offset_to_bus(pointer, constant);
 			},
-			6 => {
+			Some(Opcode::SaveFromBusWithConstantOffset) => {
 				let pointer = self.getParam();
 				let constant = self.getParam();
 				self.save_with_constant_offset_from_bus(pointer, constant);
 			},
-			7 => {
+			Some(Opcode::LoadWithBusAsConstantOffsetFromHere) => {
 				self.load_location_relative_with_bus();
 			},
-			9 => {
+			Some(Opcode::SaveWithBusAsConstantOffsetFromHere) => {
+				self.save_location_relative_with_bus();
+			},
+			Some(Opcode::AluDoAdd) => {
 				self.add();
 			},
-			11 => {
+			Some(Opcode::AluNegate) => {
+				self.negate();
+			},
+			Some(Opcode::AluMultiply) => {
 				self.multiply();
 			},
-			12 => {
+			Some(Opcode::AluDivide) => {
 				self.divide();
 			},
-			13 => {
+			Some(Opcode::JumpToBus) => {
 				let b = self.bus;
 				self.jump(b);
 				self.dontMoveParamPointer();
 			},
-			14 => {
+			Some(Opcode::BranchTo) => {
 				let param = self.getParam();
 				if self.alu.compare_result {
 					self.dontMoveParamPointer();
 					self.jump(param);
 				}
 			},
-			15 => {
+			Some(Opcode::LinkIfBranched) => {
 				// link if compare == true
 				if self.alu.compare_result {
 					self.bus = n;
 				}
 			},
-			16 => {
+			Some(Opcode::AluHiToBus) => {
 				self.get_hi();
 			},
-			17 => {
+			Some(Opcode::AluLoToBus) => {
 				self.get_lo();
 			},
-			18 => {
+			Some(Opcode::AluToInt) => {
 				self.alu_to_int();
 			},
-			19 => {
+			Some(Opcode::AluToFloat) => {
 				self.alu_to_float();
 			},
-			20 => {
-				let newblock = MemoryBlock::new();
-				self.add_region(newblock);
+			Some(Opcode::NewBlock) => {
+				self.grow_memory();
 			},
-			21 => {
+			Some(Opcode::Syscall) => {
 				let code = self.getParam();
 				// syscall
 				self.syscall(code);
 			},
-			23 => {
+			Some(Opcode::Halt) => {
+				stopCode = self.halt();
+			},
+			Some(Opcode::Pause) => {
 				stopCode = StopCode::Pause;
 				self.status = ProcessorStatus::Paused;
 			},
-			24 => {
+			Some(Opcode::LoadImmediateToBus) => {
 				let param = self.getParam();
 				self.load_immediate(param);
 			},
-			25 => {
+			Some(Opcode::AluPushFromBus) => {
 				// all ALU operations should push the bus value to the ALU first
 				// which means that this operation is unnecesary for some cases
 				self.push_to_alu();
 			},
-			26 => {
+			Some(Opcode::LoadWithVariableOffsetToBus) => {
 				let p1 = self.getParam();
 				let p2 = self.getParam();
 				self.load_with_variable_offset_to_bus(p1, p2);
 			},
-			27 => {
+			Some(Opcode::SaveFromBusWithVariableOffset) => {
 				let p1 = self.getParam();
 				let p2 = self.getParam();
 				self.save_with_variable_offset_from_bus(p1, p2);
 			},
-			28 => {
+			Some(Opcode::GetCurrentPosition) => {
 				let counter = self.next;
 				self.bus = counter;
 			},
-			29 => {
+			Some(Opcode::AluDoComparisonWithMode) => {
 				let mode = self.getParam();
 				self.alu_compare_with_mode(mode);
 			},
-			30 => {
+			Some(Opcode::Or) => {
 				self.or();
 			},
-			31 => {
+			Some(Opcode::And) => {
 				self.and();
 			},
-			32 => {
+			Some(Opcode::ShiftLeft) => {
 				self.shift_left();
 			},
-			33 => {
+			Some(Opcode::ShiftRight) => {
 				self.shift_right();
 			},
-			_ => {
-				stopCode = StopCode::Halt;
-				self.status = ProcessorStatus::Halted;
-				self.dontMoveParamPointer();
+			Some(Opcode::Push) => {
+				let value = self.bus;
+				self.push(value);
+			},
+			Some(Opcode::Pop) => {
+				if let Some(value) = self.pop() {
+					self.bus = value;
+				}
+			},
+			Some(Opcode::Call) => {
+				let target = self.getParam();
+				self.call(target);
+			},
+			Some(Opcode::Return) => {
+				self.ret();
+			},
+			Some(Opcode::GetStackPointer) => {
+				self.bus = self.stack_pointer;
+			},
+			Some(Opcode::EnableInterrupts) => {
+				self.interrupts_enabled = true;
+			},
+			Some(Opcode::DisableInterrupts) => {
+				self.interrupts_enabled = false;
+			},
+			Some(Opcode::ReturnFromInterrupt) => {
+				self.return_from_interrupt();
+			},
+			Some(Opcode::SetPermissions) => {
+				let address = self.getParam();
+				let bits = self.getParam();
+				self.set_permissions_instruction(address, bits);
+			},
+			Some(Opcode::Allocate) => {
+				let size = self.bus;
+				self.bus = self.allocate(size);
+			},
+			Some(Opcode::Free) => {
+				let address = self.bus;
+				self.free(address);
+			},
+			Some(Opcode::SetPageTable) => {
+				let length = self.getParam();
+				let base = self.bus;
+				self.set_page_table(base, length);
+			},
+			Some(Opcode::EnablePaging) => {
+				self.set_paging(true);
+			},
+			Some(Opcode::DisablePaging) => {
+				self.set_paging(false);
+			},
+			Some(Opcode::ReturnFromTrap) => {
+				self.return_from_trap();
+			},
+			Some(Opcode::Trap) => {
+				let detail = self.getParam();
+				self.perStepTrap = Some((trap::TRAP_SYSTEM_CALL, detail));
+			},
+			Some(Opcode::GetCoreId) => {
+				self.bus = self.core_id;
+			},
+			Some(Opcode::DslHalt) => {
+				// halt, as emitted by the DSL compiler
+				stopCode = self.halt();
+			},
+			None => {
+				self.raise(Fault::InvalidOpcode(op));
 			},
 		};
 
+		self.tick_devices();
+		return self.end_step(n, stopCode);
+	}
+
+	// moves the instruction pointer past the instruction at n, unless it jumped, faulted or trapped
+	fn end_step(&mut self, n: location, mut stopCode: StopCode) -> StopCode {
+		if let Some(fault) = self.perStepFault.take() {
+			// leave the instruction pointer on the faulting instruction
+			self.perStepDontMove = false;
+			return self.fault(n, fault);
+		}
+
+		if let Some((cause, detail)) = self.perStepTrap.take() {
+			self.perStepDontMove = false;
+			let return_address = match cause {
+				trap::TRAP_SYSTEM_CALL => n.wrapping_add(self.perStepParamPointer + 1),
+				_ => n,
+			};
+			self.enter_trap(return_address, cause, detail);
+			if let Some(fault) = self.perStepFault.take() {
+				return self.fault(n, fault);
+			}
+			return stopCode;
+		}
+
 		if !self.perStepDontMove {
 			// perStepParamPointer represents how many parameters were used
 			// by the operation, so we want to move perStepParamPointer + 1
-			self.next += self.perStepParamPointer + 1;
+			self.next = self.next.wrapping_add(self.perStepParamPointer + 1);
 		}
         else {
             self.perStepDontMove = false;
         }
 
+		if let Some(hit) = self.perStepWatchHit.take() {
+			self.watch_hit = Some(hit);
+			self.watch_triggered = true;
+			if let StopCode::None = stopCode {
+				stopCode = StopCode::Pause;
+				self.status = ProcessorStatus::Paused;
+			}
+		}
+
 		return stopCode;
 	}
 
+	fn fault(&mut self, address: location, fault: Fault) -> StopCode {
+		self.fault = Some(fault);
+		self.fault_address = address;
+		self.next = address;
+		self.status = ProcessorStatus::Faulted;
+		return StopCode::Fault;
+	}
+
 	// opcode 1
 	// fn load_location_(&mut self, _offset: storage) {
 	// 	let offset = bits_to_i32(_offset);
@@ -544,14 +1278,22 @@ impl Processor {
 	fn load_location_relative(&mut self, _offset: storage) {
 		let offset = bits_to_i32(_offset);
 		let next = self.next;
-		self.bus = self._get_memory_loc((offset + next as i32) as location);
+		self.bus = self._get_memory_loc(offset.wrapping_add(next as i32) as location);
 	}
 
 	// opcode 3
 	fn load_location_relative_with_bus(&mut self) {
 		let offset = bits_to_i32(self.bus);
 		let next = self.next;
-		self.bus = self._get_memory_loc((offset + next as i32) as location);
+		self.bus = self._get_memory_loc(offset.wrapping_add(next as i32) as location);
+	}
+
+	// opcode 8
+	fn save_location_relative_with_bus(&mut self) {
+		let value = self.bus;
+		let offset = bits_to_i32(value);
+		let next = self.next;
+		self._set_memory_loc(offset.wrapping_add(next as i32) as location, value);
 	}
 
 	// opcode 4
@@ -559,7 +1301,7 @@ impl Processor {
 		let offset = bits_to_i32(_offset);
 		let value = self.bus;
 		let next = self.next;
-		self._set_memory_loc((offset + next as i32) as u32, value);
+		self._set_memory_loc(offset.wrapping_add(next as i32) as u32, value);
 	}
 
 	// opcode 5
@@ -574,6 +1316,12 @@ impl Processor {
 		self.alu.add();
 	}
 
+	// opcode 10
+	fn negate(&mut self) {
+		self.push_to_alu();
+		self.alu.negate();
+	}
+
 	// opcode 8
 	fn multiply(&mut self) {
 		self.push_to_alu();
@@ -583,7 +1331,9 @@ impl Processor {
 	// opcode 9
 	fn divide(&mut self) {
 		self.push_to_alu();
-		self.alu.divide();
+		if let Err(fault) = self.alu.divide() {
+			self.raise(fault);
+		}
 	}
 
 	fn or(&mut self) {
@@ -638,24 +1388,42 @@ impl Processor {
 		}
 	}
 
-	// opcode 10
+	// opcode 22
+	// the instruction pointer stays on the halt
+	fn halt(&mut self) -> StopCode {
+		self.status = ProcessorStatus::Halted;
+		self.dontMoveParamPointer();
+		return StopCode::Halt;
+	}
+
+	// to an absolute address, for opcode 13 and the other jumps.
+	// with paging on, a jump to an unmapped page faults when the target is fetched
 	fn jump(&mut self, jumpTo: storage) {
+		if !self.mmu.paging && !self._is_allocated(jumpTo) {
+			self.raise(Fault::JumpOutOfBounds(jumpTo));
+			return;
+		}
 		self.next = jumpTo;
-		// let relative = bits_to_i32(jumpTo);
-		// self.next = ((self.next as i32) + relative) as u32;
 	}
 
-	// opcode 14
-	// add a memory region to the processor
-	// takes ownership of the region
-	fn add_region(&mut self, region: MemoryBlock) {
-		self.regions.push(region);
+	// opcode 20
+	// maps another MEM_SIZE words past the end of memory. nothing is allocated until it is written
+	fn grow_memory(&mut self) {
+		let page_count = self.memory.page_count();
+		if page_count == MAX_PAGES {
+			self.raise(Fault::MemoryOutOfBounds(location::MAX));
+			return;
+		}
+		self.memory.set_page_count(page_count.saturating_add(MEM_SIZE as u32 / permissions::PAGE_SIZE));
 	}
 
 	// opcode 15
 	fn syscall(&mut self, code: storage) {
 		let param = self.bus;
-		self.bus = i32_to_bits(syscall(code, param));
+		let result = self.syscall_host.syscall(code, param, &mut SyscallMemory::new(&mut self.memory));
+		self.bus = i32_to_bits(result);
+		// the host may have written anywhere
+		self.decode_cache.invalidate_all();
 	}
 
 	// opcode 1
@@ -670,25 +1438,86 @@ impl Processor {
 	}
 
 	fn load_with_constant_offset_to_bus(&mut self, p1: location, p2: storage) {
-		let val = self._get_memory_loc(p1 + p2);
+		let val = self._get_memory_loc(p1.wrapping_add(p2));
 		self.bus = val;
 	}
 
 	fn load_with_variable_offset_to_bus(&mut self, p1: location, p2: location) {
 		let offset = self._get_memory_loc(p2);
-		let val = self._get_memory_loc(p1 + offset);
+		let val = self._get_memory_loc(p1.wrapping_add(offset));
 		self.bus = val;
 	}
 
 	fn save_with_constant_offset_from_bus(&mut self, p1: location, p2: storage) {
 		let value = self.bus;
-		self._set_memory_loc(p1 + p2, value);
+		self._set_memory_loc(p1.wrapping_add(p2), value);
 	}
 	
 	fn save_with_variable_offset_from_bus(&mut self, p1: location, p2: location) {
 		let offset = self._get_memory_loc(p2);
 		let value = self.bus;
-		self._set_memory_loc(p1 + offset, value);
+		self._set_memory_loc(p1.wrapping_add(offset), value);
+	}
+
+	// opcode 34
+	// the stack grows down from stack_base, towards stack_limit.
+	// the stack pointer does not move if the write trapped or faulted
+	fn push(&mut self, value: storage) -> bool {
+		if self.stack_pointer <= self.stack_limit {
+			self.raise(Fault::StackOverflow(self.stack_pointer));
+			return false;
+		}
+		let sp = self.stack_pointer - 1;
+		self._set_memory_loc(sp, value);
+		if self.perStepTrap.is_some() || self.perStepFault.is_some() {
+			return false;
+		}
+		self.stack_pointer = sp;
+		return true;
+	}
+
+	// opcode 35
+	fn pop(&mut self) -> Option<storage> {
+		if self.stack_pointer >= self.stack_base {
+			self.raise(Fault::StackUnderflow(self.stack_pointer));
+			return None;
+		}
+		let value = self._get_memory_loc(self.stack_pointer);
+		if self.perStepTrap.is_some() || self.perStepFault.is_some() {
+			return None;
+		}
+		self.stack_pointer += 1;
+		return Some(value);
+	}
+
+	// opcode 36
+	fn call(&mut self, target: location) {
+		if !self.mmu.paging && !self._is_allocated(target) {
+			self.raise(Fault::JumpOutOfBounds(target));
+			return;
+		}
+		let call_site = self.next;
+		let return_address = call_site.wrapping_add(2);
+		if !self.push(return_address) {
+			return;
+		}
+		self.frames.push(CallFrame {
+			call_site,
+			target,
+			return_address,
+			stack_pointer: self.stack_pointer,
+		});
+		self.jump(target);
+		self.dontMoveParamPointer();
+	}
+
+	// opcode 37
+	fn ret(&mut self) {
+		if let Some(return_address) = self.pop() {
+			self.frames.pop();
+			self.jump(return_address);
+			self.dontMoveParamPointer();
+		}
 	}
 
 	// opcode 20
@@ -810,44 +1639,133 @@ impl Processor {
 	}
 
 	// helper
-	fn _get_memory_loc(&self, location: location) -> storage {
-		let offset = location as usize % MEM_SIZE;
-		let region_num = (location as f64 / MEM_SIZE as f64).floor() as usize;
+	// data reads made by instructions, these reach devices and trigger watchpoints.
+	// with paging on the address is translated first, and reads as 0 if it trapped
+	fn _get_memory_loc(&mut self, address: location) -> storage {
+		let location = match self.translate(address, permissions::READ) {
+			Some(location) => location,
+			None => return 0,
+		};
+		let value = match self.devices.read(location) {
+			Some(value) => value,
+			None if !self.can_access(location, permissions::READ) => {
+				self.raise(Fault::ReadViolation(location));
+				return 0;
+			},
+			None if self.memory.unmapped_access() == UnmappedAccess::Fault && !self.memory.is_mapped(location) => {
+				self.raise(Fault::MemoryOutOfBounds(location));
+				return 0;
+			},
+			None if self.heap.checks_enabled() && self.heap.is_quarantined(location) => {
+				self.raise(Fault::UseAfterFree(location));
+				return 0;
+			},
+			None => self._peek_memory_loc(location),
+		};
+		self.check_watchpoints(location, WatchKind::Read, value, value);
+		return value;
+	}
+
+	// helper
+	// reads without triggering watchpoints, for instruction fetch and the debugger
+	fn _peek_memory_loc(&self, location: location) -> storage {
+		return self.memory.read(location);
+	}
 
-		if region_num < self.regions.len() {
-			return self.regions[region_num].memory[offset];
+	// helper
+	fn _set_memory_loc(&mut self, address: location, value: storage) {
+		let location = match self.translate(address, permissions::WRITE) {
+			Some(location) => location,
+			None => return,
+		};
+		if self.devices.write(location, value) {
+			// a device has no old value, and its writes are not recorded
+			self.check_watchpoints(location, WatchKind::Write, value, value);
+			return;
+		}
+		if !self.can_access(location, permissions::WRITE) {
+			self.raise(Fault::WriteViolation(location));
+			return;
 		}
+		if self.heap.checks_enabled() && self.heap.is_quarantined(location) {
+			self.raise(Fault::UseAfterFree(location));
+			return;
+		}
+		if !self._write_physical(location, value) {
+			self.raise(Fault::MemoryOutOfBounds(location));
+		}
+	}
 
-		return 0;
+	// helper
+	// a write to memory that is recorded and triggers watchpoints, without the checks.
+	// returns false if the address is not mapped
+	fn _write_physical(&mut self, location: location, value: storage) -> bool {
+		let old = match self.memory.word_mut(location) {
+			Some(word) => std::mem::replace(word, value),
+			None => return false,
+		};
+		if self.perStepRecordWrites {
+			self.perStepWrites.push((location, old));
+		}
+		self.check_watchpoints(location, WatchKind::Write, old, value);
+		self.decode_cache.invalidate(location);
+		if self.mmu.covers(location) {
+			self.mmu.flush();
+		}
+		return true;
 	}
 
 	// helper
-	fn _set_memory_loc(&mut self, location: location, value: storage) {
-		let offset = location as usize % MEM_SIZE;
-		let region_num = (location as f64 / MEM_SIZE as f64).floor() as usize;
+	// writes without recording history or triggering watchpoints.
+	// returns false if the address is not mapped
+	fn _poke_memory_loc(&mut self, location: location, value: storage) -> bool {
+		if self.memory.write(location, value) {
+			self.decode_cache.invalidate(location);
+			if self.mmu.covers(location) {
+				self.mmu.flush();
+			}
+			return true;
+		}
+		return false;
+	}
 
-		self.regions[region_num].memory[offset] = value;
+	// helper
+	// mapped, pages that were never written count too
+	fn _is_allocated(&self, location: location) -> bool {
+		return self.memory.is_mapped(location);
 	}
 
-	// used only for JS to get memory from wasm
+	// used only for JS to get memory from wasm. 0 if the page was never written
 	fn _get_pointer(&self, location: location) -> i32 {
-		let offset = location as usize % MEM_SIZE;
-		let region_num = (location as f64 / MEM_SIZE as f64).floor() as usize;
-
-		if region_num < self.regions.len() {
-			let a = &self.regions[region_num].memory[offset] as *const storage;
-			return a as i32;
-		}
-
-		return 0;
+		return match self.memory.page(location / permissions::PAGE_SIZE) {
+			Some(page) => &page[(location % permissions::PAGE_SIZE) as usize] as *const storage as i32,
+			None => 0,
+		};
 	}
 }
 
+// one CALL that has not returned yet
+#[derive(Clone, Copy)]
+struct CallFrame {
+	call_site: location,
+	target: location,
+	return_address: location,
+	// the stack pointer after the return address was pushed
+	stack_pointer: location,
+}
+
+// a byte each, compiled blocks read and write them, see jit_wasm.rs
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+#[repr(u8)]
 enum ALUMode {
 	int,
 	float
 }
 
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+#[repr(u8)]
 enum ALUCompareMode {
 	greater_than,
 	greater_than_or_equal,
@@ -857,6 +1775,8 @@ enum ALUCompareMode {
 	lesser_than_or_equal,
 }
 
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 struct ALU {
 	value_a_int: i32, // recent value
 	value_b_int: i32, // oldest value
@@ -885,20 +1805,6 @@ impl ALU {
 		}
 	}
 
-	fn mode_int_save_value(&mut self) {
-		self.mode = ALUMode::int;
-
-		self.value_a_int = self.value_a_float as i32;
-		self.value_b_int = self.value_b_float as i32;
-	}
-
-	fn mode_float_save_value(&mut self) {
-		self.mode = ALUMode::float;
-
-		self.value_a_float = self.value_a_int as f32;
-		self.value_b_float = self.value_b_int as f32;
-	}
-
 	fn mode_int_save_bits(&mut self) {
 		self.mode = ALUMode::int;
 
@@ -927,6 +1833,13 @@ impl ALU {
 		}
 	}
 
+	fn negate(&mut self) {
+		match self.mode {
+			ALUMode::int => self.negate_int(),
+			ALUMode::float => self.negate_float(),
+		}
+	}
+
 	fn multiply(&mut self) {
 		match self.mode {
 			ALUMode::int => self.multiply_int(),
@@ -934,10 +1847,13 @@ impl ALU {
 		}
 	}
 
-	fn divide(&mut self) {
+	fn divide(&mut self) -> Result<(), Fault> {
 		match self.mode {
 			ALUMode::int => self.divide_int(),
-			ALUMode::float => self.divide_float(),
+			ALUMode::float => {
+				self.divide_float();
+				return Ok(());
+			},
 		}
 	}
 
@@ -949,19 +1865,19 @@ impl ALU {
 	}
 
 	fn bitwise_or(&mut self) {
-		self.value_a_int = self.value_a_int | self.value_b_int;
+		self.value_a_int |= self.value_b_int;
 	}
 
 	fn bitwise_and(&mut self) {
-		self.value_a_int = self.value_a_int & self.value_b_int;
+		self.value_a_int &= self.value_b_int;
 	}
 
 	fn shift_left(&mut self) {
-		self.value_a_int = self.value_a_int << self.value_b_int;
+		self.value_a_int = self.value_a_int.wrapping_shl(self.value_b_int as u32);
 	}
 
 	fn shift_right(&mut self) {
-		self.value_a_int = self.value_a_int >> self.value_b_int;
+		self.value_a_int = self.value_a_int.wrapping_shr(self.value_b_int as u32);
 	}
 
 	fn push_int(&mut self, value: i32) {
@@ -985,32 +1901,46 @@ impl ALU {
 		self.lo = 0;
 	}
 
+	fn negate_int(&mut self) {
+		self.hi = i32_to_bits(self.value_a_int.wrapping_neg());
+		self.lo = 0;
+	}
+
+	fn negate_float(&mut self) {
+		self.hi = (-self.value_a_float).to_bits();
+		self.lo = 0;
+	}
+
 	fn multiply_int(&mut self) {
 		let bits = i64_to_bits(
 			self.value_a_int as i64 * self.value_b_int as i64
 		);
 
-		let loMask = 0x00000000ffffffff;
-		let hiMask = 0xffffffff00000000;
+		let lo_mask = 0x00000000ffffffff;
+		let hi_mask = 0xffffffff00000000;
 		
-		self.hi = (hiMask & bits) as u32;
-		self.lo = (loMask & bits) as u32;
+		self.hi = (hi_mask & bits) as u32;
+		self.lo = (lo_mask & bits) as u32;
 	}
 
 	fn multiply_float(&mut self) {
 		let value: f64 = (self.value_a_float * self.value_b_float).into();
 		let bits = value.to_bits();
 
-		let loMask = 0x00000000ffffffff;
-		let hiMask = 0xffffffff00000000;
+		let lo_mask = 0x00000000ffffffff;
+		let hi_mask = 0xffffffff00000000;
 
-		self.hi = ((hiMask & bits) >> 32) as u32;
-		self.lo = (loMask & bits) as u32;
+		self.hi = ((hi_mask & bits) >> 32) as u32;
+		self.lo = (lo_mask & bits) as u32;
 	}
 
-	fn divide_int(&mut self) {
-		self.lo = i32_to_bits(self.value_a_int / self.value_b_int);
-		self.hi = i32_to_bits(self.value_a_int % self.value_b_int);
+	fn divide_int(&mut self) -> Result<(), Fault> {
+		if self.value_b_int == 0 {
+			return Err(Fault::DivideByZero);
+		}
+		self.lo = i32_to_bits(self.value_a_int.wrapping_div(self.value_b_int));
+		self.hi = i32_to_bits(self.value_a_int.wrapping_rem(self.value_b_int));
+		return Ok(());
 	}
 
 	fn divide_float(&mut self) {
@@ -1070,42 +2000,14 @@ impl ALU {
 
 }
 
-struct MemoryBlock {
-	memory: [storage; MEM_SIZE],
-}
-impl MemoryBlock {
-	fn new() -> MemoryBlock {
-		let memory = [0; MEM_SIZE];
-		MemoryBlock {
-			memory,
-		}
-	}
-
-	// fn set_value(&mut self, location: usize, value: i32) {
-	//	self.memory[location] = value;
-	// }
-}
-
 fn i32_to_bits(v: i32) -> u32 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as u32;
 }
 
 fn bits_to_i32(v: u32) -> i32 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as i32;
 }
 
 fn i64_to_bits(v: i64) -> u64 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
+	return v as u64;
 }
-
-fn bits_to_i64(v: u64) -> i64 {
-	unsafe {
-		return std::mem::transmute(v);
-	}
-}
\ No newline at end of file
@@ -0,0 +1,160 @@
+use crate::{storage, location};
+use crate::permissions::PAGE_SIZE;
+
+// pages in the whole 32 bit address space
+pub const MAX_PAGES: u32 = u32::MAX / PAGE_SIZE + 1;
+// page tables hold this many pages, the directory has MAX_PAGES / TABLE_SIZE tables
+const TABLE_SIZE: usize = 2048;
+
+pub(crate) type Page = [storage; PAGE_SIZE as usize];
+// TABLE_SIZE pages, each allocated on its first write
+type Table = Box<[Option<Box<Page>>]>;
+
+// what loads do outside the mapped pages. stores there always fault
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnmappedAccess {
+	// the value is 0, like an untouched page
+	ReadZero = 0,
+	// the load raises MemoryOutOfBounds
+	Fault = 1,
+}
+
+impl UnmappedAccess {
+	pub fn from_code(code: u32) -> Option<UnmappedAccess> {
+		return match code {
+			0 => Some(UnmappedAccess::ReadZero),
+			1 => Some(UnmappedAccess::Fault),
+			_ => None,
+		};
+	}
+}
+
+// sparse memory. addresses below page_count * PAGE_SIZE are mapped, but a page only
+// takes up space once something is written to it. until then it reads as zeros.
+// pages live in a two level table, so an access is two index operations
+pub(crate) struct Memory {
+	tables: Vec<Option<Table>>,
+	page_count: u32,
+	allocated: u32,
+	unmapped: UnmappedAccess,
+}
+
+impl Memory {
+	pub(crate) fn new(page_count: u32) -> Memory {
+		let mut memory = Memory {
+			tables: Vec::new(),
+			page_count: 0,
+			allocated: 0,
+			unmapped: UnmappedAccess::ReadZero,
+		};
+		memory.set_page_count(page_count);
+		return memory;
+	}
+
+	pub(crate) fn page_count(&self) -> u32 {
+		return self.page_count;
+	}
+
+	// pages that have been written to
+	pub(crate) fn allocated_pages(&self) -> u32 {
+		return self.allocated;
+	}
+
+	// clamped to MAX_PAGES. pages past the new end are freed
+	pub(crate) fn set_page_count(&mut self, page_count: u32) {
+		let page_count = page_count.min(MAX_PAGES);
+		if page_count < self.page_count {
+			for page in self.allocated_page_numbers() {
+				if page >= page_count {
+					self.tables[page as usize / TABLE_SIZE].as_mut().unwrap()[page as usize % TABLE_SIZE] = None;
+					self.allocated -= 1;
+				}
+			}
+		}
+		let table_count = (page_count as usize).div_ceil(TABLE_SIZE);
+		self.tables.resize_with(table_count, || None);
+		self.page_count = page_count;
+	}
+
+	pub(crate) fn unmapped_access(&self) -> UnmappedAccess {
+		return self.unmapped;
+	}
+
+	pub(crate) fn set_unmapped_access(&mut self, unmapped: UnmappedAccess) {
+		self.unmapped = unmapped;
+	}
+
+	pub(crate) fn is_mapped(&self, address: location) -> bool {
+		return address / PAGE_SIZE < self.page_count;
+	}
+
+	// 0 for untouched and unmapped addresses
+	pub(crate) fn read(&self, address: location) -> storage {
+		return match self.page(address / PAGE_SIZE) {
+			Some(page) => page[(address % PAGE_SIZE) as usize],
+			None => 0,
+		};
+	}
+
+	// false if the address is not mapped
+	pub(crate) fn write(&mut self, address: location, value: storage) -> bool {
+		return match self.word_mut(address) {
+			Some(word) => {
+				*word = value;
+				true
+			},
+			None => false,
+		};
+	}
+
+	// allocates the page if this is its first write
+	pub(crate) fn word_mut(&mut self, address: location) -> Option<&mut storage> {
+		let page = self.page_mut(address / PAGE_SIZE)?;
+		return Some(&mut page[(address % PAGE_SIZE) as usize]);
+	}
+
+	// None if the page is unmapped or was never written
+	pub(crate) fn page(&self, page: u32) -> Option<&Page> {
+		let table = self.tables.get(page as usize / TABLE_SIZE)?.as_ref()?;
+		return table[page as usize % TABLE_SIZE].as_deref();
+	}
+
+	// None if the page is unmapped
+	pub(crate) fn page_mut(&mut self, page: u32) -> Option<&mut Page> {
+		if page >= self.page_count {
+			return None;
+		}
+		let table = self.tables[page as usize / TABLE_SIZE]
+			.get_or_insert_with(|| (0..TABLE_SIZE).map(|_| None).collect());
+		let slot = &mut table[page as usize % TABLE_SIZE];
+		if slot.is_none() {
+			*slot = Some(Box::new([0; PAGE_SIZE as usize]));
+			self.allocated += 1;
+		}
+		return slot.as_deref_mut();
+	}
+
+	// frees the page, it reads as zeros again
+	pub(crate) fn release_page(&mut self, page: u32) {
+		if let Some(Some(table)) = self.tables.get_mut(page as usize / TABLE_SIZE) {
+			if table[page as usize % TABLE_SIZE].take().is_some() {
+				self.allocated -= 1;
+			}
+		}
+	}
+
+	// the numbers of the allocated pages, in order
+	pub(crate) fn allocated_page_numbers(&self) -> Vec<u32> {
+		let mut pages = Vec::new();
+		for (t, table) in self.tables.iter().enumerate() {
+			if let Some(table) = table {
+				for (slot, page) in table.iter().enumerate() {
+					if page.is_some() {
+						pages.push((t * TABLE_SIZE + slot) as u32);
+					}
+				}
+			}
+		}
+		return pages;
+	}
+}
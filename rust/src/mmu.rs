@@ -0,0 +1,144 @@
+use crate::{storage, location, Processor};
+use crate::decode::Decoded;
+use crate::permissions::{self, PAGE_SIZE};
+use crate::trap::TRAP_PAGE_FAULT;
+
+// page table entries are the physical address of a page with flags in the low bits.
+// the read, write and execute bits are the ones from permissions.rs
+pub const PTE_VALID: u32 = 8;
+// pages without it can only be used in supervisor mode
+pub const PTE_USER: u32 = 16;
+const PTE_FLAGS: u32 = PAGE_SIZE - 1;
+const TLB_SIZE: usize = 64;
+
+// the optional MMU. with paging on, every address an instruction uses is virtual:
+// virtual page v is described by the entry at page_table_base + v, and pages from
+// page_table_length on have no entry. a missing entry, an entry without PTE_VALID or the
+// access bit, or a supervisor page used from user mode is a page fault, delivered as a trap.
+// once an access has trapped the rest of the instruction's accesses do nothing, and the
+// instruction runs again from the start when the handler returns.
+//
+// everything past translation uses physical addresses: devices, permissions, watchpoints,
+// the heap, the debugger and syscall hosts.
+// the TLB only saves reading the page table. it is flushed whenever the table is written,
+// so programs never have to flush it
+#[derive(Clone, Copy)]
+pub(crate) struct Mmu {
+	pub(crate) paging: bool,
+	pub(crate) page_table_base: location,
+	pub(crate) page_table_length: u32,
+	// virtual page and entry, direct mapped. entries without PTE_VALID are empty
+	tlb: [(u32, u32); TLB_SIZE],
+	pub(crate) tlb_hits: u32,
+	pub(crate) tlb_misses: u32,
+}
+
+impl Mmu {
+	pub(crate) fn new() -> Mmu {
+		Mmu {
+			paging: false,
+			page_table_base: 0,
+			page_table_length: 0,
+			tlb: [(0, 0); TLB_SIZE],
+			tlb_hits: 0,
+			tlb_misses: 0,
+		}
+	}
+
+	pub(crate) fn flush(&mut self) {
+		self.tlb = [(0, 0); TLB_SIZE];
+	}
+
+	// true if writing the physical address changes the page table in use
+	pub(crate) fn covers(&self, address: location) -> bool {
+		return self.paging && address.wrapping_sub(self.page_table_base) < self.page_table_length;
+	}
+}
+
+impl Processor {
+	// the physical address, or None if the access trapped
+	pub(crate) fn translate(&mut self, address: location, access: u32) -> Option<location> {
+		if !self.mmu.paging {
+			return Some(address);
+		}
+		if self.perStepTrap.is_some() {
+			return None;
+		}
+		let entry = self.lookup_page(address / PAGE_SIZE);
+		let allowed = entry & PTE_VALID != 0
+			&& entry & access != 0
+			&& (self.supervisor || entry & PTE_USER != 0);
+		if !allowed {
+			self.perStepTrap = Some((TRAP_PAGE_FAULT, address));
+			return None;
+		}
+		return Some((entry & !PTE_FLAGS) | (address % PAGE_SIZE));
+	}
+
+	fn lookup_page(&mut self, page: u32) -> u32 {
+		let slot = page as usize % TLB_SIZE;
+		let (tag, entry) = self.mmu.tlb[slot];
+		if tag == page && entry & PTE_VALID != 0 {
+			self.mmu.tlb_hits = self.mmu.tlb_hits.wrapping_add(1);
+			return entry;
+		}
+		self.mmu.tlb_misses = self.mmu.tlb_misses.wrapping_add(1);
+		if page >= self.mmu.page_table_length {
+			return 0;
+		}
+		let entry = self._peek_memory_loc(self.mmu.page_table_base.wrapping_add(page));
+		if entry & PTE_VALID != 0 {
+			self.mmu.tlb[slot] = (page, entry);
+		}
+		return entry;
+	}
+
+	// for the debugger: any valid entry, in any mode, without touching the TLB
+	pub(crate) fn translate_quietly(&self, address: location) -> Option<location> {
+		if !self.mmu.paging {
+			return Some(address);
+		}
+		let page = address / PAGE_SIZE;
+		if page >= self.mmu.page_table_length {
+			return None;
+		}
+		let entry = self._peek_memory_loc(self.mmu.page_table_base.wrapping_add(page));
+		if entry & PTE_VALID == 0 {
+			return None;
+		}
+		return Some((entry & !PTE_FLAGS) | (address % PAGE_SIZE));
+	}
+
+	// the instruction at virtual address n, which is at physical address physical.
+	// parameters on the next page are translated on their own
+	pub(crate) fn fetch_translated(&mut self, n: location, physical: location) -> Option<Decoded> {
+		let mut decoded = self.fetch(physical);
+		if !self.mmu.paging || physical % PAGE_SIZE < PAGE_SIZE - 2 {
+			return Some(decoded);
+		}
+		let count = decoded.opcode.map_or(0, |opcode| opcode.operand_count());
+		for i in 0..count as usize {
+			let address = self.translate(n.wrapping_add(1 + i as u32), permissions::EXECUTE)?;
+			decoded.params[i] = self._peek_memory_loc(address);
+		}
+		return Some(decoded);
+	}
+
+	// opcode 45
+	pub(crate) fn set_page_table(&mut self, base: location, length: u32) {
+		self.mmu.page_table_base = base;
+		self.mmu.page_table_length = length;
+		self.mmu.flush();
+	}
+
+	// opcodes 46 and 47
+	pub(crate) fn set_paging(&mut self, enabled: bool) {
+		self.mmu.paging = enabled;
+		self.mmu.flush();
+	}
+}
+
+// the bus value written into a page table entry for the physical page holding address
+pub fn page_table_entry(address: location, flags: u32) -> storage {
+	return (address & !PTE_FLAGS) | (flags & PTE_FLAGS);
+}
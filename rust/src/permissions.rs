@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+
+use crate::{location, Processor};
+use crate::memory::MAX_PAGES;
+
+// permissions are set per page of this many words
+pub const PAGE_SIZE: u32 = 1024;
+
+// permission bits, as used by opcode 42 and the r_ exports
+pub const READ: u32 = 1;
+pub const WRITE: u32 = 2;
+pub const EXECUTE: u32 = 4;
+// what every page starts with
+pub const ALL_PERMISSIONS: u32 = READ | WRITE | EXECUTE;
+
+// the permissions of every page, as runs of pages with the same bits. a run starts at its
+// key and goes up to the next one, pages before the first run have all permissions.
+// neighbouring runs never have the same bits, so changing any range of pages, even the
+// whole address space, only touches the runs around it
+#[derive(Default)]
+pub(crate) struct PagePermissions {
+	runs: BTreeMap<u32, u8>,
+}
+
+impl PagePermissions {
+	pub(crate) fn new() -> PagePermissions {
+		return PagePermissions::default();
+	}
+
+	// true until permissions are first taken away
+	pub(crate) fn is_empty(&self) -> bool {
+		return self.runs.is_empty();
+	}
+
+	pub(crate) fn get(&self, page: u32) -> u32 {
+		return self.runs.range(..=page).next_back().map_or(ALL_PERMISSIONS, |(_, &bits)| bits as u32);
+	}
+
+	// first..=last, clamped to the address space
+	pub(crate) fn set(&mut self, first: u32, last: u32, bits: u32) {
+		let last = last.min(MAX_PAGES - 1);
+		let after = last + 1;
+		if after < MAX_PAGES && !self.runs.contains_key(&after) {
+			let bits_after = self.get(after) as u8;
+			self.runs.insert(after, bits_after);
+		}
+		let mut inside = self.runs.split_off(&first);
+		let mut rest = inside.split_off(&after);
+		self.runs.append(&mut rest);
+		self.runs.insert(first, (bits & ALL_PERMISSIONS) as u8);
+		self.merge(after);
+		self.merge(first);
+	}
+
+	// drops the run starting at page if the run before it has the same bits
+	fn merge(&mut self, page: u32) {
+		let before = self.runs.range(..page).next_back().map_or(ALL_PERMISSIONS as u8, |(_, &bits)| bits);
+		if self.runs.get(&page) == Some(&before) {
+			self.runs.remove(&page);
+		}
+	}
+
+	// the first page and bits of each run, in order
+	pub(crate) fn runs(&self) -> impl Iterator<Item = (u32, u8)> + '_ {
+		return self.runs.iter().map(|(&page, &bits)| (page, bits));
+	}
+}
+
+// only instructions are checked: data reads need READ, writes need WRITE and the
+// opcode of an instruction needs EXECUTE. the debugger, syscall hosts and devices are not
+impl Processor {
+	pub(crate) fn page_permissions(&self, address: location) -> u32 {
+		return self.permissions.get(address / PAGE_SIZE);
+	}
+
+	// applies to the page holding the address. returns the permissions it had
+	pub(crate) fn set_page_permissions(&mut self, address: location, bits: u32) -> u32 {
+		let page = address / PAGE_SIZE;
+		let old = self.permissions.get(page);
+		self.permissions.set(page, page, bits);
+		self.decode_cache.invalidate_all();
+		return old;
+	}
+
+	pub(crate) fn can_access(&self, address: location, permission: u32) -> bool {
+		// nothing is stored until permissions are first changed
+		if self.permissions.is_empty() {
+			return true;
+		}
+		return self.page_permissions(address) & permission != 0;
+	}
+
+	// opcode 42
+	pub(crate) fn set_permissions_instruction(&mut self, address: location, bits: u32) {
+		let old = self.set_page_permissions(address, bits);
+		if self.perStepRecordWrites {
+			self.perStepOldPermissions = Some((address, old));
+		}
+	}
+}
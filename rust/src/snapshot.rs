@@ -0,0 +1,526 @@
+use std::fmt;
+
+use crate::{
+	storage, location, Program, Processor, ProcessorStatus, ALU, ALUMode, ALUCompareMode,
+	CallFrame, Fault, Breakpoint, Condition, Template, UnmappedAccess, MEM_SIZE, MAX_PAGES, STACK_BASE,
+};
+use crate::cores::Core;
+use crate::heap::Heap;
+use crate::memory::Memory;
+use crate::permissions::{PagePermissions, PAGE_SIZE};
+
+// snapshot layout, all numbers little endian:
+//	magic "RASM", version u32
+//	processor: bus, next, status u8, fault (u8 code, u32 detail), fault_address
+//	stack: pointer, base, limit, frame count, then 4 words per frame
+//	alu: a/b int, a/b float bits, compare_result u8, compare mode u8, hi, lo, mode u8
+//	memory: mapped page count, unmapped access u8, then the number of stored pages and
+//		for each in address order its page number, the number of stored words and the words.
+//		trailing zeros are not stored, pages of zeros are left out.
+//		before version 5 memory was a block count, then for each 32K word block
+//		the number of stored words followed by the words
+//	breakpoints: enabled u8, count, then for each in address order:
+//		address, ignore count, hit count, condition string, log message string.
+//		strings are a byte length followed by utf-8, empty for none.
+//		version 1 snapshots only have the addresses
+//	interrupts: enabled u8, pending lines as a bit set. not in versions 1 and 2
+//	permissions: run count, then for each run in order its first page and a u8 of its bits,
+//		see PagePermissions. before version 9 a page count and a u8 for each page,
+//		not before version 4
+//	heap: see Heap::save. not before version 6
+//	mmu: supervisor u8, paging u8, page table base, page table length. not before version 7
+//	cores: count, the running core, then each core in order: for the running core only
+//		halted u8, its registers are the processor fields above. see Core::save for the rest.
+//		not before version 8
+//	breakpoint stop: u8 flag, then the address of the breakpoint the processor is paused on.
+//		not before version 10, older snapshots paused on a breakpoint are taken to be stopped by it
+const MAGIC: &[u8; 4] = b"RASM";
+const VERSION: u32 = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+	BadMagic,
+	UnsupportedVersion(u32),
+	Truncated,
+	Invalid(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return match *self {
+			SnapshotError::BadMagic => write!(f, "not a snapshot"),
+			SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+			SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+			SnapshotError::Invalid(what) => write!(f, "snapshot has an invalid {}", what),
+		};
+	}
+}
+
+// also used for binary traces
+pub(crate) struct Writer {
+	pub(crate) bytes: Vec<u8>,
+}
+
+impl Writer {
+	pub(crate) fn u8(&mut self, v: u8) {
+		self.bytes.push(v);
+	}
+
+	pub(crate) fn u32(&mut self, v: u32) {
+		self.bytes.extend_from_slice(&v.to_le_bytes());
+	}
+
+	fn string(&mut self, v: &str) {
+		self.u32(v.len() as u32);
+		self.bytes.extend_from_slice(v.as_bytes());
+	}
+}
+
+pub(crate) struct Reader<'a> {
+	bytes: &'a [u8],
+	at: usize,
+}
+
+impl<'a> Reader<'a> {
+	pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+		if self.at >= self.bytes.len() {
+			return Err(SnapshotError::Truncated);
+		}
+		self.at += 1;
+		return Ok(self.bytes[self.at - 1]);
+	}
+
+	pub(crate) fn u32(&mut self) -> Result<u32, SnapshotError> {
+		if self.at + 4 > self.bytes.len() {
+			return Err(SnapshotError::Truncated);
+		}
+		let b = &self.bytes[self.at..self.at + 4];
+		self.at += 4;
+		return Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+	}
+
+	pub(crate) fn bool(&mut self) -> Result<bool, SnapshotError> {
+		return Ok(self.u8()? != 0);
+	}
+
+	fn string(&mut self) -> Result<&'a str, SnapshotError> {
+		let len = self.u32()? as usize;
+		if len > self.bytes.len() - self.at {
+			return Err(SnapshotError::Truncated);
+		}
+		let bytes = &self.bytes[self.at..self.at + len];
+		self.at += len;
+		return std::str::from_utf8(bytes).map_err(|_| SnapshotError::Invalid("string"));
+	}
+}
+
+pub(crate) fn save(program: &Program) -> Vec<u8> {
+	let mut w = Writer { bytes: Vec::new() };
+	let p = &program.Processor;
+
+	w.bytes.extend_from_slice(MAGIC);
+	w.u32(VERSION);
+
+	w.u32(p.bus);
+	w.u32(p.next);
+	w.u8(status_to_u8(&p.status));
+	match p.fault {
+		Some(fault) => {
+			w.u8(fault.code() as u8);
+			w.u32(fault.detail());
+		},
+		None => {
+			w.u8(0);
+			w.u32(0);
+		},
+	}
+	w.u32(p.fault_address);
+
+	w.u32(p.stack_pointer);
+	w.u32(p.stack_base);
+	w.u32(p.stack_limit);
+	write_frames(&mut w, &p.frames);
+	write_alu(&mut w, &p.alu);
+
+	w.u32(p.memory.page_count());
+	w.u8(p.memory.unmapped_access() as u8);
+	let mut pages = Vec::new();
+	for number in p.memory.allocated_page_numbers() {
+		let page = p.memory.page(number).unwrap();
+		let used = page.iter().rposition(|&v| v != 0).map_or(0, |i| i + 1);
+		if used > 0 {
+			pages.push((number, &page[..used]));
+		}
+	}
+	w.u32(pages.len() as u32);
+	for (number, words) in pages {
+		w.u32(number);
+		w.u32(words.len() as u32);
+		for value in words.iter() {
+			w.u32(*value);
+		}
+	}
+
+	let mut breakpoints: Vec<(&location, &Breakpoint)> = program.Breakpoints.iter().collect();
+	breakpoints.sort_by_key(|&(point, _)| *point);
+	w.u8(program.DoBreakpoints as u8);
+	w.u32(breakpoints.len() as u32);
+	for (point, breakpoint) in breakpoints {
+		w.u32(*point);
+		w.u32(breakpoint.ignore_count);
+		w.u32(breakpoint.hit_count);
+		w.string(breakpoint.condition.as_ref().map_or("", |c| c.source()));
+		w.string(breakpoint.log_message.as_ref().map_or("", |m| m.source()));
+	}
+
+	w.u8(p.interrupts_enabled as u8);
+	w.u32(p.pending_interrupts);
+
+	let runs: Vec<(u32, u8)> = p.permissions.runs().collect();
+	w.u32(runs.len() as u32);
+	for (page, bits) in runs {
+		w.u32(page);
+		w.u8(bits);
+	}
+
+	p.heap.save(&mut w);
+
+	w.u8(p.supervisor as u8);
+	w.u8(p.mmu.paging as u8);
+	w.u32(p.mmu.page_table_base);
+	w.u32(p.mmu.page_table_length);
+
+	w.u32(program.Cores.len() as u32);
+	w.u32(p.core_id);
+	for (id, core) in program.Cores.iter().enumerate() {
+		if id as u32 == p.core_id {
+			w.u8(core.halted as u8);
+		}
+		else {
+			core.save(&mut w);
+		}
+	}
+
+	w.u8(program.BreakpointStop.is_some() as u8);
+	w.u32(program.BreakpointStop.unwrap_or(0));
+
+	return w.bytes;
+}
+
+// replaces the machine state with the snapshot.
+// the program is only modified if the whole snapshot is valid
+pub(crate) fn restore(program: &mut Program, bytes: &[u8]) -> Result<(), SnapshotError> {
+	if bytes.len() < 4 || &bytes[..4] != MAGIC {
+		return Err(SnapshotError::BadMagic);
+	}
+	let mut r = Reader { bytes, at: 4 };
+	let version = r.u32()?;
+	if version == 0 || version > VERSION {
+		return Err(SnapshotError::UnsupportedVersion(version));
+	}
+
+	let bus: storage = r.u32()?;
+	let next: location = r.u32()?;
+	let status = status_from_u8(r.u8()?).ok_or(SnapshotError::Invalid("processor status"))?;
+	let fault_code = r.u8()?;
+	let fault_detail = r.u32()?;
+	let fault = match fault_code {
+		0 => None,
+		code => Some(Fault::from_code(code as i32, fault_detail).ok_or(SnapshotError::Invalid("fault"))?),
+	};
+	let fault_address = r.u32()?;
+
+	let stack_pointer = r.u32()?;
+	let stack_base = r.u32()?;
+	let stack_limit = r.u32()?;
+	check_stack(stack_pointer, stack_base, stack_limit)?;
+	let frames = read_frames(&mut r)?;
+	let alu = read_alu(&mut r)?;
+
+	let memory = if version >= 5 { read_memory(&mut r)? } else { read_memory_blocks(&mut r)? };
+
+	let do_breakpoints = r.bool()?;
+	let breakpoint_count = r.u32()?;
+	let mut breakpoints = Vec::new();
+	for _ in 0..breakpoint_count {
+		let point = r.u32()?;
+		let mut breakpoint = Breakpoint::default();
+		if version >= 2 {
+			breakpoint.ignore_count = r.u32()?;
+			breakpoint.hit_count = r.u32()?;
+			breakpoint.condition = match r.string()? {
+				"" => None,
+				source => Some(Condition::parse(source).map_err(|_| SnapshotError::Invalid("breakpoint condition"))?),
+			};
+			breakpoint.log_message = match r.string()? {
+				"" => None,
+				source => Some(Template::parse(source).map_err(|_| SnapshotError::Invalid("log message"))?),
+			};
+		}
+		breakpoints.push((point, breakpoint));
+	}
+
+	let (interrupts_enabled, pending_interrupts) = if version >= 3 {
+		(r.bool()?, r.u32()?)
+	}
+	else {
+		(false, 0)
+	};
+
+	let mut permissions = PagePermissions::new();
+	if version >= 9 {
+		let run_count = r.u32()?;
+		let mut previous = None;
+		for _ in 0..run_count {
+			let page = r.u32()?;
+			let bits = r.u8()? as u32;
+			if page >= MAX_PAGES || previous.is_some_and(|previous| page <= previous) {
+				return Err(SnapshotError::Invalid("permission runs"));
+			}
+			permissions.set(page, MAX_PAGES - 1, bits);
+			previous = Some(page);
+		}
+	}
+	else if version >= 4 {
+		let page_count = r.u32()?;
+		for page in 0..page_count {
+			let bits = r.u8()? as u32;
+			permissions.set(page, page, bits);
+		}
+	}
+
+	let heap = if version >= 6 { Heap::load(&mut r)? } else { Heap::new() };
+
+	let (supervisor, paging, page_table_base, page_table_length) = if version >= 7 {
+		(r.bool()?, r.bool()?, r.u32()?, r.u32()?)
+	}
+	else {
+		(true, false, 0, 0)
+	};
+
+	let mut cores = Vec::new();
+	let mut core_id = 0;
+	if version >= 8 {
+		let count = r.u32()?;
+		core_id = r.u32()?;
+		if core_id >= count {
+			return Err(SnapshotError::Invalid("running core"));
+		}
+		for id in 0..count {
+			if id == core_id {
+				let mut running = Core::new(1, STACK_BASE);
+				running.halted = r.bool()?;
+				cores.push(running);
+			}
+			else {
+				cores.push(Core::load(&mut r)?);
+			}
+		}
+	}
+	else {
+		cores.push(Core::new(1, STACK_BASE));
+	}
+
+	let breakpoint_stop = if version >= 10 {
+		let stopped = r.bool()?;
+		let address = r.u32()?;
+		if stopped { Some(address) } else { None }
+	}
+	else if status == ProcessorStatus::Paused && breakpoints.iter().any(|&(point, _)| point == next) {
+		Some(next)
+	}
+	else {
+		None
+	};
+
+	let p: &mut Processor = &mut program.Processor;
+	p.bus = bus;
+	p.next = next;
+	p.status = status;
+	p.fault = fault;
+	p.fault_address = fault_address;
+	p.stack_pointer = stack_pointer;
+	p.stack_base = stack_base;
+	p.stack_limit = stack_limit;
+	p.frames = frames;
+	p.alu = alu;
+	p.memory = memory;
+	p.interrupts_enabled = interrupts_enabled;
+	p.pending_interrupts = pending_interrupts;
+	p.permissions = permissions;
+	p.heap = heap;
+	p.supervisor = supervisor;
+	p.mmu.paging = paging;
+	p.mmu.page_table_base = page_table_base;
+	p.mmu.page_table_length = page_table_length;
+	p.mmu.flush();
+	p.perStepTrap = None;
+	// the watchpoints are not in the snapshot, neither is what hit them
+	p.watch_hit = None;
+	p.watch_triggered = false;
+	p.core_id = core_id;
+	program.Cores = cores;
+
+	program.DoBreakpoints = do_breakpoints;
+	program.Breakpoints = breakpoints.into_iter().collect();
+	program.BreakpointStop = breakpoint_stop;
+	program.History.clear();
+	return Ok(());
+}
+
+// the stack lives from stack_limit (exclusive) up to stack_base, the stack pointer is in between
+pub(crate) fn check_stack(stack_pointer: location, stack_base: location, stack_limit: location) -> Result<(), SnapshotError> {
+	if stack_limit > stack_base || stack_pointer < stack_limit || stack_pointer > stack_base {
+		return Err(SnapshotError::Invalid("stack"));
+	}
+	return Ok(());
+}
+
+// frame count, then 4 words per frame
+pub(crate) fn write_frames(w: &mut Writer, frames: &[CallFrame]) {
+	w.u32(frames.len() as u32);
+	for frame in frames.iter() {
+		w.u32(frame.call_site);
+		w.u32(frame.target);
+		w.u32(frame.return_address);
+		w.u32(frame.stack_pointer);
+	}
+}
+
+pub(crate) fn read_frames(r: &mut Reader) -> Result<Vec<CallFrame>, SnapshotError> {
+	let frame_count = r.u32()?;
+	let mut frames = Vec::new();
+	for _ in 0..frame_count {
+		frames.push(CallFrame {
+			call_site: r.u32()?,
+			target: r.u32()?,
+			return_address: r.u32()?,
+			stack_pointer: r.u32()?,
+		});
+	}
+	return Ok(frames);
+}
+
+pub(crate) fn write_alu(w: &mut Writer, alu: &ALU) {
+	w.u32(alu.value_a_int as u32);
+	w.u32(alu.value_b_int as u32);
+	w.u32(alu.value_a_float.to_bits());
+	w.u32(alu.value_b_float.to_bits());
+	w.u8(alu.compare_result as u8);
+	w.u8(compare_mode_to_u8(&alu.compare_mode));
+	w.u32(alu.hi);
+	w.u32(alu.lo);
+	w.u8(match alu.mode {
+		ALUMode::int => 0,
+		ALUMode::float => 1,
+	});
+}
+
+pub(crate) fn read_alu(r: &mut Reader) -> Result<ALU, SnapshotError> {
+	let mut alu = ALU::new();
+	alu.value_a_int = r.u32()? as i32;
+	alu.value_b_int = r.u32()? as i32;
+	alu.value_a_float = f32::from_bits(r.u32()?);
+	alu.value_b_float = f32::from_bits(r.u32()?);
+	alu.compare_result = r.bool()?;
+	alu.compare_mode = compare_mode_from_u8(r.u8()?).ok_or(SnapshotError::Invalid("compare mode"))?;
+	alu.hi = r.u32()?;
+	alu.lo = r.u32()?;
+	alu.mode = match r.u8()? {
+		0 => ALUMode::int,
+		1 => ALUMode::float,
+		_ => return Err(SnapshotError::Invalid("ALU mode")),
+	};
+	return Ok(alu);
+}
+
+fn read_memory(r: &mut Reader) -> Result<Memory, SnapshotError> {
+	let page_count = r.u32()?;
+	if page_count > MAX_PAGES {
+		return Err(SnapshotError::Invalid("memory size"));
+	}
+	let mut memory = Memory::new(page_count);
+	memory.set_unmapped_access(UnmappedAccess::from_code(r.u8()? as u32).ok_or(SnapshotError::Invalid("unmapped access"))?);
+	let stored = r.u32()?;
+	for _ in 0..stored {
+		let number = r.u32()?;
+		let used = r.u32()? as usize;
+		if used > PAGE_SIZE as usize {
+			return Err(SnapshotError::Invalid("memory page"));
+		}
+		let page = memory.page_mut(number).ok_or(SnapshotError::Invalid("memory page"))?;
+		for value in page[..used].iter_mut() {
+			*value = r.u32()?;
+		}
+	}
+	return Ok(memory);
+}
+
+fn read_memory_blocks(r: &mut Reader) -> Result<Memory, SnapshotError> {
+	let block_pages = MEM_SIZE as u32 / PAGE_SIZE;
+	let block_count = r.u32()?;
+	if block_count > MAX_PAGES / block_pages {
+		return Err(SnapshotError::Invalid("memory size"));
+	}
+	let mut memory = Memory::new(block_count * block_pages);
+	for block in 0..block_count {
+		let used = r.u32()? as usize;
+		if used > MEM_SIZE {
+			return Err(SnapshotError::Invalid("memory block"));
+		}
+		for offset in 0..used {
+			let value = r.u32()?;
+			if value != 0 {
+				memory.write(block * MEM_SIZE as u32 + offset as u32, value);
+			}
+		}
+	}
+	return Ok(memory);
+}
+
+fn status_to_u8(status: &ProcessorStatus) -> u8 {
+	return match status {
+		ProcessorStatus::Paused => 0,
+		ProcessorStatus::Halted => 1,
+		ProcessorStatus::NotStarted => 2,
+		ProcessorStatus::Running => 3,
+		ProcessorStatus::Empty => 4,
+		ProcessorStatus::Faulted => 5,
+	};
+}
+
+fn status_from_u8(v: u8) -> Option<ProcessorStatus> {
+	return match v {
+		0 => Some(ProcessorStatus::Paused),
+		1 => Some(ProcessorStatus::Halted),
+		2 => Some(ProcessorStatus::NotStarted),
+		3 => Some(ProcessorStatus::Running),
+		4 => Some(ProcessorStatus::Empty),
+		5 => Some(ProcessorStatus::Faulted),
+		_ => None,
+	};
+}
+
+fn compare_mode_to_u8(mode: &ALUCompareMode) -> u8 {
+	return match mode {
+		ALUCompareMode::equal => 0,
+		ALUCompareMode::not_equal => 1,
+		ALUCompareMode::greater_than => 2,
+		ALUCompareMode::greater_than_or_equal => 3,
+		ALUCompareMode::lesser_than => 4,
+		ALUCompareMode::lesser_than_or_equal => 5,
+	};
+}
+
+// same numbering as the operand of opcode 29
+fn compare_mode_from_u8(v: u8) -> Option<ALUCompareMode> {
+	return match v {
+		0 => Some(ALUCompareMode::equal),
+		1 => Some(ALUCompareMode::not_equal),
+		2 => Some(ALUCompareMode::greater_than),
+		3 => Some(ALUCompareMode::greater_than_or_equal),
+		4 => Some(ALUCompareMode::lesser_than),
+		5 => Some(ALUCompareMode::lesser_than_or_equal),
+		_ => None,
+	};
+}
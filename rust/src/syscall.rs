@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{storage, location, jsint, js_syscall, MEM_SIZE};
+use crate::memory::Memory;
+
+// syscall codes, see SyscallsEnum on the JS side
+pub const CREATE_BUFFER: storage = 1;
+pub const SET_BUFFER_HEAD: storage = 2;
+pub const SET_BUFFER_LENGTH: storage = 3;
+pub const SET_BUFFER_TYPE: storage = 4;
+pub const DELETE_BUFFER: storage = 5;
+pub const SLEEP: storage = 20;
+pub const ALERT: storage = 30;
+
+// buffer types, see BufferType on the JS side
+pub const BUFFER_INPUT_KEY: storage = 1;
+pub const BUFFER_INPUT_TERMINAL: storage = 2;
+pub const BUFFER_OUTPUT_PALETTE: storage = 3;
+pub const BUFFER_OUTPUT_SCREEN: storage = 4;
+pub const BUFFER_OUTPUT_SCREEN_SIZE: storage = 5;
+pub const BUFFER_OUTPUT_FILE: storage = 6;
+pub const BUFFER_INPUT_FILE: storage = 7;
+
+pub const RESULT_OK: i32 = 0;
+pub const RESULT_ERROR: i32 = 1;
+
+// whatever the processor talks to when it executes a syscall (opcode 21)
+pub trait SyscallHost: Send {
+	// the returned value is put on the bus
+	fn syscall(&mut self, code: storage, param: storage, memory: &mut SyscallMemory) -> i32;
+
+	// called between frames, when the program pauses.
+	// input buffers are written into memory and output buffers read out of it
+	fn refresh(&mut self, _memory: &mut SyscallMemory) {}
+
+	// true once there is no more input for the program, like stdin at EOF.
+	// a program waiting for input would wait forever
+	fn input_closed(&self) -> bool {
+		return false;
+	}
+}
+
+// the view of machine memory handed to syscall hosts
+pub struct SyscallMemory<'a> {
+	memory: &'a mut Memory,
+}
+
+impl<'a> SyscallMemory<'a> {
+	pub(crate) fn new(memory: &'a mut Memory) -> SyscallMemory<'a> {
+		SyscallMemory {
+			memory,
+		}
+	}
+
+	// unmapped addresses read as 0
+	pub fn read(&self, location: location) -> storage {
+		return self.memory.read(location);
+	}
+
+	// returns false if the address is not mapped
+	pub fn write(&mut self, location: location, value: storage) -> bool {
+		return self.memory.write(location, value);
+	}
+
+	// reads a zero terminated string, one byte per memory cell
+	pub fn read_string(&self, location: location) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		let mut l = location;
+		loop {
+			let value = self.read(l);
+			if value == 0 || bytes.len() >= MEM_SIZE {
+				break;
+			}
+			bytes.push(value as u8);
+			l = l.wrapping_add(1);
+		}
+		return bytes;
+	}
+}
+
+// forwards syscalls to the js_syscall import. the JS side reads and writes
+// its buffers on its own, so there is nothing to refresh
+pub struct JsSyscallHost;
+
+impl SyscallHost for JsSyscallHost {
+	fn syscall(&mut self, code: storage, param: storage, _memory: &mut SyscallMemory) -> i32 {
+		return js_syscall(code as jsint, param as jsint);
+	}
+}
+
+struct Buffer {
+	head: Option<location>,
+	length: Option<storage>,
+	kind: Option<storage>,
+	loaded: bool,
+}
+
+impl Buffer {
+	fn is_initialized(&self) -> bool {
+		return self.head.is_some() && self.length.is_some() && self.kind.is_some();
+	}
+}
+
+// handles syscalls the way the browser does, using the process' stdio and files:
+//	Alert prints the zero terminated string at the bus address to stdout
+//	terminal input buffers are filled from stdin whenever the program empties them,
+//	until stdin reaches EOF
+//	file input buffers are filled from the input file
+//	file output buffers are written to the output file on every refresh
+//	Sleep blocks for the given number of milliseconds
+pub struct NativeSyscallHost {
+	buffers: BTreeMap<storage, Buffer>,
+	buffer_create_id: storage,
+	terminal_pending: Vec<u8>,
+	stdin_closed: bool,
+	input_file: Vec<u8>,
+	output_path: Option<PathBuf>,
+}
+
+impl Default for NativeSyscallHost {
+	fn default() -> NativeSyscallHost {
+		return NativeSyscallHost::new();
+	}
+}
+
+impl NativeSyscallHost {
+	pub fn new() -> NativeSyscallHost {
+		NativeSyscallHost {
+			buffers: BTreeMap::new(),
+			buffer_create_id: 0,
+			terminal_pending: Vec::new(),
+			stdin_closed: false,
+			input_file: Vec::new(),
+			output_path: None,
+		}
+	}
+
+	pub fn with_input_file(mut self, contents: Vec<u8>) -> NativeSyscallHost {
+		self.input_file = contents;
+		return self;
+	}
+
+	pub fn with_output_file(mut self, path: PathBuf) -> NativeSyscallHost {
+		self.output_path = Some(path);
+		return self;
+	}
+
+	// buffer properties can only be set once, on the most recently created buffer
+	fn initialize_newest(&mut self, field: impl Fn(&mut Buffer) -> &mut Option<storage>, value: storage) -> i32 {
+		return match self.buffers.get_mut(&self.buffer_create_id) {
+			Some(buffer) => {
+				let slot = field(buffer);
+				if slot.is_some() {
+					return RESULT_ERROR;
+				}
+				*slot = Some(value);
+				RESULT_OK
+			},
+			None => RESULT_ERROR,
+		};
+	}
+
+	fn load_input_files(&mut self, memory: &mut SyscallMemory) {
+		for buffer in self.buffers.values_mut() {
+			if buffer.loaded || !buffer.is_initialized() || buffer.kind != Some(BUFFER_INPUT_FILE) {
+				continue;
+			}
+			let head = buffer.head.unwrap();
+			let length = buffer.length.unwrap() as usize;
+			for (i, byte) in self.input_file.iter().take(length).enumerate() {
+				memory.write(head.wrapping_add(i as location), *byte as storage);
+			}
+			buffer.loaded = true;
+		}
+	}
+
+	// a terminal buffer is considered empty when its first position is 0
+	fn fill_terminal_buffers(&mut self, memory: &mut SyscallMemory) {
+		for buffer in self.buffers.values() {
+			if !buffer.is_initialized() || buffer.kind != Some(BUFFER_INPUT_TERMINAL) {
+				continue;
+			}
+			let head = buffer.head.unwrap();
+			if memory.read(head) != 0 {
+				continue;
+			}
+			if self.terminal_pending.is_empty() {
+				if self.stdin_closed {
+					continue;
+				}
+				let mut line = String::new();
+				match io::stdin().lock().read_line(&mut line) {
+					// EOF, or stdin cannot be read. either way nothing more will come
+					Ok(0) | Err(_) => {
+						self.stdin_closed = true;
+						continue;
+					},
+					Ok(_) => {},
+				}
+				self.terminal_pending.extend(line.bytes());
+			}
+			let length = buffer.length.unwrap() as usize;
+			let count = length.min(self.terminal_pending.len());
+			for (i, byte) in self.terminal_pending.drain(..count).enumerate() {
+				memory.write(head.wrapping_add(i as location), byte as storage);
+			}
+		}
+	}
+
+	fn write_output_file(&self, memory: &mut SyscallMemory) {
+		let path = match self.output_path {
+			Some(ref path) => path,
+			None => return,
+		};
+		let mut contents = Vec::new();
+		for buffer in self.buffers.values() {
+			if !buffer.is_initialized() || buffer.kind != Some(BUFFER_OUTPUT_FILE) {
+				continue;
+			}
+			let head = buffer.head.unwrap();
+			for i in 0..buffer.length.unwrap() {
+				let value = memory.read(head.wrapping_add(i));
+				if value == 0 {
+					break;
+				}
+				contents.push(value as u8);
+			}
+		}
+		if let Err(e) = fs::write(path, contents) {
+			eprintln!("could not write `{}`: {}", path.display(), e);
+		}
+	}
+}
+
+impl SyscallHost for NativeSyscallHost {
+	fn syscall(&mut self, code: storage, param: storage, memory: &mut SyscallMemory) -> i32 {
+		let result = match code {
+			CREATE_BUFFER => {
+				self.buffer_create_id += 1;
+				self.buffers.insert(self.buffer_create_id, Buffer {
+					head: None,
+					length: None,
+					kind: None,
+					loaded: false,
+				});
+				RESULT_OK
+			},
+			SET_BUFFER_HEAD => self.initialize_newest(|buffer| &mut buffer.head, param),
+			SET_BUFFER_LENGTH => self.initialize_newest(|buffer| &mut buffer.length, param),
+			SET_BUFFER_TYPE => self.initialize_newest(|buffer| &mut buffer.kind, param),
+			DELETE_BUFFER => {
+				match self.buffers.remove(&param) {
+					Some(_) => RESULT_OK,
+					None => RESULT_ERROR,
+				}
+			},
+			SLEEP => {
+				thread::sleep(Duration::from_millis(param as u64));
+				RESULT_OK
+			},
+			ALERT => {
+				let text = memory.read_string(param);
+				let stdout = io::stdout();
+				let mut out = stdout.lock();
+				let _ = out.write_all(&text);
+				let _ = out.write_all(b"\n");
+				let _ = out.flush();
+				RESULT_OK
+			},
+			_ => RESULT_ERROR,
+		};
+
+		self.load_input_files(memory);
+		return result;
+	}
+
+	fn refresh(&mut self, memory: &mut SyscallMemory) {
+		self.fill_terminal_buffers(memory);
+		self.write_output_file(memory);
+	}
+
+	fn input_closed(&self) -> bool {
+		return self.stdin_closed;
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyscallRecord {
+	pub code: storage,
+	pub param: storage,
+	// for Alert, the string that would have been shown
+	pub text: Option<String>,
+}
+
+// records every syscall instead of acting on it, for tests.
+// clones share the same log, so keep one to inspect after the host is moved into a machine
+#[derive(Clone)]
+pub struct RecordingSyscallHost {
+	calls: Arc<Mutex<Vec<SyscallRecord>>>,
+	responses: Arc<Mutex<HashMap<storage, i32>>>,
+	refreshes: Arc<Mutex<usize>>,
+}
+
+impl Default for RecordingSyscallHost {
+	fn default() -> RecordingSyscallHost {
+		return RecordingSyscallHost::new();
+	}
+}
+
+impl RecordingSyscallHost {
+	pub fn new() -> RecordingSyscallHost {
+		RecordingSyscallHost {
+			calls: Arc::new(Mutex::new(Vec::new())),
+			responses: Arc::new(Mutex::new(HashMap::new())),
+			refreshes: Arc::new(Mutex::new(0)),
+		}
+	}
+
+	// syscalls with this code return the given result, the default is RESULT_OK
+	pub fn respond(&self, code: storage, result: i32) {
+		self.responses.lock().unwrap().insert(code, result);
+	}
+
+	pub fn calls(&self) -> Vec<SyscallRecord> {
+		return self.calls.lock().unwrap().clone();
+	}
+
+	pub fn refreshes(&self) -> usize {
+		return *self.refreshes.lock().unwrap();
+	}
+}
+
+impl SyscallHost for RecordingSyscallHost {
+	fn syscall(&mut self, code: storage, param: storage, memory: &mut SyscallMemory) -> i32 {
+		let text = match code {
+			ALERT => Some(String::from_utf8_lossy(&memory.read_string(param)).into_owned()),
+			_ => None,
+		};
+		self.calls.lock().unwrap().push(SyscallRecord {
+			code,
+			param,
+			text,
+		});
+		return *self.responses.lock().unwrap().get(&code).unwrap_or(&RESULT_OK);
+	}
+
+	fn refresh(&mut self, _memory: &mut SyscallMemory) {
+		*self.refreshes.lock().unwrap() += 1;
+	}
+}
@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+
+use crate::{storage, location, Program};
+use crate::snapshot::Writer;
+
+// how many instructions are kept unless changed with r_SetTraceLimit
+pub const DEFAULT_TRACE_LIMIT: usize = 1 << 16;
+
+// binary trace layout, all numbers little endian:
+//	magic "RTRC", version u32, record count
+//	then for each record: address, opcode, param count u8, the params,
+//	bus before, bus after, hi, lo, write count, then (address, old, new) for each write
+const MAGIC: &[u8; 4] = b"RTRC";
+const VERSION: u32 = 1;
+
+// one executed instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+	pub address: location,
+	pub opcode: storage,
+	pub params: Vec<storage>,
+	pub bus_before: storage,
+	pub bus_after: storage,
+	// ALU hi and lo after the instruction
+	pub hi: storage,
+	pub lo: storage,
+	// (address, old value, new value), in the order they were written
+	pub writes: Vec<(location, storage, storage)>,
+}
+
+// records instructions while enabled. bounded, the oldest records are dropped first
+pub struct Tracer {
+	enabled: bool,
+	// inclusive address ranges, an instruction is traced if it is inside any of them.
+	// no filters traces everything
+	filters: Vec<(location, location)>,
+	records: VecDeque<TraceRecord>,
+	limit: usize,
+}
+
+impl Tracer {
+	pub fn new() -> Tracer {
+		Tracer {
+			enabled: false,
+			filters: Vec::new(),
+			records: VecDeque::new(),
+			limit: DEFAULT_TRACE_LIMIT,
+		}
+	}
+
+	pub fn start(&mut self) {
+		self.enabled = true;
+	}
+
+	pub fn stop(&mut self) {
+		self.enabled = false;
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		return self.enabled;
+	}
+
+	pub fn clear(&mut self) {
+		self.records.clear();
+	}
+
+	pub fn add_filter(&mut self, start: location, end: location) {
+		self.filters.push((start.min(end), start.max(end)));
+	}
+
+	pub fn clear_filters(&mut self) {
+		self.filters.clear();
+	}
+
+	pub fn set_limit(&mut self, limit: usize) {
+		self.limit = limit;
+		while self.records.len() > limit {
+			self.records.pop_front();
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		return self.records.len();
+	}
+
+	pub fn records(&self) -> impl Iterator<Item = &TraceRecord> {
+		return self.records.iter();
+	}
+
+	// whether the instruction at the address would be recorded
+	pub(crate) fn wants(&self, address: location) -> bool {
+		return self.enabled && self.limit > 0
+			&& (self.filters.is_empty() || self.filters.iter().any(|&(start, end)| start <= address && address <= end));
+	}
+
+	fn push(&mut self, record: TraceRecord) {
+		if self.records.len() >= self.limit {
+			self.records.pop_front();
+		}
+		self.records.push_back(record);
+	}
+
+	pub fn export_binary(&self) -> Vec<u8> {
+		let mut w = Writer { bytes: Vec::new() };
+		w.bytes.extend_from_slice(MAGIC);
+		w.u32(VERSION);
+		w.u32(self.records.len() as u32);
+		for record in self.records.iter() {
+			w.u32(record.address);
+			w.u32(record.opcode);
+			w.u8(record.params.len() as u8);
+			for param in record.params.iter() {
+				w.u32(*param);
+			}
+			w.u32(record.bus_before);
+			w.u32(record.bus_after);
+			w.u32(record.hi);
+			w.u32(record.lo);
+			w.u32(record.writes.len() as u32);
+			for &(address, old, new) in record.writes.iter() {
+				w.u32(address);
+				w.u32(old);
+				w.u32(new);
+			}
+		}
+		return w.bytes;
+	}
+
+	// one JSON object per line, e.g.
+	// {"address":3,"opcode":2,"params":[30],"bus_before":5,"bus_after":5,"hi":0,"lo":0,"writes":[[30,0,5]]}
+	pub fn export_json(&self) -> String {
+		let mut out = String::new();
+		for record in self.records.iter() {
+			let params: Vec<String> = record.params.iter().map(|p| p.to_string()).collect();
+			let writes: Vec<String> = record.writes.iter()
+				.map(|&(address, old, new)| format!("[{},{},{}]", address, old, new))
+				.collect();
+			let _ = writeln!(out,
+				"{{\"address\":{},\"opcode\":{},\"params\":[{}],\"bus_before\":{},\"bus_after\":{},\"hi\":{},\"lo\":{},\"writes\":[{}]}}",
+				record.address, record.opcode, params.join(","), record.bus_before, record.bus_after,
+				record.hi, record.lo, writes.join(","));
+		}
+		return out;
+	}
+}
+
+// what has to be read before the instruction runs
+pub struct TraceStart {
+	address: location,
+	opcode: storage,
+	bus: storage,
+}
+
+// call before the processor steps. returns None if the instruction is not traced
+pub(crate) fn begin_step(program: &mut Program) -> Option<TraceStart> {
+	let p = &mut program.Processor;
+	// taking an interrupt is not an instruction, it is left out
+	if !program.Tracer.wants(p.next) || p.next_interrupt().is_some() {
+		return None;
+	}
+	// the tracer needs the old values of written memory, same as the history
+	p.perStepRecordWrites = true;
+	return Some(TraceStart {
+		address: p.next,
+		opcode: p._peek_memory_loc(p.next),
+		bus: p.bus,
+	});
+}
+
+// writes are the old values collected by the processor during the step
+pub(crate) fn end_step(program: &mut Program, start: Option<TraceStart>, writes: &[(location, storage)]) {
+	let start = match start {
+		Some(start) => start,
+		None => return,
+	};
+	let p = &program.Processor;
+
+	// a write's new value is the old value of the next write to the same address,
+	// or what is in memory now for the last one
+	let mut traced_writes = Vec::with_capacity(writes.len());
+	for (i, &(address, old)) in writes.iter().enumerate() {
+		let new = writes[i + 1..].iter()
+			.find(|&&(later, _)| later == address)
+			.map_or_else(|| p._peek_memory_loc(address), |&(_, value)| value);
+		traced_writes.push((address, old, new));
+	}
+
+	// the params as they were decoded, even if the instruction overwrote them
+	let params = p.perStepParams.iter().take(p.perStepParamPointer as usize).cloned().collect();
+
+	let record = TraceRecord {
+		address: start.address,
+		opcode: start.opcode,
+		params,
+		bus_before: start.bus,
+		bus_after: p.bus,
+		hi: p.alu.hi,
+		lo: p.alu.lo,
+		writes: traced_writes,
+	};
+	program.Tracer.push(record);
+}
@@ -0,0 +1,88 @@
+use crate::{location, storage, Processor, CallFrame, Fault};
+use crate::interrupt::INTERRUPT_TABLE;
+
+// the causes a trap can have, each with an entry in the vector table.
+// a page fault returns to the instruction that faulted, so it runs again
+pub const TRAP_PAGE_FAULT: u32 = 0;
+// opcode 49, returns to the instruction after it
+pub const TRAP_SYSTEM_CALL: u32 = 1;
+// an interrupt taken in user mode, the detail is the line. returns to the interrupted instruction.
+// in supervisor mode interrupts go through the interrupt table instead
+pub const TRAP_INTERRUPT: u32 = 2;
+pub const TRAP_CAUSES: u32 = 8;
+// one handler address per cause, just below the interrupt table. a cause whose entry is 0
+// has no handler, and the trap becomes a fault instead.
+// the vectors, and the two words below them, are physical addresses
+pub const TRAP_VECTORS: location = INTERRUPT_TABLE - TRAP_CAUSES;
+// written when a trap is taken: the cause, and its detail. for a page fault the detail
+// is the virtual address
+pub const TRAP_CAUSE: location = TRAP_VECTORS - 2;
+pub const TRAP_ADDRESS: location = TRAP_VECTORS - 1;
+
+// bits of the word pushed above the return address, the mode opcode 48 goes back to
+const MODE_SUPERVISOR: u32 = 1;
+const MODE_INTERRUPTS: u32 = 2;
+
+// the fault raised instead when a trap cannot be taken
+fn trap_fault(cause: u32, detail: storage) -> Fault {
+	if cause == TRAP_PAGE_FAULT {
+		return Fault::PageFault(detail);
+	}
+	return Fault::NoTrapHandler(cause);
+}
+
+impl Processor {
+	// taken at the end of the step that trapped. the handler runs in supervisor mode with
+	// interrupts disabled, with the return address and then the old mode pushed.
+	// a page fault while pushing those is a double fault, and faults
+	pub(crate) fn enter_trap(&mut self, return_address: location, cause: u32, detail: storage) {
+		let handler = self._peek_memory_loc(TRAP_VECTORS + cause);
+		if handler == 0 {
+			self.raise(trap_fault(cause, detail));
+			return;
+		}
+		let mut mode = 0;
+		if self.supervisor {
+			mode |= MODE_SUPERVISOR;
+		}
+		if self.interrupts_enabled {
+			mode |= MODE_INTERRUPTS;
+		}
+		self.supervisor = true;
+		self._write_physical(TRAP_CAUSE, cause);
+		self._write_physical(TRAP_ADDRESS, detail);
+		if !self.push(return_address) || !self.push(mode) {
+			if let Some((cause, detail)) = self.perStepTrap.take() {
+				self.raise(trap_fault(cause, detail));
+			}
+			return;
+		}
+		self.interrupts_enabled = false;
+		self.frames.push(CallFrame {
+			call_site: return_address,
+			target: handler,
+			return_address,
+			stack_pointer: self.stack_pointer,
+		});
+		self.next = handler;
+	}
+
+	// opcode 48
+	pub(crate) fn return_from_trap(&mut self) {
+		let stack_pointer = self.stack_pointer;
+		let popped = self.pop().and_then(|mode| self.pop().map(|address| (mode, address)));
+		let (mode, return_address) = match popped {
+			Some(popped) => popped,
+			None => {
+				// so the instruction can run again after a page fault
+				self.stack_pointer = stack_pointer;
+				return;
+			},
+		};
+		self.frames.pop();
+		self.supervisor = mode & MODE_SUPERVISOR != 0;
+		self.interrupts_enabled = mode & MODE_INTERRUPTS != 0;
+		self.jump(return_address);
+		self.dontMoveParamPointer();
+	}
+}
@@ -0,0 +1,94 @@
+use crate::{storage, location, Processor};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+	Read,
+	Write,
+	ReadWrite,
+}
+
+impl WatchKind {
+	// 1 read, 2 write, 3 read/write, as used by the r_ exports
+	pub fn from_code(code: u32) -> Option<WatchKind> {
+		return match code {
+			1 => Some(WatchKind::Read),
+			2 => Some(WatchKind::Write),
+			3 => Some(WatchKind::ReadWrite),
+			_ => None,
+		};
+	}
+
+	pub fn code(&self) -> u32 {
+		return match self {
+			WatchKind::Read => 1,
+			WatchKind::Write => 2,
+			WatchKind::ReadWrite => 3,
+		};
+	}
+
+	fn matches(&self, access: WatchKind) -> bool {
+		return *self == WatchKind::ReadWrite || *self == access;
+	}
+}
+
+// a data breakpoint over the addresses start..=end
+#[derive(Debug, Clone, Copy)]
+pub struct Watchpoint {
+	pub id: u32,
+	pub start: location,
+	pub end: location,
+	pub kind: WatchKind,
+}
+
+// the access that paused the machine.
+// for reads, old and new are both the value read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+	pub watchpoint: u32,
+	pub address: location,
+	pub access: WatchKind,
+	pub old: storage,
+	pub new: storage,
+	// address of the instruction that made the access
+	pub instruction: location,
+}
+
+impl Processor {
+	pub(crate) fn add_watchpoint(&mut self, start: location, end: location, kind: WatchKind) -> u32 {
+		self.next_watchpoint_id += 1;
+		let id = self.next_watchpoint_id;
+		self.watchpoints.push(Watchpoint {
+			id,
+			start: start.min(end),
+			end: start.max(end),
+			kind,
+		});
+		return id;
+	}
+
+	pub(crate) fn remove_watchpoint(&mut self, id: u32) -> bool {
+		let before = self.watchpoints.len();
+		self.watchpoints.retain(|w| w.id != id);
+		return self.watchpoints.len() != before;
+	}
+
+	// called from the memory accessors. only the first hit of a step is kept,
+	// the processor pauses once the instruction has finished
+	pub(crate) fn check_watchpoints(&mut self, address: location, access: WatchKind, old: storage, new: storage) {
+		if self.watchpoints.is_empty() || self.perStepWatchHit.is_some() {
+			return;
+		}
+		let hit = self.watchpoints.iter()
+			.find(|w| w.kind.matches(access) && w.start <= address && address <= w.end);
+		if let Some(watchpoint) = hit {
+			self.perStepWatchHit = Some(WatchHit {
+				watchpoint: watchpoint.id,
+				address,
+				access,
+				old,
+				new,
+				instruction: self.next,
+			});
+		}
+	}
+}
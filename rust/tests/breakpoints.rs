@@ -0,0 +1,118 @@
+extern crate rust_asm;
+
+use rust_asm::{Machine, RunReason};
+
+mod common;
+
+fn load(code: &[u32]) -> Machine {
+	let mut machine = common::load(code);
+	machine.enable_breakpoints();
+	return machine;
+}
+
+// 1: 1 -> bus, 3: jump to the address on the bus
+fn endless_loop() -> Machine {
+	return load(&[24, 1, 13]);
+}
+
+#[test]
+fn condition_decides_whether_to_stop() {
+	// 1: 1 -> bus, 3: 2 -> bus, 5: 3 -> bus, 7: halt
+	let mut machine = load(&[24, 1, 24, 2, 24, 3, 100]);
+	assert!(machine.set_breakpoint_condition(5, "bus == 2 && mem[0x2] > 0"));
+	assert!(machine.set_breakpoint_condition(7, "bus != 3 || compare"));
+	assert_eq!(machine.run_budget(None).1, RunReason::Breakpoint);
+	assert_eq!(machine.instruction_pointer(), 5);
+
+	assert_eq!(machine.run_budget(None).1, RunReason::Halted);
+	assert_eq!(machine.breakpoint_hit_count(5), 1);
+	assert_eq!(machine.breakpoint_hit_count(7), 0);
+}
+
+#[test]
+fn ignore_count_skips_the_first_hits() {
+	let mut machine = endless_loop();
+	machine.set_breakpoint(3);
+	assert!(machine.set_breakpoint_ignore_count(3, 2));
+	assert!(!machine.set_breakpoint_ignore_count(1, 2));
+
+	assert_eq!(machine.run_budget(Some(100)), (5, RunReason::Breakpoint));
+	assert_eq!(machine.breakpoint_hit_count(3), 3);
+}
+
+#[test]
+fn logpoints_log_instead_of_stopping() {
+	let mut machine = endless_loop();
+	assert!(machine.set_logpoint(3, "bus={bus} first={mem[0x1]} {{literal}}"));
+	assert_eq!(machine.run_budget(Some(6)), (6, RunReason::BudgetExhausted));
+	assert_eq!(machine.take_breakpoint_log(), vec!["bus=1 first=24 {literal}"; 3]);
+	assert!(machine.take_breakpoint_log().is_empty());
+
+	assert!(machine.set_logpoint(3, ""));
+	assert_eq!(machine.run_budget(Some(6)).1, RunReason::Breakpoint);
+}
+
+#[test]
+fn resuming_stops_on_a_breakpoint_something_else_paused_on() {
+	// 1: pause, 2: halt
+	let mut machine = load(&[23, 100]);
+	machine.set_breakpoint(2);
+	assert_eq!(machine.run_budget(None), (1, RunReason::Paused));
+	assert_eq!(machine.run_budget(None), (0, RunReason::Breakpoint));
+	assert_eq!(machine.run_budget(None), (1, RunReason::Halted));
+
+	// a time slice that ends in front of it
+	let mut machine = endless_loop();
+	machine.set_breakpoint(3);
+	assert_eq!(machine.run_budget(Some(1)), (1, RunReason::BudgetExhausted));
+	assert_eq!(machine.run_budget(Some(10)), (0, RunReason::Breakpoint));
+	assert_eq!(machine.run_budget(Some(10)), (2, RunReason::Breakpoint));
+}
+
+#[test]
+fn stepping_onto_a_breakpoint_stops_the_next_run_there() {
+	// 1: 1 -> bus, 3: 2 -> bus, 5: halt
+	let mut machine = load(&[24, 1, 24, 2, 100]);
+	machine.set_breakpoint(3);
+	machine.step_into();
+	assert_eq!(machine.instruction_pointer(), 3);
+	assert_eq!(machine.run_budget(None), (0, RunReason::Breakpoint));
+	assert_eq!(machine.run_budget(None), (2, RunReason::Halted));
+}
+
+#[test]
+fn bad_conditions_are_rejected() {
+	let mut machine = endless_loop();
+	assert_eq!(machine.try_set_breakpoint_condition(3, "bus ==").unwrap_err().position, 6);
+	assert_eq!(machine.try_set_breakpoint_condition(3, "flags > 1").unwrap_err().position, 0);
+	assert!(machine.try_set_logpoint(3, "{bus").is_err());
+	assert!(!machine.is_breakpoint(3));
+
+	// nesting is limited, or evaluating it could overflow the stack
+	let deep = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+	assert_eq!(machine.try_set_breakpoint_condition(3, &deep).unwrap_err().message, "nested too deep");
+	let long = format!("1{}", " && 1".repeat(100_000));
+	assert_eq!(machine.try_set_breakpoint_condition(3, &long).unwrap_err().message, "nested too deep");
+	let nested = format!("{}bus{} == 1", "mem[".repeat(30), "]".repeat(30));
+	assert!(machine.try_set_breakpoint_condition(3, &nested).is_ok());
+}
+
+#[test]
+fn snapshots_keep_breakpoint_settings() {
+	let mut machine = endless_loop();
+	machine.set_breakpoint_condition(3, "bus == 1");
+	machine.set_breakpoint_ignore_count(3, 1);
+	machine.set_logpoint(1, "at {ip}");
+	machine.run_budget(Some(100));
+	let snapshot = machine.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	let breakpoint = restored.breakpoint(3).unwrap();
+	assert_eq!(breakpoint.condition.as_ref().unwrap().source(), "bus == 1");
+	assert_eq!((breakpoint.ignore_count, breakpoint.hit_count), (1, 2));
+	assert_eq!(restored.breakpoint(1).unwrap().log_message.as_ref().unwrap().source(), "at {ip}");
+
+	// paused on the breakpoint, so resuming goes around the loop once
+	assert_eq!(restored.run_budget(Some(100)), (2, RunReason::Breakpoint));
+}
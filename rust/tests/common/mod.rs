@@ -0,0 +1,17 @@
+// fixtures shared by the integration tests. each test crate uses only some of them
+#![allow(dead_code)]
+
+use rust_asm::Machine;
+
+// a new machine with code loaded at 1, where execution starts
+pub fn load(code: &[u32]) -> Machine {
+	return load_into(Machine::new(), code);
+}
+
+// the same for a machine set up by the caller, like one with another syscall host
+pub fn load_into(mut machine: Machine, code: &[u32]) -> Machine {
+	let mut image = vec![0];
+	image.extend_from_slice(code);
+	machine.initialize(&image);
+	return machine;
+}
@@ -0,0 +1,149 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RunReason};
+
+mod common;
+use common::load;
+
+// below core 0's stack and the vector tables
+const STACK: u32 = 30_000;
+const SECOND: u32 = 10;
+
+// core 0 runs code from 1, core 1 runs second from SECOND
+fn two_cores(code: &[u32], second: &[u32]) -> Machine {
+	let mut machine = load(code);
+	for (i, &word) in second.iter().enumerate() {
+		machine.write_memory(SECOND + i as u32, word);
+	}
+	assert_eq!(machine.add_core(SECOND, STACK), 1);
+	return machine;
+}
+
+// core id -> bus, bus -> memory[at], halt
+fn store_core_id(at: u32) -> Vec<u32> {
+	return vec![50, 2, at, 22];
+}
+
+#[test]
+fn cores_take_turns_one_step_at_a_time() {
+	let mut machine = two_cores(&store_core_id(60), &store_core_id(61));
+	assert_eq!(machine.core_count(), 2);
+
+	assert_eq!(machine.run_budget(Some(1)), (1, RunReason::BudgetExhausted));
+	assert_eq!(machine.current_core(), 1);
+	assert_eq!(machine.core_registers(0), Some((2, 0)));
+	assert_eq!(machine.core_registers(1), Some((SECOND, 0)));
+	assert_eq!(machine.core_registers(2), None);
+
+	assert_eq!(machine.run_budget(None), (5, RunReason::Halted));
+	assert_eq!(machine.read_memory(60), 0);
+	assert_eq!(machine.read_memory(61), 1);
+	assert!(machine.is_core_halted(0));
+	assert!(machine.is_core_halted(1));
+}
+
+#[test]
+fn cores_share_memory_and_can_race() {
+	// memory[60] + 1 -> memory[60], on both cores at once. both load 0 before either stores
+	let mut machine = load(&[1, 60, 25, 24, 1, 9, 16, 2, 60, 22]);
+	machine.add_core(1, STACK);
+	assert_eq!(machine.run_budget(None), (14, RunReason::Halted));
+	assert_eq!(machine.read_memory(60), 1);
+}
+
+#[test]
+fn halted_cores_are_skipped() {
+	// SECOND: 5 -> memory[60], halt
+	let mut machine = two_cores(&[22], &[24, 5, 2, 60, 22]);
+	assert_eq!(machine.run_budget(None), (4, RunReason::Halted));
+	assert_eq!(machine.read_memory(60), 5);
+	assert_eq!(machine.current_core(), 1);
+	assert_eq!(machine.instruction_pointer(), SECOND + 4);
+}
+
+#[test]
+fn a_fault_stops_every_core() {
+	// core 0 loops forever
+	let mut machine = two_cores(&[24, 1, 13], &[99]);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::InvalidOpcode(99)));
+	assert_eq!(machine.current_core(), 1);
+	assert_eq!(machine.last_fault_address(), SECOND);
+}
+
+#[test]
+fn stepping_back_returns_to_the_core_that_ran() {
+	let mut machine = two_cores(&store_core_id(60), &store_core_id(61));
+	machine.set_history_limit(1024);
+	machine.write_memory(60, 9);
+	machine.run();
+
+	machine.step_back();
+	assert_eq!(machine.current_core(), 1);
+	assert!(!machine.is_core_halted(1));
+	assert!(machine.is_core_halted(0));
+
+	while machine.step_back() {}
+	assert_eq!(machine.current_core(), 0);
+	assert!(!machine.is_core_halted(0));
+	assert_eq!(machine.read_memory(60), 9);
+	assert_eq!(machine.read_memory(61), 0);
+
+	machine.run();
+	assert_eq!(machine.read_memory(60), 0);
+	assert_eq!(machine.read_memory(61), 1);
+}
+
+#[test]
+fn snapshots_keep_every_core() {
+	let mut machine = two_cores(&store_core_id(60), &[24, 7, 34, 50, 2, 61, 22]);
+	machine.run_budget(Some(5));
+	let snapshot = machine.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert_eq!(restored.core_count(), 2);
+	assert_eq!(restored.current_core(), machine.current_core());
+	assert_eq!(restored.core_registers(0), machine.core_registers(0));
+	assert_eq!(restored.core_registers(1), machine.core_registers(1));
+	assert_eq!(restored.save_snapshot(), snapshot);
+
+	machine.run();
+	restored.run();
+	assert!(restored.is_halted());
+	assert_eq!(restored.read_memory(61), 1);
+	assert_eq!(restored.read_memory(STACK - 1), 7);
+	assert_eq!(restored.save_snapshot(), machine.save_snapshot());
+}
+
+// 1: call 4, halt. 4: two instructions and return
+fn call() -> Vec<u32> {
+	return vec![36, 4, 22, 24, 7, 24, 8, 37];
+}
+
+#[test]
+fn stepping_out_follows_the_core_that_was_stepped() {
+	// core 1 loops at depth 0, which would satisfy core 0's step out
+	let mut machine = two_cores(&call(), &[24, SECOND, 13]);
+	machine.step_into();
+	machine.step_into();
+	assert_eq!(machine.current_core(), 0);
+	assert_eq!(machine.instruction_pointer(), 4);
+
+	assert_eq!(machine.step_out(), 5);
+	assert_eq!(machine.last_run_reason(), RunReason::Reached.code());
+	assert_eq!(machine.current_core(), 0);
+	assert_eq!(machine.instruction_pointer(), 3);
+	assert!(machine.call_stack().is_empty());
+}
+
+#[test]
+fn stepping_over_follows_the_core_that_was_stepped() {
+	// core 1 jumps to 1 and makes the same call a little later
+	let mut machine = two_cores(&call(), &[24, 1, 13]);
+	machine.step_over();
+	assert_eq!(machine.last_run_reason(), RunReason::Reached.code());
+	assert_eq!(machine.current_core(), 0);
+	assert_eq!(machine.instruction_pointer(), 3);
+	assert_eq!(machine.core_registers(1).map(|(next, _)| next), Some(4));
+}
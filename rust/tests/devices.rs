@@ -0,0 +1,164 @@
+extern crate rust_asm;
+
+use rust_asm::{Keyboard, MapError, RunReason, Screen, SerialPort};
+
+mod common;
+use common::load;
+
+// above the first memory block, nothing is allocated there
+const SERIAL: u32 = 0xF000;
+const KEYBOARD: u32 = 0xF010;
+const SCREEN: u32 = 0xE000;
+
+#[test]
+fn serial_output() {
+	// sends "hi"
+	let mut machine = load(&[24, 104, 2, SERIAL, 24, 105, 2, SERIAL, 100]);
+	let serial = SerialPort::new();
+	machine.map_device(SERIAL, SERIAL + 1, Box::new(serial.clone())).unwrap();
+
+	assert_eq!(machine.run_budget(None), (5, RunReason::Halted));
+	assert_eq!(serial.output(), b"hi".to_vec());
+	assert_eq!(serial.take_output(), b"hi".to_vec());
+	assert!(serial.output().is_empty());
+}
+
+#[test]
+fn serial_input() {
+	// status -> memory[50], then two bytes -> memory[51] and memory[52]
+	let mut machine = load(&[1, SERIAL + 1, 2, 50, 1, SERIAL, 2, 51, 1, SERIAL, 2, 52, 100]);
+	let serial = SerialPort::new();
+	machine.map_device(SERIAL, SERIAL + 1, Box::new(serial.clone())).unwrap();
+	serial.send(b"A");
+
+	machine.run();
+	assert_eq!(machine.read_memory(50), 1);
+	assert_eq!(machine.read_memory(51), 65);
+	// nothing left to read
+	assert_eq!(machine.read_memory(52), 0);
+}
+
+#[test]
+fn keyboard_keys_are_taken_in_order() {
+	// key count -> memory[50], then two keys -> memory[51] and memory[52]
+	let mut machine = load(&[1, KEYBOARD + 1, 2, 50, 1, KEYBOARD, 2, 51, 1, KEYBOARD, 2, 52, 100]);
+	let keyboard = Keyboard::new();
+	machine.map_device(KEYBOARD, KEYBOARD + 1, Box::new(keyboard.clone())).unwrap();
+	keyboard.press(37);
+	keyboard.press(39);
+
+	machine.run();
+	assert_eq!(machine.read_memory(50), 2);
+	assert_eq!(machine.read_memory(51), 37);
+	assert_eq!(machine.read_memory(52), 39);
+}
+
+#[test]
+fn screen_pixels() {
+	// 7 -> pixel 5, then pixel 5 -> memory[50]
+	let mut machine = load(&[24, 7, 6, SCREEN, 5, 5, SCREEN, 5, 2, 50, 100]);
+	let screen = Screen::new(4, 3).unwrap();
+	machine.map_device(SCREEN, SCREEN + 4 * 3 - 1, Box::new(screen.clone())).unwrap();
+
+	machine.run();
+	assert_eq!(screen.pixel(1, 1), 7);
+	assert_eq!(screen.pixels().iter().filter(|&&pixel| pixel != 0).count(), 1);
+	assert_eq!(machine.read_memory(50), 7);
+}
+
+#[test]
+fn screens_too_big_to_map_are_rejected() {
+	assert!(Screen::new(0x10000, 0x10000).is_none());
+	assert!(Screen::new(u32::MAX, 2).is_none());
+	assert_eq!(Screen::new(1024, 768).unwrap().pixels().len(), 1024 * 768);
+}
+
+#[test]
+fn devices_shadow_memory() {
+	// 5 -> memory[60], then memory[60] -> memory[61]
+	let mut machine = load(&[24, 5, 2, 60, 1, 60, 2, 61, 100]);
+	let serial = SerialPort::new();
+	serial.send(&[9]);
+	machine.map_device(60, 60, Box::new(serial.clone())).unwrap();
+
+	machine.run();
+	assert_eq!(serial.output(), vec![5]);
+	assert_eq!(machine.read_memory(61), 9);
+	// the debugger sees the memory underneath
+	assert_eq!(machine.read_memory(60), 0);
+}
+
+#[test]
+fn mapping_errors() {
+	let mut machine = load(&[100]);
+	let id = machine.map_device(100, 199, Box::new(SerialPort::new())).unwrap();
+
+	assert_eq!(machine.map_device(10, 5, Box::new(SerialPort::new())).err(), Some(MapError::EmptyRange));
+	assert_eq!(machine.map_device(150, 250, Box::new(SerialPort::new())).err(), Some(MapError::Overlaps(id)));
+	assert_eq!(machine.map_device(50, 100, Box::new(SerialPort::new())).err(), Some(MapError::Overlaps(id)));
+	let other = machine.map_device(200, 200, Box::new(SerialPort::new())).unwrap();
+	assert_eq!(machine.device_mappings(), vec![id, 100, 199, other, 200, 200]);
+}
+
+#[test]
+fn unmapped_addresses_go_back_to_memory() {
+	// 5 -> memory[60]
+	let mut machine = load(&[24, 5, 2, 60, 100]);
+	let serial = SerialPort::new();
+	let id = machine.map_device(60, 61, Box::new(serial.clone())).unwrap();
+
+	assert!(machine.unmap_device(id).is_some());
+	assert!(machine.unmap_device(id).is_none());
+	machine.run();
+	assert!(serial.output().is_empty());
+	assert_eq!(machine.read_memory(60), 5);
+}
+
+#[test]
+fn writes_outside_memory_fault_without_a_device() {
+	let mut machine = load(&[24, 5, 2, SERIAL, 100]);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Faulted));
+}
+
+#[test]
+fn watchpoints_see_device_accesses() {
+	let mut machine = load(&[24, 104, 2, SERIAL, 100]);
+	let serial = SerialPort::new();
+	machine.map_device(SERIAL, SERIAL + 1, Box::new(serial.clone())).unwrap();
+	machine.add_watchpoint(SERIAL, SERIAL, 2);
+
+	assert_eq!(machine.run_budget(None), (2, RunReason::Watchpoint));
+	let hit = machine.watch_hit().unwrap();
+	assert_eq!((hit.address, hit.new), (SERIAL, 104));
+	assert_eq!(serial.output(), b"h".to_vec());
+}
+
+#[test]
+fn compiled_blocks_use_devices() {
+	// sends the counter 1..=40 to the serial port
+	let mut code = vec![
+		1, 100, // 1: counter -> bus
+		25, // 3: push onto the ALU
+		24, 1, // 4: 1 -> bus
+		9, // 6: add
+		16, // 7: hi -> bus
+		2, 100, // 8: bus -> counter
+		2, SERIAL, // 10: bus -> serial port
+		25, // 12: push the counter
+		24, 40, // 13: 40 -> bus
+		25, // 15: push the limit
+		29, 4, // 16: counter < limit
+		14, 1, // 18: goto 1 if it is
+		100, // 20: halt
+	];
+	code.resize(101, 0);
+	let mut machine = load(&code);
+	let serial = SerialPort::new();
+	machine.map_device(SERIAL, SERIAL + 1, Box::new(serial.clone())).unwrap();
+	machine.set_history_limit(0);
+	machine.set_jit(true);
+
+	machine.run();
+	assert!(machine.jit_block_count() > 0);
+	assert_eq!(serial.output(), (1..=40).collect::<Vec<u8>>());
+}
@@ -0,0 +1,36 @@
+extern crate rust_asm;
+
+use rust_asm::mnemonic;
+
+mod common;
+use common::load;
+
+#[test]
+fn decodes_by_parameter_count() {
+	// 1: 5 -> bus, 3: load memory[7 + 2], 6: save with memory[9] as offset, 9: add, 10: halt
+	let machine = load(&[24, 5, 5, 7, 2, 27, 8, 9, 9, 100]);
+	assert_eq!(machine.disassemble(1, 11), "\
+1: LoadImmediateToBus 5
+3: LoadWithConstantOffsetToBus 7 2
+6: SaveFromBusWithVariableOffset 8 9
+9: AluDoAdd
+10: DslHalt
+");
+	assert_eq!(machine.disassemble_words(1, 4), vec![1, 24, 1, 5, 3, 5, 2, 7, 2]);
+
+	let instruction = machine.decode(6);
+	assert_eq!(instruction.params, vec![8, 9]);
+	assert_eq!(instruction.size(), 3);
+}
+
+#[test]
+fn unknown_words_decode_as_data() {
+	let machine = load(&[99, 1000, 0]);
+	let instructions = machine.instructions(1, 4);
+	assert_eq!(instructions.len(), 3);
+	assert_eq!(instructions[0].mnemonic, None);
+	assert_eq!(instructions[1].to_string(), "2: .word 1000");
+	assert_eq!(instructions[2].to_string(), "3: Noop");
+	assert_eq!(mnemonic(36), Some("Call"));
+	assert_eq!(mnemonic(99), None);
+}
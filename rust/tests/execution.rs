@@ -0,0 +1,93 @@
+extern crate rust_asm;
+
+use rust_asm::RunReason;
+
+mod common;
+use common::load;
+
+#[test]
+fn budget_stops_an_infinite_loop() {
+	// 1: 1 -> bus, 3: jump to bus
+	let mut machine = load(&[24, 1, 13]);
+
+	assert_eq!(machine.run_budget(Some(100)), (100, RunReason::BudgetExhausted));
+	assert_eq!(machine.run_budget(Some(7)), (7, RunReason::BudgetExhausted));
+	assert_eq!(machine.instruction_pointer(), 3);
+}
+
+#[test]
+fn budget_reports_halts_and_faults() {
+	let mut halting = load(&[0, 0, 100]);
+	assert_eq!(halting.run_budget(Some(100)), (3, RunReason::Halted));
+	assert_eq!(halting.run_budget(Some(100)), (0, RunReason::Halted));
+
+	let mut faulting = load(&[0, 99]);
+	assert_eq!(faulting.run_budget(Some(100)), (2, RunReason::Faulted));
+}
+
+#[test]
+fn pause_stops_a_sliced_run() {
+	let mut machine = load(&[24, 1, 13]);
+	machine.run_budget(Some(10));
+	machine.pause();
+
+	assert!(machine.is_paused());
+	assert_eq!(machine.run_budget(Some(4)), (4, RunReason::BudgetExhausted));
+}
+
+#[test]
+fn resuming_from_a_breakpoint_runs_past_it() {
+	// 1: noop, 2: noop, 3: pause, 4: halt
+	let mut machine = load(&[0, 0, 23, 100]);
+	machine.set_breakpoint(2);
+	machine.enable_breakpoints();
+
+	assert_eq!(machine.run_budget(None), (1, RunReason::Breakpoint));
+	assert_eq!(machine.instruction_pointer(), 2);
+
+	assert_eq!(machine.run_budget(None), (2, RunReason::Paused));
+	assert_eq!(machine.run_budget(None), (1, RunReason::Halted));
+}
+
+#[test]
+fn machines_do_not_share_state() {
+	// 7 -> memory[60], halt
+	let mut first = load(&[24, 7, 2, 60, 22]);
+	// 9 -> memory[60], loop
+	let mut second = load(&[24, 9, 2, 60, 24, 5, 13]);
+	second.set_breakpoint(5);
+	second.enable_breakpoints();
+
+	assert_eq!(first.run_budget(None), (3, RunReason::Halted));
+	assert_eq!(second.read_memory(60), 0);
+	assert_eq!(second.instruction_pointer(), 1);
+
+	assert_eq!(second.run_budget(Some(10)), (2, RunReason::Breakpoint));
+	assert_eq!(first.read_memory(60), 7);
+	assert_eq!(second.read_memory(60), 9);
+	assert_eq!(first.bus(), 7);
+	assert!(first.is_halted());
+	assert!(!second.is_halted());
+	assert!(!first.is_breakpoint(5));
+}
+
+#[test]
+fn initializing_again_starts_over() {
+	// 1: push 7, goto 1, with a breakpoint on 1
+	let code = [0, 24, 7, 34, 24, 1, 13];
+	let mut machine = load(&code[1..]);
+	machine.set_breakpoint(1);
+	machine.enable_breakpoints();
+	assert_eq!(machine.run_budget(None), (0, RunReason::Breakpoint));
+	assert_eq!(machine.run_budget(None), (4, RunReason::Breakpoint));
+	assert_eq!(machine.run_budget(None), (4, RunReason::Breakpoint));
+
+	machine.initialize(&code);
+	let mut fresh = load(&code[1..]);
+	fresh.set_breakpoint(1);
+	fresh.enable_breakpoints();
+	assert_eq!(machine.save_snapshot(), fresh.save_snapshot());
+	assert!(machine.call_stack().is_empty());
+	// stopped on 1 before, but this is a new run
+	assert_eq!(machine.run_budget(None), (0, RunReason::Breakpoint));
+}
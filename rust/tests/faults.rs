@@ -0,0 +1,180 @@
+extern crate rust_asm;
+
+use rust_asm::{r_GetLastFault, r_GetLastFaultAddress, r_GetLastFaultDetail, r_Initialize, r_StepInto};
+use rust_asm::{Fault, Machine, TRAP_VECTORS};
+use rust_asm::permissions::{PAGE_SIZE, READ, WRITE};
+
+mod common;
+use common::load;
+
+// past the end of the 32 pages a machine starts with
+const HIGH: u32 = 40_000;
+// a page the tests take permissions away from
+const LOCKED: u32 = 10 * PAGE_SIZE;
+
+// steps one instruction at a time until the machine faults,
+// checking that no step before the last one did
+fn step_to_fault(machine: &mut Machine, steps: u32) {
+	for _ in 1..steps {
+		machine.step_into();
+		assert_eq!(machine.fault(), None);
+	}
+	machine.step_into();
+}
+
+fn assert_fault(machine: &Machine, fault: Fault, address: u32) {
+	assert_eq!(machine.fault(), Some(fault));
+	assert_eq!(machine.last_fault(), fault.code());
+	assert_eq!(machine.last_fault_detail(), fault.detail() as i32);
+	assert_eq!(machine.last_fault_address(), address);
+	assert_eq!(machine.instruction_pointer(), address);
+}
+
+#[test]
+fn invalid_opcode() {
+	let mut machine = load(&[0, 99]);
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::InvalidOpcode(99), 2);
+}
+
+#[test]
+fn memory_out_of_bounds() {
+	// 1: 7 -> bus, 3: bus -> memory[HIGH]
+	let mut machine = load(&[24, 7, 2, HIGH]);
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::MemoryOutOfBounds(HIGH), 3);
+}
+
+#[test]
+fn divide_by_zero() {
+	// 1: 0 -> alu, 4: 5 / 0
+	let mut machine = load(&[24, 0, 25, 24, 5, 12]);
+	step_to_fault(&mut machine, 4);
+	assert_fault(&machine, Fault::DivideByZero, 6);
+}
+
+#[test]
+fn jump_out_of_bounds() {
+	// 1: HIGH -> bus, 3: jump to bus
+	let mut machine = load(&[24, HIGH, 13]);
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::JumpOutOfBounds(HIGH), 3);
+}
+
+#[test]
+fn instruction_out_of_bounds() {
+	// 1: jump to the last word of memory, a noop, and run off the end
+	let last = 32 * PAGE_SIZE - 1;
+	let mut machine = load(&[24, last, 13]);
+	step_to_fault(&mut machine, 4);
+	assert_fault(&machine, Fault::InstructionOutOfBounds(last + 1), last + 1);
+}
+
+#[test]
+fn stack_overflow() {
+	// 1: push, 2: goto 1
+	let mut machine = load(&[34, 24, 1, 13]);
+	let limit = machine.stack_pointer() - 1024;
+	step_to_fault(&mut machine, 1024 * 3 + 1);
+	assert_fault(&machine, Fault::StackOverflow(limit), 1);
+}
+
+#[test]
+fn stack_underflow() {
+	let mut machine = load(&[0, 35]);
+	let base = machine.stack_pointer();
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::StackUnderflow(base), 2);
+}
+
+#[test]
+fn read_violation() {
+	let mut machine = load(&[1, LOCKED + 5]);
+	machine.set_permissions(LOCKED, LOCKED, WRITE);
+	step_to_fault(&mut machine, 1);
+	assert_fault(&machine, Fault::ReadViolation(LOCKED + 5), 1);
+}
+
+#[test]
+fn write_violation() {
+	let mut machine = load(&[24, 7, 2, LOCKED + 5]);
+	machine.set_permissions(LOCKED, LOCKED, READ);
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::WriteViolation(LOCKED + 5), 3);
+}
+
+#[test]
+fn execute_violation() {
+	let mut machine = load(&[24, LOCKED, 13]);
+	machine.set_permissions(LOCKED, LOCKED, READ | WRITE);
+	step_to_fault(&mut machine, 3);
+	assert_fault(&machine, Fault::ExecuteViolation(LOCKED), LOCKED);
+}
+
+// 1: 10 -> bus, allocate, bus -> memory[60], 6: free memory[60] twice
+const ALLOCATE_AND_FREE: [u32; 11] = [24, 10, 43, 2, 60, 1, 60, 44, 1, 60, 44];
+const HEAP: u32 = 32 * PAGE_SIZE;
+
+#[test]
+fn double_free() {
+	let mut machine = load(&ALLOCATE_AND_FREE);
+	machine.set_heap_checks(true);
+	step_to_fault(&mut machine, 7);
+	assert_fault(&machine, Fault::DoubleFree(HEAP), 11);
+}
+
+#[test]
+fn invalid_free() {
+	let mut machine = load(&[24, 5, 44]);
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::InvalidFree(5), 3);
+}
+
+#[test]
+fn use_after_free() {
+	// 9: the first word of the freed allocation -> bus
+	let mut code = ALLOCATE_AND_FREE[..8].to_vec();
+	code.extend(&[26, 0, 60]);
+	let mut machine = load(&code);
+	machine.set_heap_checks(true);
+	step_to_fault(&mut machine, 6);
+	assert_fault(&machine, Fault::UseAfterFree(HEAP), 9);
+}
+
+#[test]
+fn page_fault() {
+	// an empty page table maps nothing, not even the code
+	let mut machine = load(&[0]);
+	machine.set_page_table(LOCKED, 32);
+	machine.set_paging(true);
+	step_to_fault(&mut machine, 1);
+	assert_fault(&machine, Fault::PageFault(1), 1);
+}
+
+#[test]
+fn privilege_violation() {
+	let mut machine = load(&[0, 20]);
+	machine.set_supervisor(false);
+	step_to_fault(&mut machine, 2);
+	assert_fault(&machine, Fault::PrivilegeViolation(20), 2);
+}
+
+#[test]
+fn no_trap_handler() {
+	let mut machine = load(&[49, 5]);
+	assert_eq!(machine.read_memory(TRAP_VECTORS + 1), 0);
+	step_to_fault(&mut machine, 1);
+	assert_fault(&machine, Fault::NoTrapHandler(1), 1);
+}
+
+// the only test in this file to use the main machine
+#[test]
+fn the_exports_report_the_last_fault() {
+	r_Initialize(&[0, 24, HIGH, 13]);
+	r_StepInto();
+	assert_eq!(r_GetLastFault(), 0);
+	r_StepInto();
+	assert_eq!(r_GetLastFault(), Fault::JumpOutOfBounds(HIGH).code());
+	assert_eq!(r_GetLastFaultAddress(), 3);
+	assert_eq!(r_GetLastFaultDetail(), HIGH as i32);
+}
@@ -0,0 +1,238 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RunReason, MAX_PAGES};
+use rust_asm::permissions::PAGE_SIZE;
+
+mod common;
+use common::load;
+
+// where the programs below keep their pointers
+const P: u32 = 100;
+const Q: u32 = 101;
+// the first page past the end of memory
+const HEAP: u32 = 32 * PAGE_SIZE;
+
+// size -> bus, allocate, bus -> memory[to]
+fn allocate(size: u32, to: u32) -> Vec<u32> {
+	return vec![24, size, 43, 2, to];
+}
+
+// memory[from] -> bus, free
+fn free(from: u32) -> Vec<u32> {
+	return vec![1, from, 44];
+}
+
+#[test]
+fn small_allocations_share_pages_by_size_class() {
+	let mut code = allocate(3, P);
+	code.extend(allocate(4, Q));
+	code.extend(allocate(5, P + 2));
+	code.push(22);
+	let mut machine = load(&code);
+	assert_eq!(machine.run_budget(None), (10, RunReason::Halted));
+
+	assert_eq!(machine.read_memory(P), HEAP);
+	assert_eq!(machine.read_memory(Q), HEAP + 4);
+	assert_eq!(machine.read_memory(P + 2), HEAP + PAGE_SIZE);
+	assert_eq!(machine.heap_allocations(), vec![(HEAP, 4), (HEAP + 4, 4), (HEAP + PAGE_SIZE, 8)]);
+	assert_eq!(machine.memory_pages(), 34);
+}
+
+#[test]
+fn freed_allocations_are_reused() {
+	let mut code = allocate(10, P);
+	code.extend(free(P));
+	code.extend(allocate(10, Q));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.run();
+
+	assert_eq!(machine.fault(), None);
+	assert_eq!(machine.read_memory(Q), machine.read_memory(P));
+	assert_eq!(machine.heap_allocations().len(), 1);
+}
+
+#[test]
+fn large_allocations_get_pages_that_are_released_when_freed() {
+	// 6: 7 -> the first word of the allocation
+	let mut code = allocate(2000, P);
+	code.extend(&[24, 7, 27, 0, P]);
+	code.extend(free(P));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.set_history_limit(0);
+
+	assert_eq!(machine.run_budget(Some(5)), (5, RunReason::BudgetExhausted));
+	assert_eq!(machine.heap_allocations(), vec![(HEAP, 2 * PAGE_SIZE)]);
+	assert_eq!(machine.read_memory(HEAP), 7);
+	assert_eq!(machine.allocated_pages(), 2);
+
+	machine.run();
+	assert!(machine.heap_allocations().is_empty());
+	assert_eq!(machine.read_memory(HEAP), 0);
+	assert_eq!(machine.allocated_pages(), 1);
+}
+
+#[test]
+fn allocating_fails_with_0() {
+	let mut code = allocate(0, P);
+	code.extend(allocate(5, Q));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.write_memory(P, 1);
+	machine.write_memory(Q, 1);
+	machine.set_memory_pages(MAX_PAGES);
+	assert_eq!(machine.run_budget(None), (7, RunReason::Halted));
+	assert_eq!(machine.read_memory(P), 0);
+	assert_eq!(machine.read_memory(Q), 0);
+}
+
+#[test]
+fn bad_frees_fault() {
+	let mut machine = load(&[24, 5, 44, 22]);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::InvalidFree(5)));
+	assert_eq!(machine.last_fault_address(), 3);
+
+	// freeing 0 does nothing
+	let mut machine = load(&[24, 0, 44, 22]);
+	assert_eq!(machine.run_budget(None), (3, RunReason::Halted));
+
+	// without checks a second free is just not an allocation
+	let mut code = allocate(10, P);
+	code.extend(free(P));
+	code.extend(free(P));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.run();
+	assert_eq!(machine.fault(), Some(Fault::InvalidFree(HEAP)));
+
+	let mut machine = load(&code);
+	machine.set_heap_checks(true);
+	machine.run();
+	assert_eq!(machine.fault(), Some(Fault::DoubleFree(HEAP)));
+}
+
+#[test]
+fn checks_catch_use_after_free() {
+	let mut code = allocate(10, P);
+	code.extend(free(P));
+	// 9: the first word of the freed allocation -> bus
+	code.extend(&[26, 0, P, 22]);
+
+	let mut machine = load(&code);
+	machine.run();
+	assert_eq!(machine.fault(), None);
+
+	let mut machine = load(&code);
+	machine.set_heap_checks(true);
+	machine.run();
+	assert_eq!(machine.fault(), Some(Fault::UseAfterFree(HEAP)));
+	assert_eq!(machine.last_fault_address(), 9);
+
+	// quarantined allocations are not handed out again
+	let mut code = allocate(10, P);
+	code.extend(free(P));
+	code.extend(allocate(10, Q));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.set_heap_checks(true);
+	machine.run();
+	assert_eq!(machine.read_memory(Q), HEAP + 16);
+}
+
+#[test]
+fn stepping_back_restores_the_heap() {
+	let mut code = allocate(2000, P);
+	code.extend(&[24, 7, 27, 0, P]);
+	code.extend(free(P));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.set_history_limit(1024);
+	machine.start_trace();
+	machine.run();
+	assert!(machine.heap_allocations().is_empty());
+	// releasing the pages is not a write
+	let freed = machine.trace().find(|record| record.opcode == 44).unwrap();
+	assert!(freed.writes.is_empty());
+
+	// over the free, which released the pages
+	machine.step_back();
+	machine.step_back();
+	machine.step_back();
+	assert_eq!(machine.heap_allocations(), vec![(HEAP, 2 * PAGE_SIZE)]);
+	assert_eq!(machine.read_memory(HEAP), 7);
+
+	while machine.step_back() {}
+	assert!(machine.heap_allocations().is_empty());
+	assert_eq!(machine.memory_pages(), 32);
+}
+
+#[test]
+fn stepping_back_undoes_each_allocate_and_free() {
+	// allocate small and large, free both, goto 1. with checks on the quarantine fills
+	// up, so older allocations are released and their pages reused
+	let mut code = allocate(3, P);
+	code.extend(allocate(1500, Q));
+	code.extend(free(P));
+	code.extend(free(Q));
+	code.extend(&[24, 1, 13]);
+	let mut machine = load(&code);
+	machine.set_heap_checks(true);
+	machine.set_history_limit(1000);
+	let mut snapshots = vec![machine.save_snapshot()];
+	for _ in 0..900 {
+		machine.step_into();
+		snapshots.push(machine.save_snapshot());
+	}
+	assert_eq!(machine.fault(), None);
+
+	snapshots.pop();
+	while let Some(snapshot) = snapshots.pop() {
+		assert!(machine.step_back());
+		assert!(machine.save_snapshot() == snapshot, "{} steps in", snapshots.len());
+	}
+	assert!(machine.heap_allocations().is_empty());
+
+	// releasing the quarantine can not be undone
+	machine.run_budget(Some(100));
+	machine.set_heap_checks(false);
+	assert!(!machine.step_back());
+}
+
+#[test]
+fn snapshots_keep_the_heap() {
+	let mut code = allocate(10, P);
+	code.extend(allocate(3000, Q));
+	code.extend(free(P));
+	code.push(22);
+	let mut machine = load(&code);
+	machine.set_heap_checks(true);
+	machine.run();
+	let snapshot = machine.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert_eq!(restored.heap_allocations(), machine.heap_allocations());
+	assert_eq!(restored.save_snapshot(), snapshot);
+}
+
+#[test]
+fn compiled_blocks_allocate_like_the_interpreter() {
+	// 1: allocate 8 then 600 words, free the first, goto 1. the 600 word ones are never freed
+	let mut code = allocate(8, P);
+	code.extend(allocate(600, Q));
+	code.extend(free(P));
+	code.extend(&[24, 1, 13]);
+	let mut results = Vec::new();
+	for &jit in [false, true].iter() {
+		let mut machine = load(&code);
+		machine.set_history_limit(0);
+		machine.set_jit(jit);
+		machine.run_budget(Some(2000));
+		results.push((machine.heap_allocations(), machine.memory_pages(), machine.instruction_pointer()));
+	}
+	assert_eq!(results[0], results[1]);
+	// 10 instructions a time around
+	assert_eq!(results[0].0.len(), 200);
+}
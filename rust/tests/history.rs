@@ -0,0 +1,83 @@
+extern crate rust_asm;
+
+use rust_asm::Machine;
+
+mod common;
+
+// history is off until it is given a limit
+fn load(code: &[u32]) -> Machine {
+	let mut machine = common::load(code);
+	machine.set_history_limit(1024);
+	return machine;
+}
+
+#[test]
+fn step_back_undoes_memory_writes_and_registers() {
+	// 1: 5 -> bus, 3: bus -> memory[30], 5: 6 -> bus, 7: bus -> memory[30], 9: halt
+	let mut machine = load(&[24, 5, 2, 30, 24, 6, 2, 30, 100]);
+	machine.run();
+	assert_eq!(machine.read_memory(30), 6);
+
+	assert!(machine.step_back());
+	assert!(machine.is_paused());
+	assert_eq!(machine.instruction_pointer(), 9);
+
+	assert!(machine.step_back());
+	assert_eq!(machine.read_memory(30), 5);
+	assert_eq!(machine.instruction_pointer(), 7);
+
+	assert!(machine.step_back());
+	assert!(machine.step_back());
+	assert_eq!(machine.read_memory(30), 0);
+	assert!(machine.step_back());
+	assert_eq!(machine.bus(), 0);
+	assert_eq!(machine.instruction_pointer(), 1);
+
+	assert!(!machine.step_back());
+}
+
+#[test]
+fn step_back_undoes_calls() {
+	// 1: call 6, 3: halt, 6: return
+	let mut machine = load(&[36, 6, 100, 0, 0, 37]);
+	machine.step_into();
+	assert_eq!(machine.call_stack().len(), 4);
+
+	assert!(machine.step_back());
+	assert!(machine.call_stack().is_empty());
+	assert_eq!(machine.stack_pointer(), 32768);
+
+	machine.run();
+	assert!(machine.step_back());
+	assert!(machine.step_back());
+	assert_eq!(machine.instruction_pointer(), 6);
+	assert_eq!(machine.call_stack(), vec![1, 6, 3, 32767]);
+}
+
+#[test]
+fn reverse_continue_stops_on_the_previous_breakpoint() {
+	let mut machine = load(&[0, 0, 0, 0, 0, 100]);
+	machine.set_breakpoint(2);
+	machine.enable_breakpoints();
+	machine.run();
+	machine.run();
+	assert!(machine.is_halted());
+
+	assert_eq!(machine.reverse_continue(), 5);
+	assert_eq!(machine.instruction_pointer(), 2);
+	assert_eq!(machine.reverse_continue(), 1);
+	assert_eq!(machine.instruction_pointer(), 1);
+}
+
+#[test]
+fn history_is_bounded() {
+	let mut machine = load(&[0, 0, 0, 0, 0, 100]);
+	machine.set_history_limit(2);
+	machine.run();
+
+	assert_eq!(machine.history_length(), 2);
+	assert!(machine.step_back());
+	assert!(machine.step_back());
+	assert!(!machine.step_back());
+	assert_eq!(machine.instruction_pointer(), 5);
+}
@@ -0,0 +1,160 @@
+extern crate rust_asm;
+
+use rust_asm::{Machine, RunReason, Timer, INTERRUPT_TABLE};
+
+const TIMER: u32 = 0xF020;
+const HANDLER: u32 = 30;
+
+// 1: enable interrupts, then 2: noop, 3: noop, 4: 2 -> bus, 6: jump to bus, forever.
+// the handler at 30 counts interrupts in memory[60], keeping the bus on the stack
+fn load(prologue: &[u32]) -> Machine {
+	// execution starts at 1
+	let mut image = vec![0];
+	image.extend_from_slice(prologue);
+	let main = image.len() as u32;
+	image.extend_from_slice(&[39, 0, 0, 24, main + 1, 13]);
+	image.resize(HANDLER as usize, 0);
+	image.extend_from_slice(&[
+		34, // 30: push the bus
+		1, 60, // 31: count -> bus
+		25, // 33: push onto the ALU
+		24, 1, // 34: 1 -> bus
+		9, // 36: add
+		16, // 37: hi -> bus
+		2, 60, // 38: bus -> count
+		35, // 40: pop the bus
+		41, // 41: return from interrupt
+	]);
+
+	let mut machine = Machine::new();
+	machine.initialize(&image);
+	machine.write_memory(INTERRUPT_TABLE, HANDLER);
+	return machine;
+}
+
+#[test]
+fn raised_interrupts_run_the_handler() {
+	let mut machine = load(&[]);
+	assert!(machine.raise_interrupt(0));
+	assert_eq!(machine.pending_interrupts(), 1);
+
+	// enable interrupts, take it, then the 9 instructions of the handler
+	assert_eq!(machine.run_budget(Some(11)), (11, RunReason::BudgetExhausted));
+	assert_eq!(machine.read_memory(60), 1);
+	assert_eq!(machine.instruction_pointer(), 2);
+	assert_eq!(machine.pending_interrupts(), 0);
+	assert!(machine.interrupts_enabled());
+	assert!(machine.call_stack().is_empty());
+}
+
+#[test]
+fn handlers_run_with_interrupts_disabled() {
+	let mut machine = load(&[]);
+	machine.raise_interrupt(0);
+	machine.run_budget(Some(3));
+	assert_eq!(machine.instruction_pointer(), HANDLER + 1);
+	assert!(!machine.interrupts_enabled());
+	// the frame has the stack pointer from before the handler pushed the bus
+	assert_eq!(machine.call_stack(), vec![2, HANDLER, 2, machine.stack_pointer() + 1]);
+
+	// raised again inside the handler, it is taken once the handler returns
+	machine.raise_interrupt(0);
+	machine.run_budget(Some(8 + 1 + 9));
+	assert_eq!(machine.read_memory(60), 2);
+	assert_eq!(machine.instruction_pointer(), 2);
+}
+
+#[test]
+fn disabled_interrupts_stay_pending() {
+	// 1: disable them again right away
+	let mut machine = load(&[40]);
+	machine.raise_interrupt(0);
+	// 40 runs, then 39 enables them and the interrupt is taken before the loop
+	assert_eq!(machine.run_budget(Some(1)), (1, RunReason::BudgetExhausted));
+	assert_eq!(machine.pending_interrupts(), 1);
+	machine.run_budget(Some(2));
+	assert_eq!(machine.instruction_pointer(), HANDLER);
+}
+
+#[test]
+fn lowest_line_first_and_lines_without_a_handler_wait() {
+	let mut machine = load(&[]);
+	machine.write_memory(INTERRUPT_TABLE, 0);
+	machine.write_memory(INTERRUPT_TABLE + 3, HANDLER);
+	machine.write_memory(INTERRUPT_TABLE + 5, HANDLER);
+	machine.raise_interrupt(5);
+	machine.raise_interrupt(3);
+	machine.raise_interrupt(1);
+
+	machine.run_budget(Some(2));
+	assert_eq!(machine.instruction_pointer(), HANDLER);
+	assert_eq!(machine.pending_interrupts(), (1 << 5) | (1 << 1));
+	machine.run_budget(Some(9 + 1 + 9));
+	assert_eq!(machine.read_memory(60), 2);
+	assert_eq!(machine.pending_interrupts(), 1 << 1);
+
+	assert!(!machine.raise_interrupt(32));
+}
+
+#[test]
+fn timer_interrupts() {
+	// 1: 10 -> bus, 3: bus -> the timer period
+	let prologue = [24, 10, 2, TIMER];
+	let mut counts = Vec::new();
+	for &jit in [false, true].iter() {
+		let mut machine = load(&prologue);
+		machine.map_device(TIMER, TIMER + 2, Box::new(Timer::new())).unwrap();
+		machine.set_history_limit(0);
+		machine.set_jit(jit);
+		assert_eq!(machine.run_budget(Some(10_000)), (10_000, RunReason::BudgetExhausted));
+		counts.push((machine.read_memory(60), machine.instruction_pointer(), machine.stack_pointer()));
+	}
+	assert_eq!(counts[0], counts[1]);
+	// one interrupt every 10 instructions once the timer is started by the first two,
+	// the steps taking them are not instructions. (10_000 - 2) / 11
+	assert_eq!(counts[0].0, 908);
+}
+
+#[test]
+fn breakpoints_stop_in_the_handler() {
+	let mut machine = load(&[]);
+	machine.set_breakpoint(2);
+	machine.set_breakpoint(HANDLER);
+	machine.enable_breakpoints();
+	machine.raise_interrupt(0);
+
+	// not at 2, the interrupt is taken in front of it
+	assert_eq!(machine.run_budget(None), (2, RunReason::Breakpoint));
+	assert_eq!(machine.instruction_pointer(), HANDLER);
+	assert_eq!(machine.run_budget(None), (9, RunReason::Breakpoint));
+	assert_eq!(machine.instruction_pointer(), 2);
+	assert_eq!(machine.breakpoint_hit_count(2), 1);
+}
+
+#[test]
+fn stepping_back_over_an_interrupt() {
+	let mut machine = load(&[]);
+	machine.set_history_limit(1024);
+	machine.raise_interrupt(0);
+	machine.run_budget(Some(2));
+	assert_eq!(machine.instruction_pointer(), HANDLER);
+
+	assert!(machine.step_back());
+	assert_eq!(machine.instruction_pointer(), 2);
+	assert_eq!(machine.pending_interrupts(), 1);
+	assert!(machine.interrupts_enabled());
+	assert!(machine.call_stack().is_empty());
+}
+
+#[test]
+fn snapshots_keep_the_interrupt_state() {
+	let mut machine = load(&[]);
+	machine.run_budget(Some(1));
+	machine.raise_interrupt(4);
+	let snapshot = machine.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert!(restored.interrupts_enabled());
+	assert_eq!(restored.pending_interrupts(), 1 << 4);
+}
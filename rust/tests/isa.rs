@@ -0,0 +1,116 @@
+extern crate rust_asm;
+
+use std::collections::HashSet;
+
+use rust_asm::{Machine, Opcode, RecordingSyscallHost, INSTRUCTION_SET};
+use rust_asm::isa::{json_string, to_json};
+
+// the string literals in a JSON document, unescaped
+fn json_strings(json: &str) -> Vec<String> {
+	let mut strings = Vec::new();
+	let mut chars = json.chars();
+	while let Some(c) = chars.next() {
+		if c != '"' {
+			continue;
+		}
+		let mut s = String::new();
+		loop {
+			match chars.next().unwrap() {
+				'"' => break,
+				'\\' => match chars.next().unwrap() {
+					'u' => {
+						let hex: String = chars.by_ref().take(4).collect();
+						s.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+					},
+					c => s.push(c),
+				},
+				c => s.push(c),
+			}
+		}
+		strings.push(s);
+	}
+	return strings;
+}
+
+#[test]
+fn table_matches_the_enum() {
+	for (i, info) in INSTRUCTION_SET.iter().enumerate() {
+		assert_eq!(info.opcode as usize, i);
+		assert_eq!(Opcode::from_code(info.code), Some(info.opcode));
+		assert_eq!(info.opcode.code(), info.code);
+	}
+	assert_eq!(Opcode::from_code(99), None);
+	assert_eq!(Opcode::DslHalt.mnemonic(), "DslHalt");
+
+	// tooling looks opcodes up by mnemonic
+	let mnemonics: HashSet<&str> = INSTRUCTION_SET.iter().map(|info| info.mnemonic).collect();
+	assert_eq!(mnemonics.len(), INSTRUCTION_SET.len());
+}
+
+// the operand counts in the table have to match what Processor::step reads
+#[test]
+fn operand_counts_match_execution() {
+	for info in INSTRUCTION_SET.iter() {
+		let mut machine = Machine::with_syscall_host(Box::new(RecordingSyscallHost::new()));
+		machine.initialize(&[0, info.code, 1, 1, 1]);
+		machine.start_trace();
+		machine.step_into();
+
+		let record = machine.trace().next().unwrap();
+		assert_eq!(record.params.len() as u32, info.opcode.operand_count(), "{}", info.mnemonic);
+	}
+}
+
+// the entries of the _DslOpcodes table the TypeScript compiler emits code with:
+// the key, the number of arguments and the opcode
+fn ts_opcodes() -> Vec<(String, usize, u32)> {
+	let source = include_str!("../../js/src/utils/language/dslmachine.ts");
+	let start = source.find("export const _DslOpcodes = {").unwrap();
+	let end = start + source[start..].find("\n};").unwrap();
+	let body: Vec<&str> = source[start..end].lines().skip(1)
+		.map(|line| line.trim())
+		.filter(|line| !line.starts_with("//"))
+		.collect();
+
+	let mut entries = Vec::new();
+	for entry in body.join(" ").split("],").filter(|entry| !entry.trim().is_empty()) {
+		let colon = entry.find(':').unwrap();
+		let args = &entry[entry.find('(').unwrap() + 1..entry.find(')').unwrap()];
+		let words = &entry[entry.find('[').unwrap() + 1..];
+		let code = words.split(',').next().unwrap().trim().parse().unwrap();
+		entries.push((entry[..colon].trim().to_string(), args.split(',').filter(|arg| !arg.trim().is_empty()).count(), code));
+	}
+	return entries;
+}
+
+#[test]
+fn typescript_table_matches() {
+	let entries = ts_opcodes();
+	for (key, args, code) in entries.iter() {
+		match INSTRUCTION_SET.iter().find(|info| info.mnemonic == key) {
+			Some(info) => {
+				assert_eq!(*code, info.code, "{}", key);
+				assert_eq!(*args as u32, info.opcode.operand_count(), "{}", key);
+			},
+			// the rest are helpers for particular syscalls
+			None => assert_eq!((*code, *args), (Opcode::Syscall.code(), 0), "{}", key),
+		}
+	}
+	for info in INSTRUCTION_SET.iter() {
+		assert!(entries.iter().any(|(key, _, _)| key == info.mnemonic), "{} is missing", info.mnemonic);
+	}
+}
+
+#[test]
+fn json_strings_are_escaped() {
+	assert_eq!(json_string("a \"b\" \\ c\n"), "\"a \\\"b\\\" \\\\ c\\u000a\"");
+	assert_eq!(json_strings(&json_string("a \"b\" \\ c\n")), vec!["a \"b\" \\ c\n"]);
+
+	let mut expected = Vec::new();
+	for info in INSTRUCTION_SET.iter() {
+		expected.extend(["code", "mnemonic", info.mnemonic, "operands"].iter().map(|s| s.to_string()));
+		expected.extend(info.operands.iter().map(|kind| kind.name().to_string()));
+		expected.extend(["privileged", "description", info.description].iter().map(|s| s.to_string()));
+	}
+	assert_eq!(json_strings(&to_json()), expected);
+}
@@ -0,0 +1,322 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RecordingSyscallHost, MAX_PAGES};
+
+mod common;
+
+// history is off by default, this is the limit the tests turn it on with
+const HISTORY: usize = 1024;
+// the stack is at the top of the first memory block, so this covers it too
+const COMPARED_WORDS: u32 = 32 * 1024;
+
+fn load(code: &[u32], jit: bool, history: usize) -> Machine {
+	// random programs can jump onto a syscall
+	let mut machine = load_with(Machine::with_syscall_host(Box::new(RecordingSyscallHost::new())), code, jit);
+	machine.set_history_limit(history);
+	return machine;
+}
+
+fn load_with(mut machine: Machine, code: &[u32], jit: bool) -> Machine {
+	machine.set_jit(jit);
+	return common::load_into(machine, code);
+}
+
+#[derive(Debug, PartialEq)]
+struct State {
+	instruction_pointer: u32,
+	bus: u32,
+	status: i32,
+	stack_pointer: u32,
+	call_stack: Vec<u32>,
+	fault: Option<Fault>,
+	fault_address: u32,
+	memory: Vec<u32>,
+}
+
+fn state(machine: &Machine) -> State {
+	return State {
+		instruction_pointer: machine.instruction_pointer(),
+		bus: machine.bus(),
+		status: machine.status(),
+		stack_pointer: machine.stack_pointer(),
+		call_stack: machine.call_stack(),
+		fault: machine.fault(),
+		fault_address: machine.last_fault_address(),
+		memory: (0..COMPARED_WORDS).map(|address| machine.read_memory(address)).collect(),
+	};
+}
+
+// steps and the reason as a code, so runs and stepping commands compare the same way
+fn run(machine: &mut Machine, budget: Option<u32>) -> (u32, i32) {
+	let (steps, reason) = machine.run_budget(budget);
+	return (steps, reason.code());
+}
+
+fn stepped(machine: &mut Machine, command: impl FnOnce(&mut Machine) -> i32) -> (u32, i32) {
+	let steps = command(machine);
+	return (steps as u32, machine.last_run_reason());
+}
+
+// runs the program interpreted and compiled, checking that every run returns the same
+// and that both machines end in the same state. without history and then with HISTORY,
+// returns the compiled machine with history
+fn differential(code: &[u32], mut run: impl FnMut(&mut Machine) -> Vec<(u32, i32)>) -> Machine {
+	let mut compiled = None;
+	for &history in [0, HISTORY].iter() {
+		let mut interpreted = load(code, false, history);
+		let mut machine = load(code, true, history);
+		assert_eq!(run(&mut interpreted), run(&mut machine));
+		assert_eq!(state(&interpreted), state(&machine));
+		assert_eq!(interpreted.jit_block_count(), 0);
+		compiled = Some(machine);
+	}
+	return compiled.unwrap();
+}
+
+// counts memory[100] up to memory[101]
+fn counting_loop(limit: u32) -> Vec<u32> {
+	let mut code = vec![
+		1, 100, // 1: counter -> bus
+		25, // 3: push onto the ALU
+		24, 1, // 4: 1 -> bus
+		9, // 6: add
+		16, // 7: hi -> bus
+		2, 100, // 8: bus -> counter
+		25, // 10: push the counter
+		1, 101, // 11: limit -> bus
+		25, // 13: push the limit
+		29, 4, // 14: counter < limit
+		14, 1, // 16: goto 1 if it is
+		100, // 18: halt
+	];
+	code.resize(101, 0);
+	code[100] = limit;
+	return code;
+}
+
+#[test]
+fn hot_loops_are_compiled() {
+	let machine = differential(&counting_loop(1000), |machine| vec![run(machine, None)]);
+	assert_eq!(machine.read_memory(100), 1000);
+	assert!(machine.is_halted());
+	assert!(machine.jit_block_count() > 0);
+}
+
+#[test]
+fn budgets_split_blocks() {
+	differential(&counting_loop(1000), |machine| {
+		let mut runs = Vec::new();
+		for budget in [1, 7, 50, 3, 1000, 11, 5000].iter() {
+			runs.push(run(machine, Some(*budget)));
+		}
+		return runs;
+	});
+}
+
+#[test]
+fn stepping_commands_stop_inside_blocks() {
+	differential(&counting_loop(1000), |machine| {
+		return vec![
+			run(machine, Some(500)),
+			stepped(machine, |machine| machine.step_n(333)),
+			stepped(machine, |machine| machine.run_to(11)),
+			stepped(machine, |machine| machine.run_to(7)),
+			run(machine, None),
+		];
+	});
+}
+
+#[test]
+fn breakpoints_are_interpreted() {
+	let machine = differential(&counting_loop(1000), |machine| {
+		let mut runs = vec![run(machine, Some(2000))];
+		machine.set_breakpoint(8);
+		machine.set_breakpoint_ignore_count(8, 3);
+		machine.enable_breakpoints();
+		runs.push(run(machine, None));
+		runs.push(run(machine, None));
+		machine.disable_breakpoints();
+		runs.push(run(machine, None));
+		return runs;
+	});
+	assert_eq!(machine.breakpoint_hit_count(8), 5);
+}
+
+#[test]
+fn logpoints_log_the_same() {
+	let mut logs = Vec::new();
+	differential(&counting_loop(100), |machine| {
+		assert!(machine.set_logpoint(8, "counter {bus}"));
+		machine.enable_breakpoints();
+		let runs = vec![run(machine, None)];
+		logs.push(machine.take_breakpoint_log());
+		return runs;
+	});
+	assert_eq!(logs.len(), 4);
+	assert_eq!(logs[0].len(), 100);
+	assert!(logs.iter().all(|log| *log == logs[0]));
+}
+
+#[test]
+fn calls_and_the_stack() {
+	let mut code = vec![
+		36, 30, // 1: call 30
+		1, 100, // 3: counter -> bus
+		25, // 5: push onto the ALU
+		24, 1, // 6: 1 -> bus
+		9, // 8: add
+		16, // 9: hi -> bus
+		2, 100, // 10: bus -> counter
+		25, // 12: push the counter
+		1, 101, // 13: limit -> bus
+		25, // 15: push the limit
+		29, 4, // 16: counter < limit
+		14, 1, // 18: goto 1 if it is
+		100, // 20: halt
+	];
+	code.resize(29, 0);
+	code.extend_from_slice(&[
+		1, 102, // 30: total -> bus
+		34, // 32: push onto the stack
+		35, // 33: and pop it again
+		25, // 34: push onto the ALU
+		24, 3, // 35: 3 -> bus
+		9, // 37: add
+		16, // 38: hi -> bus
+		2, 102, // 39: bus -> total
+		37, // 41: return
+	]);
+	code.resize(103, 0);
+	code[100] = 200;
+
+	let machine = differential(&code, |machine| {
+		return vec![
+			run(machine, Some(1234)),
+			stepped(machine, |machine| machine.step_out()),
+			run(machine, None),
+		];
+	});
+	assert_eq!(machine.read_memory(102), 600);
+}
+
+#[test]
+fn self_modifying_code() {
+	let mut code = vec![
+		24, 0, // 1: the immediate at 2 is the counter
+		25, // 3: push onto the ALU
+		24, 1, // 4: 1 -> bus
+		9, // 6: add
+		16, // 7: hi -> bus
+		2, 2, // 8: bus -> the immediate at 2
+		2, 100, // 10: bus -> memory[100]
+		25, // 12: push the counter
+		24, 500, // 13: 500 -> bus
+		25, // 15: push the limit
+		29, 4, // 16: counter < limit
+		14, 1, // 18: goto 1 if it is
+		100, // 20: halt
+	];
+	code.resize(101, 0);
+
+	let machine = differential(&code, |machine| vec![run(machine, None)]);
+	assert_eq!(machine.read_memory(100), 500);
+}
+
+#[test]
+fn code_changed_from_outside() {
+	differential(&counting_loop(1000), |machine| {
+		let mut runs = vec![run(machine, Some(600))];
+		// count by 2 from now on
+		machine.write_memory(5, 2);
+		runs.push(run(machine, None));
+		return runs;
+	});
+}
+
+#[test]
+fn blocks_at_the_top_of_memory() {
+	// the loop branches to the last instruction of memory, which wraps around to 0 and 1
+	let top = u32::MAX - 1;
+	let mut code = counting_loop(100);
+	code[17] = top;
+	let machine = differential(&code, |machine| {
+		machine.set_memory_pages(MAX_PAGES);
+		machine.write_memory(top, 24);
+		machine.write_memory(top + 1, 7);
+		return vec![run(machine, None)];
+	});
+	assert_eq!(machine.read_memory(100), 100);
+	assert!(machine.jit_block_count() > 0);
+}
+
+#[test]
+fn faults_inside_blocks() {
+	// 1: push the bus, 2: 1 -> bus, 4: jump to bus, until the stack overflows
+	let machine = differential(&[34, 24, 1, 13], |machine| vec![run(machine, None)]);
+	assert!(matches!(machine.fault(), Some(Fault::StackOverflow(_))));
+	assert_eq!(machine.instruction_pointer(), 1);
+}
+
+#[test]
+fn syscalls_are_interpreted() {
+	// 1: 7 -> bus, 3: syscall 5, 5: 1 -> bus, 7: jump to bus
+	let code = [24, 7, 21, 5, 24, 1, 13];
+	let interpreted_host = RecordingSyscallHost::new();
+	let compiled_host = RecordingSyscallHost::new();
+	let mut interpreted = load_with(Machine::with_syscall_host(Box::new(interpreted_host.clone())), &code, false);
+	let mut compiled = load_with(Machine::with_syscall_host(Box::new(compiled_host.clone())), &code, true);
+
+	assert_eq!(interpreted.run_budget(Some(999)), compiled.run_budget(Some(999)));
+	assert_eq!(state(&interpreted), state(&compiled));
+	assert_eq!(compiled_host.calls().len(), 250);
+	assert_eq!(interpreted_host.calls(), compiled_host.calls());
+}
+
+#[test]
+fn stepping_back_through_compiled_blocks() {
+	let mut interpreted = load(&counting_loop(1000), false, HISTORY);
+	let mut compiled = load(&counting_loop(1000), true, HISTORY);
+	assert_eq!(run(&mut interpreted, Some(5000)), run(&mut compiled, Some(5000)));
+	assert!(compiled.jit_block_count() > 0);
+	assert_eq!(compiled.history_length(), HISTORY);
+
+	for _ in 0..700 {
+		assert!(interpreted.step_back());
+		assert!(compiled.step_back());
+	}
+	assert_eq!(state(&interpreted), state(&compiled));
+	assert_eq!(run(&mut interpreted, None), run(&mut compiled, None));
+	assert_eq!(state(&interpreted), state(&compiled));
+}
+
+// small random programs, mostly loops over a handful of addresses
+#[test]
+fn random_programs() {
+	let opcodes: Vec<u32> = (0..=38).chain(Some(100)).collect();
+	let mut seed: u32 = 12345;
+	let mut next = move |n: u32| {
+		seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+		return (seed >> 8) % n;
+	};
+
+	let mut compiled_blocks = 0;
+	for _ in 0..200 {
+		let mut code = Vec::new();
+		while code.len() < 60 {
+			let opcode = opcodes[next(opcodes.len() as u32) as usize];
+			code.push(opcode);
+			// addresses and small values, most of them inside the program
+			code.push(next(80));
+			code.push(next(6));
+		}
+		let machine = differential(&code, |machine| {
+			return vec![
+				run(machine, Some(3000)),
+				run(machine, Some(17)),
+				run(machine, Some(3000)),
+			];
+		});
+		compiled_blocks += machine.jit_block_count();
+	}
+	assert!(compiled_blocks > 0);
+}
@@ -0,0 +1,136 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RunReason, UnmappedAccess, MAX_PAGES, TRAP_CAUSE, TRAP_SYSTEM_CALL, TRAP_VECTORS};
+use rust_asm::permissions::PAGE_SIZE;
+
+mod common;
+use common::load;
+
+const HIGH: u32 = 4_000_000_000;
+
+#[test]
+fn pages_are_allocated_on_first_write() {
+	// 1: 7 -> bus, 3: bus -> memory[5000]
+	let mut machine = load(&[24, 7, 2, 5000, 22]);
+	assert_eq!(machine.memory_pages(), 32);
+	assert_eq!(machine.allocated_pages(), 1);
+	assert_eq!(machine.read_memory(20_000), 0);
+	assert_eq!(machine.allocated_pages(), 1);
+
+	assert_eq!(machine.run_budget(None), (3, RunReason::Halted));
+	assert_eq!(machine.read_memory(5000), 7);
+	assert_eq!(machine.allocated_pages(), 2);
+}
+
+#[test]
+fn large_address_spaces_only_use_the_pages_written() {
+	let mut machine = load(&[24, 7, 2, HIGH, 24, 8, 2, u32::MAX, 22]);
+	machine.set_memory_pages(MAX_PAGES);
+	assert_eq!(machine.run_budget(None), (5, RunReason::Halted));
+	assert_eq!(machine.read_memory(HIGH), 7);
+	assert_eq!(machine.read_memory(u32::MAX), 8);
+	assert_eq!(machine.allocated_pages(), 3);
+
+	// clamped to the 32 bit address space
+	machine.set_memory_pages(u32::MAX);
+	assert_eq!(machine.memory_pages(), MAX_PAGES);
+}
+
+// 1: goto address, with memory grown to the whole address space, code at address and halt at 0
+fn at_the_top(address: u32, code: &[u32]) -> Machine {
+	let mut machine = load(&[24, address, 13]);
+	machine.set_memory_pages(MAX_PAGES);
+	for (i, &word) in code.iter().enumerate() {
+		machine.write_memory(address.wrapping_add(i as u32), word);
+	}
+	machine.write_memory(0, 22);
+	return machine;
+}
+
+#[test]
+fn instructions_at_the_top_of_memory_wrap_around() {
+	// a noop in the last word
+	let mut machine = at_the_top(u32::MAX, &[0]);
+	assert_eq!(machine.run_budget(None), (4, RunReason::Halted));
+
+	// a call in the last two words, to a return at 10
+	let top = u32::MAX - 1;
+	let mut machine = at_the_top(top, &[36, 10]);
+	machine.write_memory(10, 37);
+	assert_eq!(machine.run_budget(Some(3)), (3, RunReason::BudgetExhausted));
+	// call site, target, return address, stack pointer
+	assert_eq!(machine.call_stack(), vec![top, 10, 0, machine.stack_pointer()]);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Halted));
+
+	// a trap in the last two words, to a handler at 100 that returns
+	let mut machine = at_the_top(top, &[49, 5]);
+	machine.write_memory(TRAP_VECTORS + TRAP_SYSTEM_CALL, 100);
+	machine.write_memory(100, 48);
+	assert_eq!(machine.run_budget(None), (5, RunReason::Halted));
+	assert_eq!(machine.read_memory(TRAP_CAUSE), TRAP_SYSTEM_CALL);
+}
+
+#[test]
+fn unmapped_writes_fault_and_reads_can_be_made_to() {
+	let mut machine = load(&[24, 7, 2, HIGH, 22]);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::MemoryOutOfBounds(HIGH)));
+	assert_eq!(machine.allocated_pages(), 1);
+
+	// 1: memory[HIGH] -> bus
+	let mut machine = load(&[1, HIGH, 22]);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Halted));
+
+	let mut machine = load(&[1, HIGH, 22]);
+	machine.set_unmapped_access(UnmappedAccess::Fault);
+	assert_eq!(machine.run_budget(None), (1, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::MemoryOutOfBounds(HIGH)));
+}
+
+#[test]
+fn opcode_20_maps_another_block_and_stepping_back_unmaps_it() {
+	// 1: map 32K more words, 2: 7 -> bus, 4: bus -> memory[40000]
+	let mut machine = load(&[20, 24, 7, 2, 40_000, 22]);
+	machine.set_history_limit(1024);
+	assert_eq!(machine.run_budget(None), (4, RunReason::Halted));
+	assert_eq!(machine.memory_pages(), 64);
+	assert_eq!(machine.read_memory(40_000), 7);
+
+	while machine.step_back() {}
+	assert_eq!(machine.memory_pages(), 32);
+	assert_eq!(machine.read_memory(40_000), 0);
+	assert_eq!(machine.allocated_pages(), 1);
+}
+
+#[test]
+fn shrinking_memory_frees_the_pages_past_the_end() {
+	let mut machine = load(&[]);
+	machine.write_memory(10 * PAGE_SIZE, 1);
+	machine.write_memory(20 * PAGE_SIZE, 2);
+	assert_eq!(machine.allocated_pages(), 3);
+
+	machine.set_memory_pages(15);
+	assert_eq!(machine.allocated_pages(), 2);
+	assert_eq!(machine.read_memory(20 * PAGE_SIZE), 0);
+	machine.set_memory_pages(32);
+	assert_eq!(machine.read_memory(20 * PAGE_SIZE), 0);
+	assert_eq!(machine.read_memory(10 * PAGE_SIZE), 1);
+}
+
+#[test]
+fn snapshots_only_store_written_pages() {
+	let mut machine = load(&[24, 7, 2, HIGH, 22]);
+	machine.set_memory_pages(MAX_PAGES);
+	machine.set_unmapped_access(UnmappedAccess::Fault);
+	machine.run();
+	let snapshot = machine.save_snapshot();
+	assert!(snapshot.len() < 256);
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert_eq!(restored.memory_pages(), MAX_PAGES);
+	assert_eq!(restored.read_memory(HIGH), 7);
+	assert_eq!(restored.read_memory(3), 2);
+	assert_eq!(restored.allocated_pages(), 2);
+	assert_eq!(restored.save_snapshot(), snapshot);
+}
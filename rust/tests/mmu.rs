@@ -0,0 +1,254 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RunReason, TRAP_ADDRESS, TRAP_CAUSE, TRAP_PAGE_FAULT, TRAP_VECTORS};
+use rust_asm::mmu::{page_table_entry, PTE_USER, PTE_VALID};
+use rust_asm::permissions::{EXECUTE, PAGE_SIZE, READ, WRITE};
+
+mod common;
+use common::load;
+
+// physical page 20
+const TABLE: u32 = 20 * PAGE_SIZE;
+const PAGE_FAULT_VECTOR: u32 = TRAP_VECTORS + TRAP_PAGE_FAULT;
+const ALL: u32 = READ | WRITE | EXECUTE | PTE_VALID;
+
+// maps virtual page to the physical page holding address
+fn map(machine: &mut Machine, page: u32, address: u32, flags: u32) {
+	machine.write_memory(TABLE + page, page_table_entry(address, flags));
+}
+
+// the code, the page table and the stack are identity mapped for the supervisor
+fn kernel(code: &[u32]) -> Machine {
+	let mut machine = load(code);
+	map(&mut machine, 0, 0, ALL);
+	map(&mut machine, 20, TABLE, READ | WRITE | PTE_VALID);
+	map(&mut machine, 31, 31 * PAGE_SIZE, READ | WRITE | PTE_VALID);
+	return machine;
+}
+
+// 1: page table at TABLE with 32 entries, 5: paging on
+fn enable_paging() -> Vec<u32> {
+	return vec![24, TABLE, 45, 32, 46];
+}
+
+#[test]
+fn addresses_are_translated_through_the_page_table() {
+	// 6: 7 -> virtual 1029
+	let mut code = enable_paging();
+	code.extend(&[24, 7, 2, PAGE_SIZE + 5, 22]);
+	let mut machine = kernel(&code);
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | WRITE | PTE_VALID);
+
+	assert_eq!(machine.run_budget(None), (6, RunReason::Halted));
+	assert_eq!(machine.read_memory(10 * PAGE_SIZE + 5), 7);
+	assert_eq!(machine.read_memory(PAGE_SIZE + 5), 0);
+	assert!(machine.paging_enabled());
+	assert_eq!(machine.translate(PAGE_SIZE + 5), Some(10 * PAGE_SIZE + 5));
+	assert_eq!(machine.translate(2 * PAGE_SIZE), None);
+	assert!(machine.tlb_stats().1 > 0);
+}
+
+#[test]
+fn breakpoint_conditions_read_virtual_addresses() {
+	let mut code = enable_paging();
+	code.extend(&[24, 7, 2, PAGE_SIZE + 5, 22]);
+	let mut machine = kernel(&code);
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | WRITE | PTE_VALID);
+	machine.enable_breakpoints();
+	assert!(machine.set_breakpoint_condition(10, &format!("mem[{}] == 7", PAGE_SIZE + 5)));
+
+	assert_eq!(machine.run_budget(None), (5, RunReason::Breakpoint));
+	assert_eq!(machine.instruction_pointer(), 10);
+}
+
+#[test]
+fn instructions_can_cross_into_a_page_mapped_elsewhere() {
+	// 6: goto 1023, where 7 -> bus has its parameter on virtual page 1
+	let mut code = enable_paging();
+	code.extend(&[24, 1023, 13]);
+	let mut machine = kernel(&code);
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | EXECUTE | PTE_VALID);
+	machine.write_memory(1023, 24);
+	machine.write_memory(10 * PAGE_SIZE, 7);
+	machine.write_memory(10 * PAGE_SIZE + 1, 22);
+
+	assert_eq!(machine.run_budget(None), (7, RunReason::Halted));
+	assert_eq!(machine.bus(), 7);
+	assert_eq!(machine.instruction_pointer(), PAGE_SIZE + 1);
+}
+
+#[test]
+fn remapping_the_page_a_parameter_is_on_changes_the_instruction() {
+	// 6: goto 1023, where the parameter of bus <- immediate is on virtual page 1.
+	// 1025 maps page 1 to physical page 11 instead, which goes back to 1023 and halts at 1025
+	let mut code = enable_paging();
+	code.extend(&[24, 1023, 13]);
+	let mut machine = kernel(&code);
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | EXECUTE | PTE_VALID);
+	machine.write_memory(1023, 24);
+	let remap = [7, 24, page_table_entry(11 * PAGE_SIZE, READ | EXECUTE | PTE_VALID), 2, TABLE + 1, 24, 1023, 13];
+	for (i, &word) in remap.iter().enumerate() {
+		machine.write_memory(10 * PAGE_SIZE + i as u32, word);
+	}
+	for (i, &word) in [8, 22, 0, 0, 0, 24, 1023, 13].iter().enumerate() {
+		machine.write_memory(11 * PAGE_SIZE + i as u32, word);
+	}
+
+	assert_eq!(machine.run_budget(None), (12, RunReason::Halted));
+	assert_eq!(machine.bus(), 8);
+}
+
+#[test]
+fn step_over_reads_the_call_through_the_page_table() {
+	// 6: goto 1024, virtual page 1 is physical page 10.
+	// 1024: call 1034, halt. 1034: 7 -> bus, return
+	let mut code = enable_paging();
+	code.extend(&[24, PAGE_SIZE, 13]);
+	let mut machine = kernel(&code);
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | EXECUTE | PTE_VALID);
+	for (i, &word) in [36, PAGE_SIZE + 10, 22].iter().enumerate() {
+		machine.write_memory(10 * PAGE_SIZE + i as u32, word);
+	}
+	for (i, &word) in [24, 7, 37].iter().enumerate() {
+		machine.write_memory(10 * PAGE_SIZE + 10 + i as u32, word);
+	}
+	assert_eq!(machine.run_budget(Some(5)), (5, RunReason::BudgetExhausted));
+	assert_eq!(machine.instruction_pointer(), PAGE_SIZE);
+	machine.pause();
+
+	machine.step_over();
+	assert_eq!(machine.instruction_pointer(), PAGE_SIZE + 2);
+	assert_eq!(machine.bus(), 7);
+	assert!(machine.call_stack().is_empty());
+}
+
+// 200: maps virtual page 1 to physical page 10 and returns to the faulting instruction,
+// keeping the bus on the stack
+fn demand_paging() -> Machine {
+	let mut code = enable_paging();
+	code.extend(&[24, 7, 2, PAGE_SIZE + 5, 22]);
+	let mut machine = kernel(&code);
+	let handler = [34, 24, page_table_entry(10 * PAGE_SIZE, READ | WRITE | PTE_VALID), 2, TABLE + 1, 35, 48];
+	for (i, &word) in handler.iter().enumerate() {
+		machine.write_memory(200 + i as u32, word);
+	}
+	machine.write_memory(PAGE_FAULT_VECTOR, 200);
+	return machine;
+}
+
+#[test]
+fn page_faults_trap_and_the_instruction_runs_again() {
+	let mut machine = demand_paging();
+	let stack_pointer = machine.stack_pointer();
+
+	assert_eq!(machine.run_budget(None), (12, RunReason::Halted));
+	assert_eq!(machine.read_memory(10 * PAGE_SIZE + 5), 7);
+	assert_eq!(machine.read_memory(TRAP_CAUSE), TRAP_PAGE_FAULT);
+	assert_eq!(machine.read_memory(TRAP_ADDRESS), PAGE_SIZE + 5);
+	assert_eq!(machine.stack_pointer(), stack_pointer);
+	assert!(machine.call_stack().is_empty());
+	assert!(machine.is_supervisor());
+}
+
+#[test]
+fn page_faults_without_a_handler_fault() {
+	let mut machine = demand_paging();
+	machine.write_memory(PAGE_FAULT_VECTOR, 0);
+
+	assert_eq!(machine.run_budget(None), (5, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::PageFault(PAGE_SIZE + 5)));
+	assert_eq!(machine.last_fault_address(), 8);
+}
+
+// 6: pushes 2048 and mode 0, and returns from the trap into user mode at 2048
+// 2048: 7 -> virtual 1029
+fn user_program() -> Machine {
+	let mut code = enable_paging();
+	code.extend(&[24, 2 * PAGE_SIZE, 34, 24, 0, 34, 48]);
+	let mut machine = kernel(&code);
+	map(&mut machine, 2, 2 * PAGE_SIZE, READ | EXECUTE | PTE_VALID | PTE_USER);
+	for (i, &word) in [24, 7, 2, PAGE_SIZE + 5, 22].iter().enumerate() {
+		machine.write_memory(2 * PAGE_SIZE + i as u32, word);
+	}
+	return machine;
+}
+
+#[test]
+fn user_mode_can_only_use_user_pages() {
+	let mut machine = user_program();
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | WRITE | PTE_VALID);
+	assert_eq!(machine.run_budget(None), (10, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::PageFault(PAGE_SIZE + 5)));
+	assert_eq!(machine.last_fault_address(), 2 * PAGE_SIZE + 2);
+	assert!(!machine.is_supervisor());
+
+	let mut machine = user_program();
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | WRITE | PTE_VALID | PTE_USER);
+	assert_eq!(machine.run_budget(None), (11, RunReason::Halted));
+	assert_eq!(machine.read_memory(10 * PAGE_SIZE + 5), 7);
+}
+
+#[test]
+fn stepping_back_restores_the_mode_and_the_page_table() {
+	let mut machine = demand_paging();
+	machine.set_history_limit(1024);
+	machine.run();
+	assert_eq!(machine.read_memory(10 * PAGE_SIZE + 5), 7);
+
+	// to the store, before the trap was taken
+	for _ in 0..8 {
+		machine.step_back();
+	}
+	assert_eq!(machine.instruction_pointer(), 8);
+	assert_eq!(machine.read_memory(TABLE + 1), 0);
+	assert!(machine.paging_enabled());
+
+	while machine.step_back() {}
+	assert!(!machine.paging_enabled());
+	assert_eq!(machine.read_memory(TRAP_ADDRESS), 0);
+
+	// and running again takes the same trap
+	machine.run();
+	assert_eq!(machine.read_memory(10 * PAGE_SIZE + 5), 7);
+	assert_eq!(machine.read_memory(TRAP_ADDRESS), PAGE_SIZE + 5);
+}
+
+#[test]
+fn the_disassembler_reads_virtual_addresses() {
+	let mut machine = kernel(&[]);
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | EXECUTE | PTE_VALID);
+	// virtual 1024: 7 -> bus, halt. 2047: a load with its parameter on unmapped page 2
+	for (i, &word) in [24, 7, 22].iter().enumerate() {
+		machine.write_memory(10 * PAGE_SIZE + i as u32, word);
+	}
+	machine.write_memory(11 * PAGE_SIZE - 1, 24);
+	machine.set_page_table(TABLE, 32);
+	machine.set_paging(true);
+	let tlb = machine.tlb_stats();
+
+	assert_eq!(machine.disassemble(PAGE_SIZE, PAGE_SIZE + 3), format!("{}: LoadImmediateToBus 7\n{}: Halt\n", PAGE_SIZE, PAGE_SIZE + 2));
+	assert_eq!(machine.disassemble(2 * PAGE_SIZE, 2 * PAGE_SIZE + 1), format!("{}: unmapped\n", 2 * PAGE_SIZE));
+	assert_eq!(machine.decode(2 * PAGE_SIZE - 1).params, vec![0]);
+	assert!(!machine.decode(2 * PAGE_SIZE).mapped);
+	assert_eq!(machine.tlb_stats(), tlb);
+}
+
+#[test]
+fn snapshots_keep_the_mmu_state() {
+	let mut machine = user_program();
+	map(&mut machine, 1, 10 * PAGE_SIZE, READ | WRITE | PTE_VALID | PTE_USER);
+	// in user mode, before the store
+	assert_eq!(machine.run_budget(Some(9)), (9, RunReason::BudgetExhausted));
+	let snapshot = machine.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert!(restored.paging_enabled());
+	assert!(!restored.is_supervisor());
+	assert_eq!(restored.translate(PAGE_SIZE + 5), Some(10 * PAGE_SIZE + 5));
+	assert_eq!(restored.save_snapshot(), snapshot);
+
+	restored.run();
+	assert!(restored.is_halted());
+	assert_eq!(restored.read_memory(10 * PAGE_SIZE + 5), 7);
+}
@@ -0,0 +1,106 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine};
+
+mod common;
+
+fn run(code: &[u32]) -> Machine {
+	let mut machine = common::load(code);
+	machine.run();
+	return machine;
+}
+
+#[test]
+fn opcode_8_saves_bus_relative_to_current_with_bus_offset() {
+	// 24 5: 5 -> bus, 8 at address 3: bus -> memory[3 + 5]
+	let machine = run(&[24, 5, 8, 100]);
+
+	assert!(machine.is_halted());
+	assert_eq!(machine.read_memory(8), 5);
+}
+
+#[test]
+fn opcode_8_handles_negative_offsets() {
+	// -2 -> bus, 8 at address 3 writes to address 1
+	let machine = run(&[24, -2i32 as u32, 8, 100]);
+
+	assert!(machine.is_halted());
+	assert_eq!(machine.read_memory(1), -2i32 as u32);
+}
+
+#[test]
+fn opcode_10_negates_ints() {
+	// 24 7: 7 -> bus, 10: negate, 16: hi -> bus
+	let machine = run(&[24, 7, 10, 16, 100]);
+
+	assert_eq!(machine.bus(), -7i32 as u32);
+}
+
+#[test]
+fn opcode_10_negates_floats() {
+	// 19: ALU to float mode
+	let machine = run(&[19, 24, 2.5f32.to_bits(), 10, 16, 100]);
+
+	assert_eq!(machine.bus(), (-2.5f32).to_bits());
+}
+
+#[test]
+fn opcode_22_halts_without_moving() {
+	let machine = run(&[22, 24, 1]);
+
+	assert!(machine.is_halted());
+	assert_eq!(machine.fault(), None);
+	assert_eq!(machine.bus(), 0);
+	assert_eq!(machine.instruction_pointer(), 1);
+}
+
+#[test]
+fn call_and_return_restore_the_stack() {
+	// 1: call 6, 3: halt, 6: 9 -> bus, 8: return
+	let machine = run(&[36, 6, 100, 0, 0, 24, 9, 37]);
+
+	assert!(machine.is_halted());
+	assert_eq!(machine.instruction_pointer(), 3);
+	assert_eq!(machine.bus(), 9);
+	assert_eq!(machine.stack_pointer(), 32768);
+	assert!(machine.call_stack().is_empty());
+}
+
+#[test]
+fn call_stack_lists_active_frames() {
+	// 1: call 6, 3: halt, 6: pause, 7: return
+	let mut machine = run(&[36, 6, 100, 0, 0, 23, 37]);
+
+	assert!(machine.is_paused());
+	assert_eq!(machine.call_stack(), vec![1, 6, 3, 32767]);
+
+	machine.run();
+	assert!(machine.is_halted());
+	assert!(machine.call_stack().is_empty());
+}
+
+#[test]
+fn push_and_pop_round_trip() {
+	// 5 -> bus, push, 7 -> bus, push, pop, pop
+	let machine = run(&[24, 5, 34, 24, 7, 34, 35, 35, 100]);
+
+	assert_eq!(machine.bus(), 5);
+	assert_eq!(machine.stack_pointer(), 32768);
+}
+
+#[test]
+fn return_with_empty_stack_faults() {
+	let machine = run(&[37]);
+
+	assert_eq!(machine.fault(), Some(Fault::StackUnderflow(32768)));
+	assert_eq!(machine.last_fault_address(), 1);
+}
+
+#[test]
+fn pushing_forever_overflows() {
+	// 1: 3 -> bus, 3: push, 4: 3 -> bus, 6: jump to bus
+	let machine = run(&[24, 3, 34, 24, 3, 13]);
+
+	assert_eq!(machine.fault(), Some(Fault::StackOverflow(32768 - 1024)));
+	assert_eq!(machine.last_fault_address(), 3);
+}
@@ -0,0 +1,187 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RunReason};
+use rust_asm::permissions::{PAGE_SIZE, READ, WRITE, EXECUTE, ALL_PERMISSIONS};
+
+mod common;
+use common::load;
+
+const DATA: u32 = PAGE_SIZE;
+
+// 1: memory[DATA] + 1 -> memory[DATA], 10: jump to 1, forever
+fn counter() -> Vec<u32> {
+	return vec![1, DATA, 25, 24, 1, 9, 16, 2, DATA, 24, 1, 13];
+}
+
+#[test]
+fn pages_start_with_all_permissions() {
+	let mut machine = load(&counter());
+	assert_eq!(machine.page_permissions(0), ALL_PERMISSIONS);
+	assert_eq!(machine.page_permissions(u32::MAX), ALL_PERMISSIONS);
+	machine.run_budget(Some(100));
+	assert_eq!(machine.fault(), None);
+}
+
+#[test]
+fn ranges_cover_every_page_they_touch() {
+	let mut machine = load(&[]);
+	machine.set_permissions(PAGE_SIZE - 1, 2 * PAGE_SIZE, READ);
+	assert_eq!(machine.page_permissions(0), READ);
+	assert_eq!(machine.page_permissions(PAGE_SIZE), READ);
+	assert_eq!(machine.page_permissions(2 * PAGE_SIZE + PAGE_SIZE - 1), READ);
+	assert_eq!(machine.page_permissions(3 * PAGE_SIZE), ALL_PERMISSIONS);
+
+	// bits past execute are dropped
+	machine.set_permissions(0, 0, 0xff);
+	assert_eq!(machine.page_permissions(0), ALL_PERMISSIONS);
+}
+
+#[test]
+fn pushes_to_a_read_only_stack_fault_without_moving_it() {
+	let programs = [
+		// 1: push 7
+		&[24, 7, 34][..],
+		// 1: call 10
+		&[36, 10][..],
+	];
+	for code in programs.iter() {
+		let mut machine = load(code);
+		let stack_pointer = machine.stack_pointer();
+		machine.set_permissions(stack_pointer - 1, stack_pointer - 1, READ);
+		machine.run_budget(None);
+		assert_eq!(machine.fault(), Some(Fault::WriteViolation(stack_pointer - 1)));
+		assert_eq!(machine.stack_pointer(), stack_pointer);
+		assert!(machine.call_stack().is_empty());
+	}
+}
+
+#[test]
+fn writes_to_read_only_code_fault() {
+	// 1: 7 -> bus, 3: bus -> memory[1]
+	let mut machine = load(&[24, 7, 2, 1, 22]);
+	machine.set_permissions(0, PAGE_SIZE - 1, READ | EXECUTE);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::WriteViolation(1)));
+	assert_eq!(machine.last_fault_address(), 3);
+	assert_eq!(machine.read_memory(1), 24);
+}
+
+#[test]
+fn reads_without_the_read_permission_fault() {
+	let mut machine = load(&[1, DATA, 22]);
+	machine.set_permissions(DATA, DATA, WRITE);
+	assert_eq!(machine.run_budget(None), (1, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::ReadViolation(DATA)));
+	assert_eq!(machine.last_fault_address(), 1);
+}
+
+#[test]
+fn data_pages_can_not_be_executed() {
+	// 1: call DATA, where a return is waiting
+	let mut machine = load(&[36, DATA, 22]);
+	machine.write_memory(DATA, 37);
+	machine.set_permissions(DATA, DATA, READ | WRITE);
+	assert_eq!(machine.run_budget(None), (2, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::ExecuteViolation(DATA)));
+	assert_eq!(machine.last_fault_address(), DATA);
+
+	let mut machine = load(&[36, DATA, 22]);
+	machine.write_memory(DATA, 37);
+	assert_eq!(machine.run_budget(None), (3, RunReason::Halted));
+}
+
+#[test]
+fn opcode_42_sets_permissions() {
+	// 1: make the data page read only, 4: 7 -> bus, 6: bus -> memory[DATA]
+	let mut machine = load(&[42, DATA + 5, READ, 24, 7, 2, DATA, 22]);
+	assert_eq!(machine.run_budget(None), (3, RunReason::Faulted));
+	assert_eq!(machine.page_permissions(DATA), READ);
+	assert_eq!(machine.fault(), Some(Fault::WriteViolation(DATA)));
+	assert_eq!(machine.last_fault_address(), 6);
+}
+
+#[test]
+fn stepping_back_restores_permissions() {
+	let mut machine = load(&[42, 0, READ | WRITE, 22]);
+	machine.set_history_limit(1024);
+	machine.run_budget(Some(1));
+	assert_eq!(machine.page_permissions(0), READ | WRITE);
+	assert!(machine.step_back());
+	assert_eq!(machine.page_permissions(0), ALL_PERMISSIONS);
+	assert_eq!(machine.instruction_pointer(), 1);
+}
+
+#[test]
+fn snapshots_keep_permissions() {
+	let mut machine = load(&counter());
+	machine.set_permissions(0, PAGE_SIZE - 1, READ | EXECUTE);
+	machine.set_permissions(2 * PAGE_SIZE, 2 * PAGE_SIZE, 0);
+	let snapshot = machine.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert_eq!(restored.page_permissions(0), READ | EXECUTE);
+	assert_eq!(restored.page_permissions(DATA), ALL_PERMISSIONS);
+	assert_eq!(restored.page_permissions(2 * PAGE_SIZE), 0);
+	assert_eq!(restored.save_snapshot(), snapshot);
+}
+
+#[test]
+fn the_whole_address_space_is_one_range() {
+	let mut machine = load(&[]);
+	let empty = machine.save_snapshot().len();
+	machine.set_permissions(0, u32::MAX, READ);
+	machine.set_permissions(DATA, DATA, READ | WRITE);
+	assert_eq!(machine.page_permissions(0), READ);
+	assert_eq!(machine.page_permissions(DATA), READ | WRITE);
+	assert_eq!(machine.page_permissions(u32::MAX / 2), READ);
+	assert_eq!(machine.page_permissions(u32::MAX), READ);
+	assert!(machine.save_snapshot().len() < empty + 64);
+
+	machine.set_permissions(0, u32::MAX, ALL_PERMISSIONS);
+	assert_eq!(machine.page_permissions(DATA), ALL_PERMISSIONS);
+	assert_eq!(machine.save_snapshot().len(), empty);
+}
+
+#[test]
+fn dense_permissions_from_version_8_restore() {
+	let mut machine = Machine::new();
+	machine.restore_snapshot(include_bytes!("fixtures/snapshot_v8_permissions.bin")).unwrap();
+	assert_eq!(machine.page_permissions(0), READ | EXECUTE);
+	assert_eq!(machine.page_permissions(DATA), ALL_PERMISSIONS);
+	assert_eq!(machine.page_permissions(2 * PAGE_SIZE), 0);
+	assert_eq!(machine.page_permissions(3 * PAGE_SIZE), ALL_PERMISSIONS);
+	assert_eq!(machine.page_permissions(u32::MAX), ALL_PERMISSIONS);
+}
+
+#[test]
+fn compiled_blocks_check_permissions() {
+	let mut results = Vec::new();
+	for &jit in [false, true].iter() {
+		let mut machine = load(&counter());
+		machine.set_history_limit(0);
+		machine.set_jit(jit);
+		machine.run_budget(Some(1000));
+		if jit {
+			assert!(machine.jit_block_count() > 0);
+		}
+
+		// the counter page becomes read only, then the code page stops being executable
+		machine.set_permissions(DATA, DATA, READ);
+		let write = machine.run_budget(Some(1000));
+		let write_fault = (machine.fault(), machine.last_fault_address(), machine.read_memory(DATA));
+
+		let mut machine = load(&counter());
+		machine.set_history_limit(0);
+		machine.set_jit(jit);
+		machine.run_budget(Some(1000));
+		machine.set_permissions(0, 0, READ | WRITE);
+		let execute = machine.run_budget(Some(1000));
+		let execute_fault = (machine.fault(), machine.last_fault_address());
+
+		results.push((write, write_fault, execute, execute_fault));
+	}
+	assert_eq!(results[0], results[1]);
+	assert_eq!(results[0].1 .0, Some(Fault::WriteViolation(DATA)));
+	assert_eq!(results[0].3, (Some(Fault::ExecuteViolation(1)), 1));
+}
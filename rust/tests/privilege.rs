@@ -0,0 +1,103 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine, RunReason, TRAP_ADDRESS, TRAP_CAUSE, TRAP_INTERRUPT, TRAP_SYSTEM_CALL, TRAP_VECTORS};
+
+mod common;
+use common::load;
+
+const HANDLER: u32 = 100;
+const USER: u32 = 10;
+
+// 1: pushes USER and the mode, and returns from the trap into user mode at USER.
+// the handler at HANDLER saves the trap detail in memory[60] and returns
+fn kernel(mode: u32, user: &[u32]) -> Machine {
+	let mut machine = load(&[24, USER, 34, 24, mode, 34, 48]);
+	for (i, &word) in user.iter().enumerate() {
+		machine.write_memory(USER + i as u32, word);
+	}
+	for (i, &word) in [1, TRAP_ADDRESS, 2, 60, 48].iter().enumerate() {
+		machine.write_memory(HANDLER + i as u32, word);
+	}
+	return machine;
+}
+
+#[test]
+fn privileged_instructions_fault_in_user_mode() {
+	let programs: [&[u32]; 8] = [&[20], &[21, 0], &[39], &[40], &[42, 0, 7], &[45, 0], &[46], &[48]];
+	for code in programs.iter() {
+		let mut machine = load(code);
+		machine.set_supervisor(false);
+		assert_eq!(machine.run_budget(None), (1, RunReason::Faulted));
+		assert_eq!(machine.fault(), Some(Fault::PrivilegeViolation(code[0])));
+		assert_eq!(machine.last_fault_address(), 1);
+	}
+
+	// everything else runs as before
+	let mut machine = load(&[24, 7, 2, 60, 22]);
+	machine.set_supervisor(false);
+	assert_eq!(machine.run_budget(None), (3, RunReason::Halted));
+	assert_eq!(machine.read_memory(60), 7);
+}
+
+#[test]
+fn trap_enters_supervisor_mode_and_returns_past_it() {
+	// USER: trap with 5, halt
+	let mut machine = kernel(0, &[49, 5, 22]);
+	machine.write_memory(TRAP_VECTORS + TRAP_SYSTEM_CALL, HANDLER);
+	let stack_pointer = machine.stack_pointer();
+
+	assert_eq!(machine.run_budget(Some(6)), (6, RunReason::BudgetExhausted));
+	assert!(machine.is_supervisor());
+	assert_eq!(machine.instruction_pointer(), HANDLER);
+
+	assert_eq!(machine.run_budget(None), (4, RunReason::Halted));
+	assert_eq!(machine.read_memory(60), 5);
+	assert_eq!(machine.read_memory(TRAP_CAUSE), TRAP_SYSTEM_CALL);
+	assert_eq!(machine.instruction_pointer(), USER + 2);
+	assert_eq!(machine.stack_pointer(), stack_pointer);
+	assert!(!machine.is_supervisor());
+}
+
+#[test]
+fn traps_without_a_handler_fault() {
+	let mut machine = kernel(0, &[49, 5, 22]);
+	assert_eq!(machine.run_budget(None), (6, RunReason::Faulted));
+	assert_eq!(machine.fault(), Some(Fault::NoTrapHandler(TRAP_SYSTEM_CALL)));
+	assert_eq!(machine.last_fault_address(), USER);
+}
+
+#[test]
+fn interrupts_in_user_mode_trap() {
+	// user mode with interrupts enabled. USER: noop, goto USER
+	let mut machine = kernel(2, &[0, 24, USER, 13]);
+	machine.write_memory(TRAP_VECTORS + TRAP_INTERRUPT, HANDLER);
+	machine.raise_interrupt(3);
+
+	// 6: the trap, then the handler
+	assert_eq!(machine.run_budget(Some(9)), (9, RunReason::BudgetExhausted));
+	assert_eq!(machine.instruction_pointer(), USER);
+	assert_eq!(machine.read_memory(60), 3);
+	assert_eq!(machine.read_memory(TRAP_CAUSE), TRAP_INTERRUPT);
+	assert_eq!(machine.pending_interrupts(), 0);
+	assert!(!machine.is_supervisor());
+	assert!(machine.interrupts_enabled());
+}
+
+#[test]
+fn stepping_back_restores_the_mode() {
+	let mut machine = kernel(0, &[49, 5, 22]);
+	machine.set_history_limit(1024);
+	machine.write_memory(TRAP_VECTORS + TRAP_SYSTEM_CALL, HANDLER);
+	machine.run();
+	assert!(!machine.is_supervisor());
+
+	// the halt, then the return from the trap
+	machine.step_back();
+	assert!(!machine.is_supervisor());
+	machine.step_back();
+	assert!(machine.is_supervisor());
+
+	while machine.step_back() {}
+	assert!(machine.is_supervisor());
+	assert_eq!(machine.read_memory(TRAP_CAUSE), 0);
+}
@@ -0,0 +1,83 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// a scratch file for this test run
+fn scratch(name: &str) -> PathBuf {
+	return env::temp_dir().join(format!("rust-asm-runner-{}-{}", std::process::id(), name));
+}
+
+// the runner with a .dsl program: whitespace separated words, '#' comments
+fn runner(name: &str, dsl: &str) -> Command {
+	let path = scratch(name);
+	fs::write(&path, dsl).unwrap();
+	let mut command = Command::new(env!("CARGO_BIN_EXE_runner"));
+	command.arg("--text").arg(path);
+	return command;
+}
+
+// so a runner that never stops fails the test instead of hanging it
+fn wait(mut child: Child) -> i32 {
+	let start = Instant::now();
+	loop {
+		if let Some(status) = child.try_wait().unwrap() {
+			return status.code().unwrap();
+		}
+		if start.elapsed() > Duration::from_secs(10) {
+			child.kill().unwrap();
+			panic!("the runner did not stop");
+		}
+		thread::sleep(Duration::from_millis(10));
+	}
+}
+
+#[test]
+fn stdin_eof_stops_a_program_waiting_for_input() {
+	let dsl = "
+		0 # execution starts at 1
+		# a terminal input buffer at 300, 8 words long
+		24 0 21 1
+		24 300 21 2
+		24 8 21 3
+		24 2 21 4
+		# 17: wait for input, forever
+		23 24 17 13
+	";
+	let child = runner("eof.dsl", dsl).stdin(Stdio::null()).spawn().unwrap();
+	assert_eq!(wait(child), 3);
+}
+
+// creates a buffer with code 1, then sets its head, length and type with 2, 3 and 4
+fn buffer(head: u32, length: u32, kind: u32) -> String {
+	return format!("24 0 21 1  24 {} 21 2  24 {} 21 3  24 {} 21 4\n", head, length, kind);
+}
+
+#[test]
+fn programs_read_the_input_file_and_write_the_output_file() {
+	// the input is loaded into 200 once its buffer is set up, then its first byte is replaced.
+	// the output buffer covers the same words
+	let dsl = format!("0\n{}24 74 2 200\n{}22\n", buffer(200, 16, 7), buffer(200, 16, 6));
+	let input = scratch("hello.in");
+	let output = scratch("hello.out");
+	fs::write(&input, "hello").unwrap();
+
+	let child = runner("hello.dsl", &dsl).arg("--input").arg(&input).arg("--output").arg(&output).spawn().unwrap();
+	assert_eq!(wait(child), 0);
+	assert_eq!(fs::read_to_string(&output).unwrap(), "Jello");
+}
+
+#[test]
+fn buffers_wrap_around_the_address_space() {
+	// the input buffer starts at the last address, so its second byte lands in 0
+	let dsl = format!("0\n{}{}22\n", buffer(u32::MAX, 2, 7), buffer(0, 1, 6));
+	let input = scratch("wrap.in");
+	let output = scratch("wrap.out");
+	fs::write(&input, "ab").unwrap();
+
+	let child = runner("wrap.dsl", &dsl).arg("--input").arg(&input).arg("--output").arg(&output).spawn().unwrap();
+	assert_eq!(wait(child), 0);
+	assert_eq!(fs::read_to_string(&output).unwrap(), "b");
+}
@@ -0,0 +1,68 @@
+extern crate rust_asm;
+
+use rust_asm::{Fault, Machine};
+use rust_asm::permissions::{PAGE_SIZE, READ, WRITE};
+
+fn image(code: &[(usize, &[u32])]) -> Vec<u32> {
+	let mut image = vec![0; 64];
+	for (address, words) in code {
+		image[*address..*address + words.len()].copy_from_slice(words);
+	}
+	return image;
+}
+
+// overwrites the parameter of an instruction that already ran, then runs it again
+fn self_modifying() -> Vec<u32> {
+	return image(&[
+		// P -> bus, bus -> memory[50], goto memory[60]
+		(1, &[24, 0, 2, 50, 1, 60, 13]),
+		// P = 42, memory[60] = 20, goto 0
+		(9, &[24, 42, 2, 2, 24, 20, 2, 60, 24, 0, 13]),
+		(20, &[100]),
+		(60, &[9]),
+	]);
+}
+
+// with and without the decode cache and the bookkeeping for history
+#[test]
+fn writes_to_code_are_seen_by_the_next_fetch() {
+	for &(cache, limit) in [(true, 1024), (true, 0), (false, 1024), (false, 0)].iter() {
+		let mut machine = Machine::new();
+		machine.set_decode_cache(cache);
+		machine.set_history_limit(limit);
+		machine.initialize(&self_modifying());
+		machine.run();
+		assert!(machine.is_halted());
+		assert_eq!(machine.read_memory(50), 42);
+	}
+}
+
+#[test]
+fn permissions_taken_away_apply_to_code_that_already_ran() {
+	// 1: goto PAGE_SIZE, which makes page 0 read and write only and goes back to 1
+	let mut code = vec![0; PAGE_SIZE as usize + 6];
+	code[1..4].copy_from_slice(&[24, PAGE_SIZE, 13]);
+	code[PAGE_SIZE as usize..].copy_from_slice(&[42, 0, READ | WRITE, 24, 1, 13]);
+	let mut machine = Machine::new();
+	machine.initialize(&code);
+	machine.run();
+	assert_eq!(machine.fault(), Some(Fault::ExecuteViolation(1)));
+	assert_eq!(machine.instruction_pointer(), 1);
+}
+
+#[test]
+fn writes_from_outside_are_seen_by_the_next_run() {
+	// 1: P -> bus, 3: pause, 4: goto the bus, which is 1
+	let mut machine = Machine::new();
+	machine.initialize(&image(&[(1, &[24, 1, 23, 13])]));
+	machine.run();
+	assert_eq!(machine.bus(), 1);
+
+	// through the debugger, then the way syscall hosts write
+	machine.write_memory(2, 2);
+	machine.run();
+	assert_eq!(machine.bus(), 2);
+	machine.memory().write(4, 100);
+	machine.run();
+	assert!(machine.is_halted());
+}
@@ -0,0 +1,132 @@
+extern crate rust_asm;
+
+use rust_asm::{Machine, SnapshotError};
+use rust_asm::permissions::PAGE_SIZE;
+
+mod common;
+use common::load;
+
+// 1: 19 float mode, 2: 3.5 -> bus, 4: push to ALU, 5: pause,
+// 6: 2.0 -> bus, 8: add, 9: hi -> bus, 10: save to 20, 12: halt
+fn program() -> Vec<u32> {
+	return vec![19, 24, 3.5f32.to_bits(), 25, 23, 24, 2.0f32.to_bits(), 9, 16, 2, 20, 100];
+}
+
+#[test]
+fn restored_machine_continues_where_the_snapshot_was_taken() {
+	let mut original = load(&program());
+	original.set_breakpoint(9);
+	original.run();
+	assert!(original.is_paused());
+
+	let snapshot = original.save_snapshot();
+
+	let mut restored = Machine::new();
+	restored.restore_snapshot(&snapshot).unwrap();
+	assert_eq!(restored.instruction_pointer(), original.instruction_pointer());
+	assert!(restored.is_breakpoint(9));
+
+	original.run();
+	restored.run();
+
+	assert!(restored.is_halted());
+	assert_eq!(restored.read_memory(20), 5.5f32.to_bits());
+	assert_eq!(restored.read_memory(20), original.read_memory(20));
+	assert_eq!(restored.save_snapshot(), original.save_snapshot());
+}
+
+// taken with the code of each older version: program() paused at 5, with a breakpoint on 9
+const OLD_VERSIONS: [&[u8]; 9] = [
+	include_bytes!("fixtures/snapshot_v1.bin"),
+	include_bytes!("fixtures/snapshot_v2.bin"),
+	include_bytes!("fixtures/snapshot_v3.bin"),
+	include_bytes!("fixtures/snapshot_v4.bin"),
+	include_bytes!("fixtures/snapshot_v5.bin"),
+	include_bytes!("fixtures/snapshot_v6.bin"),
+	include_bytes!("fixtures/snapshot_v7.bin"),
+	include_bytes!("fixtures/snapshot_v8.bin"),
+	include_bytes!("fixtures/snapshot_v9.bin"),
+];
+
+#[test]
+fn snapshots_from_older_versions_restore() {
+	let mut original = load(&program());
+	original.set_breakpoint(9);
+	original.run();
+
+	for (i, snapshot) in OLD_VERSIONS.iter().enumerate() {
+		assert_eq!(snapshot[4] as usize, i + 1);
+		let mut restored = Machine::new();
+		restored.restore_snapshot(snapshot).unwrap();
+		assert!(restored.is_paused(), "version {}", i + 1);
+		assert_eq!(restored.instruction_pointer(), original.instruction_pointer(), "version {}", i + 1);
+		assert!(restored.is_breakpoint(9));
+		assert_eq!(restored.save_snapshot(), original.save_snapshot(), "version {}", i + 1);
+
+		restored.run();
+		assert!(restored.is_halted(), "version {}", i + 1);
+		assert_eq!(restored.read_memory(20), 5.5f32.to_bits(), "version {}", i + 1);
+	}
+}
+
+#[test]
+fn bad_snapshots_are_rejected_without_changing_the_machine() {
+	let mut machine = load(&program());
+	let snapshot = machine.save_snapshot();
+
+	assert_eq!(machine.restore_snapshot(b"nope"), Err(SnapshotError::BadMagic));
+	assert_eq!(machine.restore_snapshot(&snapshot[..snapshot.len() - 1]), Err(SnapshotError::Truncated));
+
+	let mut future = snapshot.clone();
+	future[4] = 11;
+	assert_eq!(machine.restore_snapshot(&future), Err(SnapshotError::UnsupportedVersion(11)));
+
+	assert_eq!(machine.save_snapshot(), snapshot);
+}
+
+fn word(bytes: &mut [u8], at: usize, value: u32) {
+	bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[test]
+fn snapshots_that_do_not_add_up_are_rejected() {
+	let mut machine = load(&program());
+	let snapshot = machine.save_snapshot();
+	// the stack pointer, base and limit follow the fault
+	let mut stack = snapshot.clone();
+	word(&mut stack, 34, machine.stack_pointer() + 1);
+	assert_eq!(machine.restore_snapshot(&stack), Err(SnapshotError::Invalid("stack")));
+	let mut stack = snapshot.clone();
+	word(&mut stack, 26, 0);
+	assert_eq!(machine.restore_snapshot(&stack), Err(SnapshotError::Invalid("stack")));
+	assert_eq!(machine.save_snapshot(), snapshot);
+
+	// 1: allocate 2000 words, which get the 2 pages past the end of memory
+	let mut machine = load(&[24, 2000, 43, 22]);
+	machine.run();
+	let snapshot = machine.save_snapshot();
+	let mut allocation = (32 * PAGE_SIZE).to_le_bytes().to_vec();
+	allocation.extend(&(2 * PAGE_SIZE).to_le_bytes());
+	let at = (0..snapshot.len() - 8).rev().find(|&at| snapshot[at..at + 8] == allocation[..]).unwrap();
+	let mut heap = snapshot.clone();
+	word(&mut heap, at + 4, u32::MAX);
+	assert_eq!(machine.restore_snapshot(&heap), Err(SnapshotError::Invalid("heap allocation")));
+	let mut heap = snapshot.clone();
+	word(&mut heap, at, u32::MAX - PAGE_SIZE);
+	assert_eq!(machine.restore_snapshot(&heap), Err(SnapshotError::Invalid("heap allocation")));
+	assert_eq!(machine.save_snapshot(), snapshot);
+}
+
+#[test]
+fn restoring_forgets_the_last_watchpoint_hit() {
+	// 1: 5 -> bus, 3: bus -> memory[30], 5: halt
+	let mut machine = load(&[24, 5, 2, 30, 100]);
+	let snapshot = machine.save_snapshot();
+	machine.add_watchpoint(30, 30, 2);
+	machine.run();
+	assert!(machine.watch_hit().is_some());
+
+	machine.restore_snapshot(&snapshot).unwrap();
+	assert!(machine.watch_hit().is_none());
+	assert!(machine.last_watch_hit().is_empty());
+}
@@ -0,0 +1,89 @@
+extern crate rust_asm;
+
+use rust_asm::{Machine, RunReason};
+
+// 1: call 10, 3: 7 -> bus, 5: halt
+// 10: 1 -> bus, 12: call 20, 14: return
+// 20: 2 -> bus, 22: return
+fn load() -> Machine {
+	let mut image = vec![0; 24];
+	image[1..6].copy_from_slice(&[36, 10, 24, 7, 100]);
+	image[10..15].copy_from_slice(&[24, 1, 36, 20, 37]);
+	image[20..23].copy_from_slice(&[24, 2, 37]);
+	let mut machine = Machine::new();
+	machine.initialize(&image);
+	return machine;
+}
+
+#[test]
+fn step_over_runs_whole_calls() {
+	let mut machine = load();
+	machine.step_over();
+	assert_eq!(machine.instruction_pointer(), 3);
+	assert_eq!(machine.bus(), 2);
+	assert!(machine.is_paused());
+	assert_eq!(machine.last_run_reason(), RunReason::Reached.code());
+
+	machine.step_over();
+	assert_eq!(machine.instruction_pointer(), 5);
+	assert_eq!(machine.bus(), 7);
+	machine.step_over();
+	assert!(machine.is_halted());
+}
+
+#[test]
+fn step_over_stops_at_breakpoints_inside_the_call() {
+	let mut machine = load();
+	machine.enable_breakpoints();
+	machine.set_breakpoint(20);
+	machine.step_over();
+	assert_eq!(machine.instruction_pointer(), 20);
+	assert_eq!(machine.last_run_reason(), RunReason::Breakpoint.code());
+}
+
+#[test]
+fn step_into_and_step_out() {
+	let mut machine = load();
+	machine.step_into();
+	assert_eq!(machine.instruction_pointer(), 10);
+	machine.step_into();
+	machine.step_into();
+	assert_eq!(machine.instruction_pointer(), 20);
+
+	assert_eq!(machine.step_out(), 2);
+	assert_eq!(machine.instruction_pointer(), 14);
+	assert_eq!(machine.step_out(), 1);
+	assert_eq!(machine.instruction_pointer(), 3);
+
+	// nothing left to return from, so this is a single step
+	assert_eq!(machine.step_out(), 1);
+	assert_eq!(machine.instruction_pointer(), 5);
+	assert_eq!(machine.last_run_reason(), RunReason::Reached.code());
+	assert_eq!(machine.step_out(), 1);
+	assert_eq!(machine.last_run_reason(), RunReason::Halted.code());
+	assert_eq!(machine.step_out(), 0);
+	assert_eq!(machine.last_run_reason(), RunReason::Halted.code());
+}
+
+#[test]
+fn step_n_counts_instructions() {
+	let mut machine = load();
+	assert_eq!(machine.step_n(4), 4);
+	assert_eq!(machine.instruction_pointer(), 22);
+	assert!(machine.is_paused());
+	assert_eq!(machine.step_n(0), 0);
+	assert_eq!(machine.step_n(100), 4);
+	assert!(machine.is_halted());
+}
+
+#[test]
+fn run_to_stops_at_the_address_once() {
+	let mut machine = load();
+	assert_eq!(machine.run_to(14), 5);
+	assert_eq!(machine.instruction_pointer(), 14);
+	assert_eq!(machine.last_run_reason(), RunReason::Reached.code());
+
+	// the temporary breakpoint is gone, and an address that is never reached runs to the end
+	assert_eq!(machine.run_to(14), 3);
+	assert!(machine.is_halted());
+}
@@ -0,0 +1,98 @@
+extern crate rust_asm;
+
+use rust_asm::syscall::{self, SyscallRecord};
+use rust_asm::{Machine, NativeSyscallHost, RecordingSyscallHost};
+
+mod common;
+
+fn run_with(host: Box<dyn rust_asm::SyscallHost>, code: &[u32]) -> Machine {
+	let mut machine = common::load_into(Machine::with_syscall_host(host), code);
+	machine.run();
+	return machine;
+}
+
+#[test]
+fn alert_is_recorded_with_its_text() {
+	let host = RecordingSyscallHost::new();
+	// 24 7: address of the string -> bus, 21 30: alert, 100: halt
+	let machine = run_with(Box::new(host.clone()), &[24, 7, 21, 30, 100, 0, 'H' as u32, 'i' as u32, 0]);
+
+	assert!(machine.is_halted());
+	assert_eq!(host.calls(), vec![SyscallRecord {
+		code: syscall::ALERT,
+		param: 7,
+		text: Some("Hi".to_string()),
+	}]);
+}
+
+#[test]
+fn buffer_setup_is_recorded_in_order() {
+	let host = RecordingSyscallHost::new();
+	run_with(Box::new(host.clone()), &[
+		24, 0, 21, syscall::CREATE_BUFFER,
+		24, 2049, 21, syscall::SET_BUFFER_HEAD,
+		24, 1024, 21, syscall::SET_BUFFER_LENGTH,
+		24, syscall::BUFFER_OUTPUT_SCREEN, 21, syscall::SET_BUFFER_TYPE,
+		100,
+	]);
+
+	let calls: Vec<(u32, u32)> = host.calls().iter().map(|c| (c.code, c.param)).collect();
+	assert_eq!(calls, vec![
+		(syscall::CREATE_BUFFER, 0),
+		(syscall::SET_BUFFER_HEAD, 2049),
+		(syscall::SET_BUFFER_LENGTH, 1024),
+		(syscall::SET_BUFFER_TYPE, syscall::BUFFER_OUTPUT_SCREEN),
+	]);
+}
+
+#[test]
+fn syscall_result_is_put_on_the_bus() {
+	let host = RecordingSyscallHost::new();
+	host.respond(syscall::SLEEP, 42);
+	let machine = run_with(Box::new(host.clone()), &[24, 16, 21, syscall::SLEEP, 100]);
+
+	assert_eq!(machine.bus(), 42);
+}
+
+#[test]
+fn pausing_lets_the_host_refresh() {
+	let host = RecordingSyscallHost::new();
+	// 23: pause
+	let mut machine = run_with(Box::new(host.clone()), &[23, 100]);
+	assert!(machine.is_paused());
+
+	machine.refresh_syscall_host();
+	machine.run();
+
+	assert!(machine.is_halted());
+	assert_eq!(host.refreshes(), 1);
+}
+
+#[test]
+fn native_host_fills_file_input_buffers() {
+	let host = NativeSyscallHost::new().with_input_file(b"abc".to_vec());
+	let machine = run_with(Box::new(host), &[
+		21, syscall::CREATE_BUFFER,
+		24, 100, 21, syscall::SET_BUFFER_HEAD,
+		24, 2, 21, syscall::SET_BUFFER_LENGTH,
+		24, syscall::BUFFER_INPUT_FILE, 21, syscall::SET_BUFFER_TYPE,
+		100,
+	]);
+
+	assert_eq!(machine.read_memory(100), 'a' as u32);
+	assert_eq!(machine.read_memory(101), 'b' as u32);
+	assert_eq!(machine.read_memory(102), 0);
+}
+
+#[test]
+fn native_host_rejects_setting_a_buffer_property_twice() {
+	let host = NativeSyscallHost::new();
+	let machine = run_with(Box::new(host), &[
+		21, syscall::CREATE_BUFFER,
+		24, 100, 21, syscall::SET_BUFFER_HEAD,
+		24, 200, 21, syscall::SET_BUFFER_HEAD,
+		100,
+	]);
+
+	assert_eq!(machine.bus(), syscall::RESULT_ERROR as u32);
+}
@@ -0,0 +1,74 @@
+extern crate rust_asm;
+
+use rust_asm::TraceRecord;
+
+mod common;
+use common::load;
+
+#[test]
+fn records_each_instruction() {
+	// 1: 5 -> bus, 3: bus -> memory[30], 5: bus -> memory[30], 7: halt
+	let mut machine = load(&[24, 5, 2, 30, 2, 30, 100]);
+	machine.start_trace();
+	machine.run();
+
+	let trace: Vec<&TraceRecord> = machine.trace().collect();
+	assert_eq!(trace.len(), 4);
+	assert_eq!(*trace[1], TraceRecord {
+		address: 3,
+		opcode: 2,
+		params: vec![30],
+		bus_before: 5,
+		bus_after: 5,
+		hi: 0,
+		lo: 0,
+		writes: vec![(30, 0, 5)],
+	});
+	assert_eq!(trace[2].writes, vec![(30, 5, 5)]);
+	assert_eq!(trace[3].opcode, 100);
+	assert!(trace[3].params.is_empty());
+}
+
+#[test]
+fn params_are_recorded_before_they_are_overwritten() {
+	// 1: 9 -> bus, 3: bus -> memory[4], 5: halt
+	let mut machine = load(&[24, 9, 2, 4, 100]);
+	machine.start_trace();
+	machine.run();
+	let trace: Vec<&TraceRecord> = machine.trace().collect();
+	assert_eq!(trace[1].params, vec![4]);
+	assert_eq!(trace[1].writes, vec![(4, 4, 9)]);
+}
+
+#[test]
+fn filters_and_limit() {
+	let mut machine = load(&[24, 5, 2, 30, 2, 30, 100]);
+	machine.add_trace_filter(3, 5);
+	machine.start_trace();
+	machine.run();
+	let addresses: Vec<u32> = machine.trace().map(|r| r.address).collect();
+	assert_eq!(addresses, vec![3, 5]);
+
+	machine.set_trace_limit(1);
+	assert_eq!(machine.trace_length(), 1);
+	assert_eq!(machine.trace().next().unwrap().address, 5);
+}
+
+#[test]
+fn exports() {
+	let mut machine = load(&[24, 5, 2, 30, 100]);
+	machine.start_trace();
+	machine.run();
+
+	let json = machine.export_trace_json();
+	let lines: Vec<&str> = json.lines().collect();
+	assert_eq!(lines.len(), 3);
+	assert_eq!(lines[1], r#"{"address":3,"opcode":2,"params":[30],"bus_before":5,"bus_after":5,"hi":0,"lo":0,"writes":[[30,0,5]]}"#);
+
+	let binary = machine.export_trace_binary();
+	assert_eq!(&binary[..4], b"RTRC");
+	assert_eq!(&binary[8..12], &3u32.to_le_bytes());
+	// magic, version, count, then 3 records of 7 words and a param count byte,
+	// plus 2 params and one write
+	assert_eq!(binary.len(), 12 + 3 * (7 * 4 + 1) + 2 * 4 + 3 * 4);
+}
@@ -0,0 +1,55 @@
+extern crate rust_asm;
+
+use rust_asm::{RunReason, WatchKind};
+
+mod common;
+use common::load;
+
+#[test]
+fn write_watchpoint_pauses_after_the_write() {
+	// 1: 5 -> bus, 3: bus -> memory[30], 5: 6 -> bus, 7: bus -> memory[30], 9: halt
+	let mut machine = load(&[24, 5, 2, 30, 24, 6, 2, 30, 100]);
+	let id = machine.add_watchpoint(30, 30, 2);
+	assert_ne!(id, 0);
+
+	let (_, reason) = machine.run_budget(None);
+	assert_eq!(reason, RunReason::Watchpoint);
+	assert_eq!(machine.instruction_pointer(), 5);
+	let hit = machine.watch_hit().unwrap();
+	assert_eq!(hit.address, 30);
+	assert_eq!(hit.access, WatchKind::Write);
+	assert_eq!((hit.old, hit.new), (0, 5));
+	assert_eq!(hit.instruction, 3);
+
+	let (_, reason) = machine.run_budget(None);
+	assert_eq!(reason, RunReason::Watchpoint);
+	assert_eq!(machine.last_watch_hit(), vec![id, 30, 2, 5, 6, 7]);
+
+	assert!(machine.remove_watchpoint(id));
+	let (_, reason) = machine.run_budget(None);
+	assert_eq!(reason, RunReason::Halted);
+}
+
+#[test]
+fn read_watchpoint_covers_a_range() {
+	// 1: bus -> memory[31], 3: memory[32] -> bus, 5: halt
+	let mut machine = load(&[2, 31, 1, 32, 100]);
+	machine.add_watchpoint(32, 30, 1);
+
+	let (_, reason) = machine.run_budget(None);
+	assert_eq!(reason, RunReason::Watchpoint);
+	let hit = machine.watch_hit().unwrap();
+	assert_eq!(hit.address, 32);
+	assert_eq!(hit.access, WatchKind::Read);
+	assert_eq!(hit.instruction, 3);
+}
+
+#[test]
+fn debugger_access_does_not_trigger_watchpoints() {
+	let mut machine = load(&[100]);
+	machine.add_watchpoint(0, 100, 3);
+	machine.write_memory(40, 7);
+	assert_eq!(machine.read_memory(40), 7);
+	assert!(machine.watch_hit().is_none());
+	assert_eq!(machine.add_watchpoint(0, 1, 9), 0);
+}